@@ -0,0 +1,191 @@
+// Headless path from a theme file to a patched JAR, shared by the CLI tools
+
+use std::{
+    fs,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use krakatau2::zip::{self, ZipArchive};
+
+use crate::{
+    exchange::{
+        apply_aliases, fuzzy_name_matches, import_bundle, import_theme_json, BINARY_THEME_EXTENSION, BUNDLE_EXTENSION,
+        DEFAULT_FUZZY_THRESHOLD,
+    },
+    extract_general_goodies,
+    launch::{verify_classes, ClassVerdict, JAVA_COMMAND_ENV},
+    log::log_message,
+    output::{read_patch_marker, read_release_info, write_theme_json_next_to, OutputSettings},
+    types::CucumberBitwigTheme,
+    write_theme_to_jar, GeneralGoodies, WriteOptions,
+};
+
+// Theme JSON, a .bwtheme bundle or, with the binary-theme feature, a binary theme
+pub fn load_theme_file(path: &Path) -> anyhow::Result<CucumberBitwigTheme> {
+    if path.extension().is_some_and(|ext| ext == BINARY_THEME_EXTENSION) {
+        #[cfg(feature = "binary-theme")]
+        return crate::exchange::import_theme_bin(&fs::read(path)?);
+        #[cfg(not(feature = "binary-theme"))]
+        return Err(anyhow!("{} is a binary theme, cucumber was built without binary-theme feature", path.display()));
+    }
+    if path.extension().is_some_and(|ext| ext == BUNDLE_EXTENSION) {
+        let bundle = import_bundle(path)?;
+        if let Some(bitwig_version) = &bundle.metadata.bitwig_version {
+            log_message(&format!("Theme bundle was made for Bitwig {}", bitwig_version));
+        }
+        Ok(bundle.theme)
+    } else {
+        Ok(import_theme_json(&fs::read_to_string(path)?)?)
+    }
+}
+
+pub fn is_theme_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "json" || ext == BUNDLE_EXTENSION || ext == BINARY_THEME_EXTENSION)
+}
+
+pub fn open_jar(input_jar: &Path) -> anyhow::Result<ZipArchive<fs::File>> {
+    let file = fs::File::open(input_jar)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+
+    if let Some(marker) = read_patch_marker(&mut zip) {
+        log_message(&format!(
+            "Input JAR was already patched by cucumber {}, original colors may be lost",
+            marker.cucumber_version
+        ));
+    }
+    Ok(zip)
+}
+
+// Returns path of the written JAR. Without output_jar the name comes from settings.
+pub fn patch_jar(
+    input_jar: &Path,
+    theme_file: &Path,
+    output_jar: Option<&Path>,
+    settings: &OutputSettings,
+    options: &WriteOptions,
+) -> anyhow::Result<PathBuf> {
+    let mut zip = open_jar(input_jar)?;
+
+    let release = read_release_info(&mut zip).unwrap_or_default();
+    let output_jar = match output_jar {
+        Some(output_jar) => output_jar.to_path_buf(),
+        None => settings.resolve(input_jar, release.version.as_deref()),
+    };
+
+    let general_goodies = extract_general_goodies(&mut zip)?;
+    for note in release.compatibility_notes(&CucumberBitwigTheme::from_goodies(&general_goodies)) {
+        log_message(&note);
+    }
+    apply_theme_file(&mut zip, &general_goodies, theme_file, &output_jar, settings, options)?;
+    Ok(output_jar)
+}
+
+// Writes one theme using results of an earlier scan of the same JAR, so
+// several themes can be applied after scanning once. Writing updates the
+// goodies to match the output, so each theme gets its own copy.
+pub fn apply_theme_file<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    general_goodies: &GeneralGoodies,
+    theme_file: &Path,
+    output_jar: &Path,
+    settings: &OutputSettings,
+    options: &WriteOptions,
+) -> anyhow::Result<()> {
+    let mut theme = load_theme_file(theme_file)?;
+    let mut general_goodies = general_goodies.clone();
+
+    match &theme.author {
+        Some(author) => log_message(&format!("Theme \"{}\" by {}", theme.name, author)),
+        None => log_message(&format!("Theme \"{}\"", theme.name)),
+    }
+    if let Some(description) = &theme.description {
+        log_message(description);
+    }
+
+    let is_known_name = |name: &str| {
+        general_goodies
+            .named_colors
+            .iter()
+            .any(|color| color.color_name == name)
+    };
+
+    let alias_uses = apply_aliases(&mut theme, is_known_name);
+    for alias_use in alias_uses {
+        log_message(&format!(
+            "Color \"{}\" applied to its new name \"{}\"",
+            alias_use.old_name, alias_use.new_name
+        ));
+    }
+    for name in theme.named_colors.keys() {
+        if !is_known_name(name) {
+            log_message(&format!("Color \"{}\" is not present in this JAR, skipped", name));
+        }
+    }
+    let known_names = general_goodies.named_colors.iter().map(|color| color.color_name.as_str());
+    for fuzzy in fuzzy_name_matches(&theme, known_names, DEFAULT_FUZZY_THRESHOLD) {
+        log_message(&format!(
+            "Color \"{}\" may be \"{}\" ({:.0}% alike), add it to aliases of \"{}\" to apply",
+            fuzzy.theme_name,
+            fuzzy.known_name,
+            fuzzy.confidence * 100.0,
+            fuzzy.known_name
+        ));
+    }
+
+    let manifest = write_theme_to_jar(zip, &mut general_goodies, &theme, output_jar, options)?;
+
+    log_message(&format!("Written: {}", output_jar.display()));
+
+    let manifest_path = manifest.write_next_to(output_jar)?;
+    log_message(&format!("Manifest: {}", manifest_path.display()));
+
+    if settings.verify_classes {
+        verify_written_classes(output_jar, manifest.classes.keys().map(String::as_str))?;
+    }
+
+    // JAR is already written, so failing here isn't fatal
+    if settings.save_theme_json {
+        match write_theme_json_next_to(&theme, output_jar) {
+            Ok(path) => log_message(&format!("Theme JSON: {}", path.display())),
+            Err(err) => log_message(&format!("Warning: failed to write theme JSON: {}", err)),
+        }
+    }
+
+    Ok(())
+}
+
+// Fails only if the JVM rejected a class, a missing JVM is just reported
+fn verify_written_classes<'a>(
+    output_jar: &Path,
+    class_files: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<()> {
+    let Some(verdicts) = verify_classes(output_jar, class_files)? else {
+        log_message(&format!("No JVM found, set {} to verify patched classes", JAVA_COMMAND_ENV));
+        return Ok(());
+    };
+    let mut failed = 0;
+    for (class_file, verdict) in &verdicts {
+        match verdict {
+            ClassVerdict::Passed => log_message(&format!("Verified: {}", class_file)),
+            ClassVerdict::Failed(error) => {
+                failed += 1;
+                log_message(&format!("Verification failed: {}: {}", class_file, error));
+            }
+            ClassVerdict::Inconclusive(reason) => {
+                log_message(&format!("Couldn't verify {}: {}", class_file, reason))
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} patched classes failed verification, don't install {}",
+            failed,
+            verdicts.len(),
+            output_jar.display()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use std::{env, fs, path::PathBuf};
+
+use cucumber::{
+    exchange::{export_accessibility_report, import_theme_json},
+    log::log_message,
+};
+
+// accessibility <theme.json> <output.html>
+fn main() -> anyhow::Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let theme_json = PathBuf::from(&args[1]);
+    let output = PathBuf::from(&args[2]);
+
+    let theme = import_theme_json(&fs::read_to_string(&theme_json)?)?;
+    export_accessibility_report(&theme, &output)?;
+    log_message(&format!("Written: {}", output.display()));
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use cucumber::{
+    apply::{apply_theme_file, is_theme_file, open_jar},
+    extract_general_goodies,
+    log::{log_format_from_flags, log_message, set_log_format},
+    output::OutputSettings,
+    LineNumbers, WriteOptions,
+};
+use krakatau2::zip;
+
+// batch <input.jar> <themes dir> <output dir>
+// One patched JAR per theme file, named after the theme file. The JAR is
+// scanned once, themes are written in parallel.
+fn main() -> anyhow::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    set_log_format(log_format_from_flags(&flags));
+
+    let mut options = WriteOptions::default();
+    if flags.iter().any(|flag| flag == "--keep-line-numbers") {
+        options.line_numbers = LineNumbers::Preserve;
+    }
+    options.store_patched_classes = flags.iter().any(|flag| flag == "--store-uncompressed");
+    let mut settings = OutputSettings::from_env();
+    if flags.iter().any(|flag| flag == "--no-theme-json") {
+        settings.save_theme_json = false;
+    }
+    settings.verify_classes = flags.iter().any(|flag| flag == "--verify");
+
+    let input_jar = PathBuf::from(&args[1]);
+    let themes_dir = PathBuf::from(&args[2]);
+    let output_dir = PathBuf::from(&args[3]);
+    fs::create_dir_all(&output_dir)?;
+
+    let mut theme_files = fs::read_dir(&themes_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_theme_file(path))
+        .collect::<Vec<_>>();
+    theme_files.sort();
+
+    let started = Instant::now();
+    let general_goodies = extract_general_goodies(&mut open_jar(&input_jar)?)?;
+    log_message(&format!("Scanned {} in {:?}", input_jar.display(), started.elapsed()));
+
+    let started = Instant::now();
+    let next_theme = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    let workers = thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(theme_files.len());
+    // Own handle per worker, reading entries needs a mutable archive
+    let zips = (0..workers)
+        .map(|_| -> anyhow::Result<_> { Ok(zip::ZipArchive::new(fs::File::open(&input_jar)?)?) })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    thread::scope(|scope| {
+        for mut zip in zips {
+            let (next_theme, failed) = (&next_theme, &failed);
+            let (theme_files, general_goodies) = (&theme_files, &general_goodies);
+            let (output_dir, settings, options) = (&output_dir, &settings, &options);
+            scope.spawn(move || {
+                while let Some(theme_file) = theme_files.get(next_theme.fetch_add(1, Ordering::Relaxed)) {
+                    let stem = theme_file.file_stem().unwrap_or_default().to_string_lossy();
+                    let output_jar = output_dir.join(format!("{}.jar", stem));
+                    log_message(&format!("Applying {}", theme_file.display()));
+                    let result = apply_theme_file(&mut zip, general_goodies, theme_file, &output_jar, settings, options);
+                    if let Err(err) = result {
+                        log_message(&format!("Failed {}: {:?}", theme_file.display(), err));
+                        failed.lock().unwrap().push(theme_file.clone());
+                    }
+                }
+            });
+        }
+    });
+    let failed = failed.into_inner().unwrap();
+
+    log_message(&format!(
+        "Patched {} of {} themes in {:?}",
+        theme_files.len() - failed.len(),
+        theme_files.len(),
+        started.elapsed()
+    ));
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("{} themes failed", failed.len()));
+    }
+    Ok(())
+}
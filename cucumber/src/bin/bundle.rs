@@ -0,0 +1,20 @@
+use std::{env, fs, path::PathBuf};
+
+use cucumber::{
+    exchange::{export_bundle, import_theme_json},
+    log::log_message,
+};
+
+// bundle <theme.json> <output.bwtheme> [bitwig version]
+fn main() -> anyhow::Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let theme_json = PathBuf::from(&args[1]);
+    let output = PathBuf::from(&args[2]);
+    let bitwig_version = args.get(3);
+
+    let theme = import_theme_json(&fs::read_to_string(&theme_json)?)?;
+    export_bundle(&theme, bitwig_version.map(String::as_str), &output)?;
+    log_message(&format!("Written: {}", output.display()));
+
+    Ok(())
+}
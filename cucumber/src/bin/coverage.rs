@@ -0,0 +1,31 @@
+use std::{env, fs};
+
+use cucumber::{
+    extract_general_goodies,
+    log::{log_format, log_format_from_flags, set_log_format, LogFormat},
+    variant_coverage,
+};
+use krakatau2::zip;
+
+fn main() -> anyhow::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    set_log_format(log_format_from_flags(&flags));
+    let input_jar = &args[1];
+
+    let file = fs::File::open(input_jar)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+
+    let general_goodies = extract_general_goodies(&mut zip)?;
+
+    let coverage = variant_coverage(&general_goodies);
+    if log_format() == LogFormat::Json {
+        println!("{}", serde_json::json!({ "event": "coverage", "variants": coverage }));
+        return Ok(());
+    }
+    for (variant, count) in coverage {
+        println!("{:>16}: {}", variant, count);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::{env, fs};
+
+use cucumber::{
+    exchange::{diff_against_reference, import_theme_json},
+    log::{log_event, log_format_from_flags, log_message, set_log_format},
+    types::{AbsoluteColor, CucumberBitwigTheme},
+};
+use krakatau2::zip;
+
+// Compares colors of the JAR with reference theme JSON (e.g. factory one)
+fn main() -> anyhow::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    set_log_format(log_format_from_flags(&flags));
+    let input_jar = &args[1];
+    let reference_json = &args[2];
+
+    let file = fs::File::open(input_jar)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+    let theme = CucumberBitwigTheme::from_jar(&mut zip)?;
+    let reference = import_theme_json(&fs::read_to_string(reference_json)?)?;
+
+    let diffs = diff_against_reference(&theme, &reference);
+    for diff in &diffs {
+        let ours = diff.ours.as_ref().map(AbsoluteColor::to_hex);
+        let theirs = diff.reference.as_ref().map(AbsoluteColor::to_hex);
+        let kind = if diff.raw { "raw" } else { "named" };
+        log_event(
+            "diff",
+            serde_json::json!({
+                "name": diff.name,
+                "kind": kind,
+                "jar": ours,
+                "reference": theirs,
+            }),
+            &format!(
+                "{:>5} {}: {} (reference {})",
+                kind,
+                diff.name,
+                ours.as_deref().unwrap_or("missing"),
+                theirs.as_deref().unwrap_or("missing"),
+            ),
+        );
+    }
+
+    if diffs.is_empty() {
+        log_message("No differences from the reference theme");
+    } else {
+        log_message(&format!("{} colors differ, this JAR may be patched already", diffs.len()));
+    }
+
+    Ok(())
+}
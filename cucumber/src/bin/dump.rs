@@ -1,26 +1,37 @@
 use std::{env, fs::{self, File}, io::BufWriter};
 
-use cucumber::{extract_general_goodies, types::{AbsoluteColor, ColorConst, CucumberBitwigTheme, NamedColor, UiTarget}};
+use cucumber::{extract_general_goodies_with_options, log::{log_format_from_flags, log_message, set_log_format}, ExtractOptions, types::{AbsoluteColor, ColorConst, CucumberBitwigTheme, NamedColor, UiTarget}};
 use krakatau2::zip;
 
 fn main() -> anyhow::Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    set_log_format(log_format_from_flags(&flags));
     let input_jar = &args[1];
     let output_json = &args[2];
 
     let file = fs::File::open(input_jar)?;
     let mut zip = zip::ZipArchive::new(file)?;
 
-    let general_goodies = extract_general_goodies(&mut zip)?;
+    let options = ExtractOptions {
+        strict: flags.iter().any(|flag| flag == "--strict"),
+        low_memory: flags.iter().any(|flag| flag == "--low-memory"),
+        ..Default::default()
+    };
+    let general_goodies = extract_general_goodies_with_options(&mut zip, &options, |_| {})?;
+    if let Some(peak) = peak_rss_kb() {
+        log_message(&format!("Peak memory after extraction: {} kB", peak));
+    }
+    for failure in &general_goodies.parse_failures {
+        log_message(&format!("Failed to parse {}: {}", failure.file_name, failure.error));
+    }
 
     let mut theme = CucumberBitwigTheme {
         name: "Extracted Theme".into(),
         ..Default::default()
     };
 
-    let known_colors = general_goodies.named_colors.iter().map(|color| {
-        (color.color_name.clone(), color.components.clone())
-    }).collect();
+    let known_colors = general_goodies.known_colors();
 
     for color in general_goodies.named_colors {
         let (r, g, b) = color.components.to_rgb(&known_colors);
@@ -33,16 +44,19 @@ fn main() -> anyhow::Result<()> {
         theme.named_colors.insert(color.color_name.clone(), named_color);
     }
 
-    let timeline_const_name = general_goodies.timeline_color_ref.const_name;
-    let timeline_const = general_goodies.raw_colors.constants.consts.iter().find(|cnst| {
-        cnst.const_name == timeline_const_name
-    }).unwrap();
-    let (r, g, b) = timeline_const.color_comps.to_rgb(&known_colors);
-    let a = timeline_const.color_comps.alpha().unwrap_or(255);
+    let timeline_const = general_goodies.timeline_color_ref.as_ref().and_then(|timeline_color_ref| {
+        general_goodies.raw_colors.constants.consts.iter().find(|cnst| {
+            cnst.const_name == timeline_color_ref.const_name
+        })
+    });
+    if let Some(timeline_const) = timeline_const {
+        let (r, g, b) = timeline_const.color_comps.to_rgb(&known_colors);
+        let a = timeline_const.color_comps.alpha().unwrap_or(255);
 
-    let timeline_color_const = ColorConst::from_comps(r, g, b, a);
+        let timeline_color_const = ColorConst::from_comps(r, g, b, a);
 
-    theme.constant_refs.insert(UiTarget::Playhead, timeline_color_const);
+        theme.constant_refs.insert(UiTarget::Playhead, timeline_color_const);
+    }
 
     let file = File::create(output_json).expect("Unable to create file");
     let writer = BufWriter::new(file);
@@ -50,4 +64,11 @@ fn main() -> anyhow::Result<()> {
     serde_json::to_writer_pretty(writer, &theme).unwrap();
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// VmHWM from procfs, only there on Linux
+fn peak_rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
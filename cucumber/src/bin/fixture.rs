@@ -0,0 +1,15 @@
+use std::{env, path::PathBuf};
+
+use cucumber::{fixture::write_fixture_jar, log::log_message};
+
+// Writes the synthetic Bitwig-like JAR, e.g. to run selftest against it in CI:
+// fixture fixture.jar && selftest fixture.jar
+fn main() -> anyhow::Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let output_jar = PathBuf::from(&args[1]);
+
+    write_fixture_jar(&output_jar)?;
+    log_message(&format!("Written: {}", output_jar.display()));
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use cucumber::{
+    apply::patch_jar,
+    launch::{launch_bitwig, launch_command},
+    log::{log_format_from_flags, log_message, set_log_format},
+    output::{reveal_in_file_manager, OutputSettings},
+    LineNumbers, WriteOptions,
+};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() -> anyhow::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+
+    let mut options = WriteOptions::default();
+    if flags.iter().any(|flag| flag == "--keep-line-numbers") {
+        options.line_numbers = LineNumbers::Preserve;
+    }
+    options.store_patched_classes = flags.iter().any(|flag| flag == "--store-uncompressed");
+    set_log_format(log_format_from_flags(&flags));
+    let watch = flags.iter().any(|flag| flag == "--watch");
+    let launch = flags.iter().any(|flag| flag == "--launch");
+    let reveal = flags.iter().any(|flag| flag == "--reveal");
+    let mut settings = OutputSettings::from_env();
+    if flags.iter().any(|flag| flag == "--no-theme-json") {
+        settings.save_theme_json = false;
+    }
+    settings.verify_classes = flags.iter().any(|flag| flag == "--verify");
+
+    let input_jar = PathBuf::from(&args[1]);
+    let theme_json = PathBuf::from(&args[2]);
+    let output_jar = args.get(3).map(PathBuf::from);
+
+    let written = patch_jar(&input_jar, &theme_json, output_jar.as_deref(), &settings, &options)?;
+    if reveal {
+        if let Err(err) = reveal_in_file_manager(&written) {
+            log_message(&format!("{}", err));
+        }
+    }
+    if launch {
+        run_bitwig(&written);
+    }
+
+    if !watch {
+        return Ok(());
+    }
+
+    // Re-patch when Bitwig gets reinstalled or theme gets edited
+    let mut last_modified = modified(&input_jar, &theme_json);
+    log_message(&format!("Watching {} and {}", input_jar.display(), theme_json.display()));
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let now_modified = modified(&input_jar, &theme_json);
+        if now_modified == last_modified {
+            continue;
+        }
+        last_modified = now_modified;
+
+        print!("Input changed on disk, patch again? [Y/n] ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        if answer.trim().eq_ignore_ascii_case("n") {
+            continue;
+        }
+
+        match patch_jar(&input_jar, &theme_json, output_jar.as_deref(), &settings, &options) {
+            Ok(written) if launch => run_bitwig(&written),
+            Ok(_) => {}
+            Err(err) => log_message(&format!("Failed to patch: {:?}", err)),
+        }
+        // Don't react on changes made while we were busy
+        last_modified = modified(&input_jar, &theme_json);
+    }
+}
+
+// Bitwig output goes to our log, failing to start it isn't fatal
+fn run_bitwig(patched_jar: &Path) {
+    let command = launch_command();
+    log_message(&format!("Launching: {}", command));
+    match launch_bitwig(&command, patched_jar, |line| log_message(&format!("[bitwig] {}", line))) {
+        Ok(status) => log_message(&format!("Bitwig exited: {}", status)),
+        Err(err) => log_message(&format!("{}", err)),
+    }
+}
+
+fn modified(input_jar: &Path, theme_json: &Path) -> [Option<SystemTime>; 2] {
+    [input_jar, theme_json].map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+}
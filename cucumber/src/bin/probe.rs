@@ -0,0 +1,51 @@
+use std::{env, fs};
+
+use cucumber::{
+    extract_general_goodies,
+    log::{log_format, log_format_from_flags, set_log_format, LogFormat},
+    probe_palette_methods, MethodDescription,
+};
+use krakatau2::zip;
+
+// Prints palette methods found for every role. A role whose extracted method
+// isn't the only candidate in the class means the JAR layout has drifted.
+fn main() -> anyhow::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    set_log_format(log_format_from_flags(&flags));
+    let input_jar = &args[1];
+
+    let file = fs::File::open(input_jar)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+
+    let general_goodies = extract_general_goodies(&mut zip)?;
+    let probes = probe_palette_methods(&mut zip, &general_goodies)?;
+
+    let describe = |desc: Option<&MethodDescription>| match desc {
+        Some(desc) => format!("{}.{}{}", desc.class, desc.method, desc.signature),
+        None => "not found".into(),
+    };
+    for probe in &probes {
+        if log_format() == LogFormat::Json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "event": "palette_role",
+                    "role": probe.role,
+                    "extracted": describe(probe.extracted.as_ref()),
+                    "candidates": probe.candidates.iter().map(|desc| describe(Some(desc))).collect::<Vec<_>>(),
+                    "agrees": probe.agrees(),
+                })
+            );
+            continue;
+        }
+        println!("{:>12}: {}", probe.role, describe(probe.extracted.as_ref()));
+        if !probe.agrees() {
+            for candidate in &probe.candidates {
+                println!("{:>12}  candidate: {}", "DIFFERS", describe(Some(candidate)));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, env, fs};
+
+use anyhow::bail;
+use cucumber::{
+    extract_general_goodies,
+    log::{log_event, log_format_from_flags, log_message, set_log_format},
+    types::{AbsoluteColor, CucumberBitwigTheme},
+    write_theme_to_jar, WriteOptions,
+};
+use krakatau2::zip;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+// Fixed seed, so failures are reproducible
+const SEED: u64 = 0xC0C0_3BE2;
+const COLORS_TO_CHANGE: usize = 10;
+
+// Patches a few colors, reads the patched JAR back and checks that the new values are there
+fn main() -> anyhow::Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    set_log_format(log_format_from_flags(&flags));
+    let input_jar = &args[1];
+
+    let file = fs::File::open(input_jar)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+
+    let mut general_goodies = extract_general_goodies(&mut zip)?;
+    let mut theme = CucumberBitwigTheme::from_goodies(&general_goodies);
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let names = theme.named_colors.keys().cloned().collect::<Vec<_>>();
+    let mut expected = BTreeMap::new();
+    for name in names.choose_multiple(&mut rng, COLORS_TO_CHANGE) {
+        let color = AbsoluteColor {
+            r: rng.gen(),
+            g: rng.gen(),
+            b: rng.gen(),
+            a: 255,
+        };
+        expected.insert(name.clone(), color);
+    }
+    theme.apply_changes(&expected);
+
+    // One raw constant too, except the playhead one since it's looked up by value
+    let playhead_const = general_goodies
+        .timeline_color_ref
+        .as_ref()
+        .map(|timeline_color_ref| &timeline_color_ref.const_name);
+    let raw_names = theme
+        .raw_colors
+        .keys()
+        .filter(|name| Some(*name) != playhead_const)
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut expected_raw = BTreeMap::new();
+    if let Some(name) = raw_names.choose(&mut rng) {
+        let color = AbsoluteColor {
+            r: rng.gen(),
+            g: rng.gen(),
+            b: rng.gen(),
+            a: 255,
+        };
+        expected_raw.insert(name.clone(), color);
+    }
+    theme.apply_raw_colors(&expected_raw);
+
+    let output_jar = env::temp_dir().join("cucumber-selftest.jar");
+    write_theme_to_jar(
+        &mut zip,
+        &mut general_goodies,
+        &theme,
+        &output_jar,
+        &WriteOptions::default(),
+    )?;
+
+    let file = fs::File::open(&output_jar)?;
+    let mut zip = zip::ZipArchive::new(file)?;
+    let reloaded = CucumberBitwigTheme::from_jar(&mut zip)?;
+
+    let mut failures = 0;
+    let results = expected
+        .iter()
+        .map(|(name, color)| (name, color, reloaded.absolute_color(name)))
+        .chain(
+            expected_raw
+                .iter()
+                .map(|(name, color)| (name, color, reloaded.raw_colors.get(name))),
+        );
+    for (name, color, found) in results {
+        match found {
+            Some(found) if found == color => log_event(
+                "roundtrip",
+                serde_json::json!({ "name": name, "ok": true }),
+                &format!("OK   {}", name),
+            ),
+            found => {
+                failures += 1;
+                let found = found.map(AbsoluteColor::to_hex);
+                log_event(
+                    "roundtrip",
+                    serde_json::json!({
+                        "name": name,
+                        "ok": false,
+                        "expected": color.to_hex(),
+                        "found": found,
+                    }),
+                    &format!("FAIL {}: expected {}, found {:?}", name, color.to_hex(), found),
+                );
+            }
+        }
+    }
+
+    fs::remove_file(&output_jar)?;
+
+    if failures > 0 {
+        bail!(
+            "{} of {} colors didn't survive round-trip",
+            failures,
+            expected.len() + expected_raw.len()
+        );
+    }
+    log_message(&format!(
+        "All {} colors survived round-trip",
+        expected.len() + expected_raw.len()
+    ));
+
+    Ok(())
+}
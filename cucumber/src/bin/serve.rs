@@ -0,0 +1,134 @@
+// Automation mode for external editors and scripts: `serve`
+//
+// Reads one JSON command per line from stdin. Every stdout line is JSON, log
+// messages have "event": "message" and each command gets exactly one line with
+// "event": "response" and "ok" set, errors come with "error".
+//
+//   {"cmd": "load", "jar": "bitwig.jar"}
+//       scans the JAR and keeps results for further commands, replies with "colors" count
+//   {"cmd": "set-color", "name": "On", "color": {"r": 255, "g": 0, "b": 0, "a": 255}}
+//       replies with "previous" color
+//   {"cmd": "export", "path": "theme.json"}
+//       writes theme JSON with all edits, without "path" the theme comes in "theme"
+//   {"cmd": "save", "output": "patched.jar"}
+//       writes patched JAR, replies with "manifest" path, with
+//       "store_uncompressed": true patched classes aren't compressed
+//
+// Scanning happens only on load, so any number of edits and saves are cheap.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead},
+    path::PathBuf,
+};
+
+use anyhow::anyhow;
+use cucumber::{
+    apply::open_jar,
+    exchange::export_theme_json,
+    extract_general_goodies,
+    log::{log_event, set_log_format, LogFormat},
+    types::{AbsoluteColor, CucumberBitwigTheme, NamedColor},
+    write_theme_to_jar, GeneralGoodies, WriteOptions,
+};
+use krakatau2::zip::ZipArchive;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+enum Command {
+    Load { jar: PathBuf },
+    SetColor { name: String, color: AbsoluteColor },
+    Export { path: Option<PathBuf> },
+    Save {
+        output: PathBuf,
+        #[serde(default)]
+        store_uncompressed: bool,
+    },
+}
+
+struct Session {
+    zip: ZipArchive<fs::File>,
+    general_goodies: GeneralGoodies,
+    theme: CucumberBitwigTheme,
+}
+
+fn main() -> anyhow::Result<()> {
+    set_log_format(LogFormat::Json);
+
+    let mut session = None;
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = serde_json::from_str::<Command>(&line)
+            .map_err(anyhow::Error::from)
+            .and_then(|command| run(&mut session, command));
+        match result {
+            Ok(mut fields) => {
+                fields["ok"] = true.into();
+                log_event("response", fields, "ok");
+            }
+            Err(err) => {
+                let error = format!("{:#}", err);
+                log_event("response", json!({ "ok": false, "error": error }), &error);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run(session: &mut Option<Session>, command: Command) -> anyhow::Result<Value> {
+    if let Command::Load { jar } = command {
+        let mut zip = open_jar(&jar)?;
+        let general_goodies = extract_general_goodies(&mut zip)?;
+        let theme = CucumberBitwigTheme::from_goodies(&general_goodies);
+        let colors = theme.named_colors.len();
+        *session = Some(Session { zip, general_goodies, theme });
+        return Ok(json!({ "colors": colors }));
+    }
+
+    let session = session.as_mut().ok_or_else(|| anyhow!("No JAR loaded, send \"load\" first"))?;
+    match command {
+        Command::Load { .. } => unreachable!(),
+        Command::SetColor { name, color } => {
+            let previous = match session.theme.named_colors.get(&name) {
+                Some(NamedColor::Absolute(previous)) => previous.clone(),
+                Some(NamedColor::Relative(_)) => return Err(anyhow!("Color \"{}\" is relative", name)),
+                None => return Err(anyhow!("Unknown color \"{}\"", name)),
+            };
+            session.theme.apply_changes(&BTreeMap::from([(name, color)]));
+            Ok(json!({ "previous": previous }))
+        }
+        Command::Export { path } => {
+            let theme_json = export_theme_json(&session.theme)?;
+            match path {
+                Some(path) => {
+                    fs::write(&path, theme_json)?;
+                    Ok(json!({ "path": path }))
+                }
+                None => Ok(json!({ "theme": serde_json::from_str::<Value>(&theme_json)? })),
+            }
+        }
+        Command::Save { output, store_uncompressed } => {
+            // Writing updates the goodies to match the output, the next save
+            // must still start from the original JAR
+            let mut general_goodies = session.general_goodies.clone();
+            let manifest = write_theme_to_jar(
+                &mut session.zip,
+                &mut general_goodies,
+                &session.theme,
+                &output,
+                &WriteOptions {
+                    store_patched_classes: store_uncompressed,
+                    ..Default::default()
+                },
+            )?;
+            let manifest_path = manifest.write_next_to(&output)?;
+            Ok(json!({ "output": output, "manifest": manifest_path }))
+        }
+    }
+}
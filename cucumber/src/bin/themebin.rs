@@ -0,0 +1,26 @@
+use std::{env, fs, path::PathBuf};
+
+use cucumber::{
+    exchange::{export_theme_bin, export_theme_json, import_theme_bin, import_theme_json, BINARY_THEME_EXTENSION},
+    log::log_message,
+};
+
+// themebin <input> <output>
+// Converts theme JSON to a binary theme and back, direction follows the
+// input extension
+fn main() -> anyhow::Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let input = PathBuf::from(&args[1]);
+    let output = PathBuf::from(&args[2]);
+
+    if input.extension().is_some_and(|ext| ext == BINARY_THEME_EXTENSION) {
+        let theme = import_theme_bin(&fs::read(&input)?)?;
+        fs::write(&output, export_theme_json(&theme)?)?;
+    } else {
+        let theme = import_theme_json(&fs::read_to_string(&input)?)?;
+        fs::write(&output, export_theme_bin(&theme)?)?;
+    }
+    log_message(&format!("Written: {}", output.display()));
+
+    Ok(())
+}
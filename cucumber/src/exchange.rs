@@ -0,0 +1,766 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use krakatau2::zip::{write::FileOptions, ZipArchive, ZipWriter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::preview::theme_preview_png;
+use crate::transform::{DEFAULT_MIN_CONTRAST, KNOWN_CONTRAST_PAIRS};
+use crate::{contrast_ratio, simulate_cvd, ColorVisionDeficiency};
+use crate::types::{AbsoluteColor, ColorAdjustment, ColorConst, CucumberBitwigTheme, NamedColor, UiTarget};
+
+// Colors renamed between Bitwig versions: current name -> previous names.
+// Only confirmed renames belong here, themes can add their own via `aliases`.
+const BUILTIN_ALIASES: &[(&str, &[&str])] = &[];
+
+pub const THEME_FORMAT_VERSION: u64 = 2;
+const FORMAT_VERSION_KEY: &str = "format_version";
+
+// Output only depends on theme contents (sorted keys), so theme files diff
+// cleanly. Floats are written in their shortest exact form, f32 ones as f32
+// (0.1, not 0.10000000149011612), so importing gives back the same values.
+pub fn export_theme_json(theme: &CucumberBitwigTheme) -> serde_json::Result<String> {
+    if let Some(problem) = theme.metadata_problem() {
+        return Err(serde::ser::Error::custom(problem));
+    }
+    // Through text, a Value made from the theme directly would widen f32 to
+    // f64 and print all of its digits
+    let mut value: Value = serde_json::from_str(&serde_json::to_string(&without_extraction_info(theme))?)?;
+    if let Value::Object(map) = &mut value {
+        map.insert(FORMAT_VERSION_KEY.into(), THEME_FORMAT_VERSION.into());
+    }
+    sort_keys(&mut value);
+    serde_json::to_string_pretty(&value)
+}
+
+// What the scan found out about the JAR the theme was read from (anchor
+// classes, failures, definition sites...), it describes that JAR and not the
+// theme, so theme files leave it out
+fn without_extraction_info(theme: &CucumberBitwigTheme) -> CucumberBitwigTheme {
+    CucumberBitwigTheme {
+        origins: BTreeMap::new(),
+        duplicate_origins: BTreeMap::new(),
+        anchors: BTreeMap::new(),
+        parse_failures: Vec::new(),
+        use_counts: BTreeMap::new(),
+        uneditable_colors: Vec::new(),
+        palette_class: None,
+        ..theme.clone()
+    }
+}
+
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        Value::Object(map) => {
+            let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, item) in &mut entries {
+                sort_keys(item);
+            }
+            *map = entries.into_iter().collect();
+        }
+        _ => {}
+    }
+}
+
+pub fn import_theme_json(text: &str) -> serde_json::Result<CucumberBitwigTheme> {
+    let value = serde_json::from_str(text)?;
+    let theme: CucumberBitwigTheme = serde_json::from_value(migrate_theme_json(value))?;
+    if let Some(problem) = theme.metadata_problem() {
+        return Err(serde::de::Error::custom(problem));
+    }
+    Ok(theme)
+}
+
+// Upgrades theme JSON written by older versions to the current schema.
+// Files without `format_version` are v1.
+pub fn migrate_theme_json(mut value: Value) -> Value {
+    let Value::Object(map) = &mut value else {
+        return value;
+    };
+    let mut version = map
+        .get(FORMAT_VERSION_KEY)
+        .and_then(Value::as_u64)
+        .unwrap_or(1);
+
+    // Each step upgrades by one version, add new ones below
+    if version == 1 {
+        // v2 only introduced `format_version` itself
+        version = 2;
+    }
+
+    map.insert(FORMAT_VERSION_KEY.into(), version.into());
+    value
+}
+
+// Extension of binary theme files
+pub const BINARY_THEME_EXTENSION: &str = "cbor";
+
+#[cfg(feature = "binary-theme")]
+#[derive(Serialize, Deserialize)]
+struct BinaryTheme {
+    format_version: u64,
+    theme: CucumberBitwigTheme,
+}
+
+// Same contents as theme JSON in CBOR, quicker to parse for tools which
+// apply many themes. Not meant for diffing, unlike the JSON.
+#[cfg(feature = "binary-theme")]
+pub fn export_theme_bin(theme: &CucumberBitwigTheme) -> anyhow::Result<Vec<u8>> {
+    if let Some(problem) = theme.metadata_problem() {
+        return Err(anyhow!(problem));
+    }
+    let binary = BinaryTheme {
+        format_version: THEME_FORMAT_VERSION,
+        theme: without_extraction_info(theme),
+    };
+    let mut data = Vec::new();
+    ciborium::into_writer(&binary, &mut data)?;
+    Ok(data)
+}
+
+// Binary files are only written by versions which already have
+// format_version, so unlike JSON there is nothing to migrate
+#[cfg(feature = "binary-theme")]
+pub fn import_theme_bin(data: &[u8]) -> anyhow::Result<CucumberBitwigTheme> {
+    let binary: BinaryTheme = ciborium::from_reader(data)?;
+    if binary.format_version > THEME_FORMAT_VERSION {
+        return Err(anyhow!(
+            "Binary theme uses format v{}, this version of cucumber supports up to v{}",
+            binary.format_version,
+            THEME_FORMAT_VERSION
+        ));
+    }
+    if let Some(problem) = binary.theme.metadata_problem() {
+        return Err(anyhow!(problem));
+    }
+    Ok(binary.theme)
+}
+
+// Theme as it would look after applying user edits on top of it
+pub fn theme_with_changes(
+    theme: &CucumberBitwigTheme,
+    changed_colors: &BTreeMap<String, AbsoluteColor>,
+    changed_refs: &BTreeMap<UiTarget, ColorConst>,
+    changed_raw_colors: &BTreeMap<String, AbsoluteColor>,
+    changed_raw_floats: &BTreeMap<String, [f64; 4]>,
+    changed_adjustments: &BTreeMap<String, ColorAdjustment>,
+    flattened: &BTreeSet<String>,
+) -> CucumberBitwigTheme {
+    let mut theme = theme.clone();
+    theme.apply_changes(changed_colors);
+    theme.apply_constant_refs(changed_refs);
+    theme.apply_raw_colors(changed_raw_colors);
+    theme.apply_raw_color_floats(changed_raw_floats);
+    // After raw colors, as adjustments are resolved against them
+    theme.apply_adjustments(changed_adjustments);
+    theme.apply_flattening(flattened);
+    theme
+}
+
+pub const BUNDLE_EXTENSION: &str = "bwtheme";
+const BUNDLE_THEME_ENTRY: &str = "theme.json";
+const BUNDLE_PREVIEW_ENTRY: &str = "preview.png";
+const BUNDLE_METADATA_ENTRY: &str = "metadata.json";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BundleMetadata {
+    pub name: String,
+    pub format_version: u64,
+    pub cucumber_version: String,
+    // Bitwig version the theme was made for, if known
+    pub bitwig_version: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThemeBundle {
+    pub metadata: BundleMetadata,
+    pub theme: CucumberBitwigTheme,
+    pub preview_png: Option<Vec<u8>>,
+}
+
+// Single zip (.bwtheme) with theme JSON, preview image and metadata, for sharing
+pub fn export_bundle(
+    theme: &CucumberBitwigTheme,
+    bitwig_version: Option<&str>,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let metadata = BundleMetadata {
+        name: theme.name.clone(),
+        format_version: THEME_FORMAT_VERSION,
+        cucumber_version: env!("CARGO_PKG_VERSION").into(),
+        bitwig_version: bitwig_version.map(String::from),
+    };
+
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    let entries = [
+        (BUNDLE_METADATA_ENTRY, serde_json::to_string_pretty(&metadata)?.into_bytes()),
+        (BUNDLE_THEME_ENTRY, export_theme_json(theme)?.into_bytes()),
+        (BUNDLE_PREVIEW_ENTRY, theme_preview_png(theme)),
+    ];
+    for (name, data) in entries {
+        zip.start_file(name, FileOptions::default())?;
+        zip.write_all(&data)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+pub fn import_bundle(path: &Path) -> anyhow::Result<ThemeBundle> {
+    let mut zip = ZipArchive::new(fs::File::open(path)?)?;
+
+    let mut read_entry = |name: &str| -> anyhow::Result<Vec<u8>> {
+        let mut entry = zip
+            .by_name(name)
+            .map_err(|err| anyhow!("{} is missing from bundle: {}", name, err))?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        Ok(data)
+    };
+
+    let metadata: BundleMetadata = serde_json::from_slice(&read_entry(BUNDLE_METADATA_ENTRY)?)?;
+    if metadata.format_version > THEME_FORMAT_VERSION {
+        return Err(anyhow!(
+            "Bundle uses theme format v{}, this version of cucumber supports up to v{}",
+            metadata.format_version,
+            THEME_FORMAT_VERSION
+        ));
+    }
+    let theme = import_theme_json(std::str::from_utf8(&read_entry(BUNDLE_THEME_ENTRY)?)?)?;
+    // Preview is only informational
+    let preview_png = read_entry(BUNDLE_PREVIEW_ENTRY).ok();
+
+    Ok(ThemeBundle {
+        metadata,
+        theme,
+        preview_png,
+    })
+}
+
+// Adobe Swatch Exchange block types
+const ASE_GROUP_START: u16 = 0xC001;
+const ASE_GROUP_END: u16 = 0xC002;
+const ASE_COLOR_ENTRY: u16 = 0x0001;
+// Global, spot and normal are the choices, palette colors are normal ones
+const ASE_COLOR_TYPE_NORMAL: u16 = 2;
+
+// Swatches file for Adobe tools: one group named after the theme with an RGB
+// entry per absolute color. ASE has no alpha, so it is dropped.
+pub fn export_ase(theme: &CucumberBitwigTheme, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, encode_ase(theme))?;
+    Ok(())
+}
+
+fn encode_ase(theme: &CucumberBitwigTheme) -> Vec<u8> {
+    let mut blocks = vec![(ASE_GROUP_START, ase_name(&theme.name))];
+    for (name, color) in &theme.named_colors {
+        let NamedColor::Absolute(color) = color else {
+            continue;
+        };
+        let mut body = ase_name(name);
+        body.extend_from_slice(b"RGB ");
+        for comp in [color.r, color.g, color.b] {
+            body.extend_from_slice(&(comp as f32 / 255.0).to_be_bytes());
+        }
+        body.extend_from_slice(&ASE_COLOR_TYPE_NORMAL.to_be_bytes());
+        blocks.push((ASE_COLOR_ENTRY, body));
+    }
+    blocks.push((ASE_GROUP_END, vec![]));
+
+    let mut out = b"ASEF".to_vec();
+    // Version 1.0
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(blocks.len() as u32).to_be_bytes());
+    for (kind, body) in blocks {
+        out.extend_from_slice(&kind.to_be_bytes());
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(&body);
+    }
+    out
+}
+
+// Length in UTF-16 units (including terminator) followed by UTF-16BE text
+fn ase_name(name: &str) -> Vec<u8> {
+    let units = name.encode_utf16().chain([0]).collect::<Vec<_>>();
+    let mut out = (units.len() as u16).to_be_bytes().to_vec();
+    for unit in units {
+        out.extend_from_slice(&unit.to_be_bytes());
+    }
+    out
+}
+
+pub fn builtin_aliases() -> HashMap<String, Vec<String>> {
+    BUILTIN_ALIASES
+        .iter()
+        .map(|(name, old_names)| {
+            let old_names = old_names.iter().map(|old| old.to_string()).collect();
+            (name.to_string(), old_names)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct AliasUse {
+    pub old_name: String,
+    pub new_name: String,
+}
+
+// Renames theme colors saved under an old name to the name used by the JAR.
+// Aliases from the theme file take precedence over the built-in ones.
+pub fn apply_aliases(
+    theme: &mut CucumberBitwigTheme,
+    is_known_name: impl Fn(&str) -> bool,
+) -> Vec<AliasUse> {
+    let mut aliases = builtin_aliases();
+    for (name, old_names) in &theme.aliases {
+        aliases.insert(name.clone(), old_names.clone());
+    }
+
+    let mut used = Vec::new();
+    for (new_name, old_names) in aliases {
+        if theme.named_colors.contains_key(&new_name) || !is_known_name(&new_name) {
+            continue;
+        }
+        for old_name in old_names {
+            if let Some(color) = theme.named_colors.remove(&old_name) {
+                theme.named_colors.insert(new_name.clone(), color);
+                used.push(AliasUse {
+                    old_name,
+                    new_name: new_name.clone(),
+                });
+                break;
+            }
+        }
+    }
+    used
+}
+
+// Lowest confidence of a fuzzy name match worth reporting
+pub const DEFAULT_FUZZY_THRESHOLD: f64 = 0.8;
+
+// Theme color with no exact counterpart in the JAR and the JAR color it most
+// likely is. Only reported: it takes an alias in the theme to apply it.
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub theme_name: String,
+    pub known_name: String,
+    // 0..1, 1 when names only differ in case, punctuation or word order
+    pub confidence: f64,
+}
+
+// Lowercase words without punctuation, sorted
+fn normalize_color_name(name: &str) -> String {
+    let cleaned = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { ' ' })
+        .collect::<String>();
+    let mut words = cleaned.split_whitespace().collect::<Vec<_>>();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn name_similarity(a: &str, b: &str) -> f64 {
+    let a = normalize_color_name(a).chars().collect::<Vec<_>>();
+    let b = normalize_color_name(b).chars().collect::<Vec<_>>();
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 0.0;
+    }
+    1.0 - edit_distance(&a, &b) as f64 / len as f64
+}
+
+// Best match among known names for every theme color the JAR doesn't have,
+// known names the theme already sets aren't offered
+pub fn fuzzy_name_matches<'a>(
+    theme: &CucumberBitwigTheme,
+    known_names: impl IntoIterator<Item = &'a str> + Clone,
+    threshold: f64,
+) -> Vec<FuzzyMatch> {
+    let unknown = theme
+        .named_colors
+        .keys()
+        .filter(|name| !known_names.clone().into_iter().any(|known| known == name.as_str()));
+    unknown
+        .filter_map(|theme_name| {
+            known_names
+                .clone()
+                .into_iter()
+                .filter(|known| !theme.named_colors.contains_key(*known))
+                .map(|known| (known, name_similarity(theme_name, known)))
+                .filter(|(_, confidence)| *confidence >= threshold)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(known, confidence)| FuzzyMatch {
+                    theme_name: theme_name.clone(),
+                    known_name: known.to_string(),
+                    confidence,
+                })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct ColorDiff {
+    pub name: String,
+    // Raw color constant rather than palette color
+    pub raw: bool,
+    pub ours: Option<AbsoluteColor>,
+    pub reference: Option<AbsoluteColor>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    // Only present in our theme
+    Added,
+    // Only present in the reference
+    Removed,
+    Modified,
+}
+
+impl ColorDiff {
+    pub fn kind(&self) -> DiffKind {
+        match (&self.ours, &self.reference) {
+            (Some(_), None) => DiffKind::Added,
+            (None, Some(_)) => DiffKind::Removed,
+            _ => DiffKind::Modified,
+        }
+    }
+}
+
+// Colors which differ from the reference (e.g. factory) theme, to spot JARs
+// which were already patched before
+pub fn diff_against_reference(
+    theme: &CucumberBitwigTheme,
+    reference: &CucumberBitwigTheme,
+) -> Vec<ColorDiff> {
+    let mut diffs = Vec::new();
+
+    let names = theme.named_colors.keys().chain(reference.named_colors.keys());
+    for name in names.collect::<BTreeSet<_>>() {
+        let ours = theme.absolute_color(name);
+        let theirs = reference.absolute_color(name);
+        if ours != theirs {
+            diffs.push(ColorDiff {
+                name: name.clone(),
+                raw: false,
+                ours: ours.cloned(),
+                reference: theirs.cloned(),
+            });
+        }
+    }
+
+    let names = theme.raw_colors.keys().chain(reference.raw_colors.keys());
+    for name in names.collect::<BTreeSet<_>>() {
+        let ours = theme.raw_colors.get(name);
+        let theirs = reference.raw_colors.get(name);
+        if ours != theirs {
+            diffs.push(ColorDiff {
+                name: name.clone(),
+                raw: true,
+                ours: ours.cloned(),
+                reference: theirs.cloned(),
+            });
+        }
+    }
+
+    diffs
+}
+
+// Custom properties for CSS, `--color-<slug>: #RRGGBBAA;` per color in a :root block
+pub fn export_css_vars(theme: &CucumberBitwigTheme) -> String {
+    let mut css = String::from(":root {\n");
+    let mut used_slugs = HashSet::new();
+    for (name, color) in &theme.named_colors {
+        let NamedColor::Absolute(color) = color else {
+            css.push_str(&format!("  /* {}: relative color, no value */\n", name.replace("*/", "* /")));
+            continue;
+        };
+        let base = css_slug(name);
+        // Different names may give the same slug, numbered in name order
+        let mut slug = base.clone();
+        let mut suffix = 2;
+        while !used_slugs.insert(slug.clone()) {
+            slug = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        css.push_str(&format!("  --color-{}: {};\n", slug, color.to_hex().to_lowercase()));
+    }
+    css.push_str("}\n");
+    css
+}
+
+// "Accent (default)" -> "accent-default"
+fn css_slug(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "unnamed".into()
+    } else {
+        slug
+    }
+}
+
+// Markdown list of user edits against the theme they were made on, e.g. for
+// changelogs of a distributed theme
+pub fn export_change_report(
+    pristine: &CucumberBitwigTheme,
+    changed_colors: &BTreeMap<String, AbsoluteColor>,
+    changed_raw_colors: &BTreeMap<String, AbsoluteColor>,
+) -> String {
+    let hex = |color: Option<&AbsoluteColor>| match color {
+        Some(color) => format!("`{}`", color.to_hex()),
+        None => "none".into(),
+    };
+    let mut report = format!("# Changes in {}\n\n", pristine.name);
+    if changed_colors.is_empty() && changed_raw_colors.is_empty() {
+        report.push_str("No changes.\n");
+        return report;
+    }
+
+    if !changed_colors.is_empty() {
+        report.push_str("| Color | Before | After | Class | Note |\n|---|---|---|---|---|\n");
+    }
+    for (name, color) in changed_colors {
+        let origin = pristine.origins.get(name);
+        let class_name = origin.map(|origin| origin.class_name.as_str()).unwrap_or("unknown");
+        // Edited colors are always written as Rgbai
+        let note = match (pristine.named_colors.get(name), origin) {
+            (None, _) => "added".into(),
+            (Some(NamedColor::Relative(_)), _) => "was relative".into(),
+            (Some(NamedColor::Absolute(_)), Some(origin))
+                if !origin.variant.is_empty() && origin.variant != "Rgbai" =>
+            {
+                format!("{} → Rgbai", origin.variant)
+            }
+            _ => String::new(),
+        };
+        report.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            name.replace('|', "\\|"),
+            hex(pristine.absolute_color(name)),
+            hex(Some(color)),
+            class_name,
+            note
+        ));
+    }
+
+    if !changed_raw_colors.is_empty() {
+        report.push_str("\n## Raw color constants\n\n| Constant | Before | After |\n|---|---|---|\n");
+    }
+    for (name, color) in changed_raw_colors {
+        report.push_str(&format!(
+            "| {} | {} | {} |\n",
+            name,
+            hex(pristine.raw_colors.get(name)),
+            hex(Some(color))
+        ));
+    }
+    report
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn hex_rgb((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// Self-contained HTML page: contrast of each (foreground, background) pair
+// as seen with every color vision deficiency, pairs below target flagged,
+// and swatches of all absolute colors as seen with each deficiency.
+// Pairs with colors missing from the theme are skipped.
+pub fn accessibility_report_html(theme: &CucumberBitwigTheme, pairs: &[(&str, &str)], target: f64) -> String {
+    let kinds = ColorVisionDeficiency::ALL;
+    let rgb = |name: &str| theme.absolute_color(name).map(|color| (color.r, color.g, color.b));
+    let kind_headers = kinds
+        .iter()
+        .map(|kind| match kind {
+            ColorVisionDeficiency::None => "<th>Normal vision</th>".to_string(),
+            kind => format!("<th>{}</th>", kind.label()),
+        })
+        .collect::<String>();
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} accessibility</title>\n\
+         <style>\nbody {{ font-family: sans-serif; }}\ntd, th {{ padding: 4px 8px; text-align: left; }}\n\
+         .sample {{ padding: 4px 8px; border: 1px solid #888; }}\n.swatch {{ display: inline-block; width: 40px; height: 20px; border: 1px solid #888; }}\n\
+         .fail {{ color: #b00; font-weight: bold; }}\n</style>\n</head>\n<body>\n<h1>{} accessibility</h1>\n",
+        escape_html(&theme.name),
+        escape_html(&theme.name)
+    );
+
+    let mut flagged = Vec::new();
+    html.push_str(&format!("<h2>Contrast (target {:.1}:1)</h2>\n<table>\n", target));
+    html.push_str(&format!("<tr><th>Foreground</th><th>Background</th>{}</tr>\n", kind_headers));
+    for (foreground, background) in pairs {
+        let (Some(fg), Some(bg)) = (rgb(foreground), rgb(background)) else {
+            continue;
+        };
+        let cells = kinds
+            .iter()
+            .map(|kind| {
+                let (fg, bg) = (simulate_cvd(fg, *kind), simulate_cvd(bg, *kind));
+                let ratio = contrast_ratio(fg, bg);
+                let class = if ratio < target { " class=\"fail\"" } else { "" };
+                format!(
+                    "<td><span class=\"sample\" style=\"color: {}; background: {}\">Aa</span> <span{}>{:.2}:1</span></td>",
+                    hex_rgb(fg),
+                    hex_rgb(bg),
+                    class,
+                    ratio
+                )
+            })
+            .collect::<String>();
+        let ratio = contrast_ratio(fg, bg);
+        if ratio < target {
+            flagged.push(format!("{} on {}: {:.2}:1", foreground, background, ratio));
+        }
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td>{}</tr>\n",
+            escape_html(foreground),
+            escape_html(background),
+            cells
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Low contrast</h2>\n");
+    if flagged.is_empty() {
+        html.push_str("<p>All pairs reach the target.</p>\n");
+    } else {
+        html.push_str("<ul>\n");
+        for line in &flagged {
+            html.push_str(&format!("<li class=\"fail\">{}</li>\n", escape_html(line)));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str(&format!("<h2>Colors</h2>\n<table>\n<tr><th>Name</th>{}</tr>\n", kind_headers));
+    for name in theme.named_colors.keys() {
+        let Some(color) = rgb(name) else {
+            continue;
+        };
+        let cells = kinds
+            .iter()
+            .map(|kind| {
+                let simulated = hex_rgb(simulate_cvd(color, *kind));
+                format!("<td><span class=\"swatch\" style=\"background: {}\"></span> {}</td>", simulated, simulated)
+            })
+            .collect::<String>();
+        html.push_str(&format!("<tr><td>{}</td>{}</tr>\n", escape_html(name), cells));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+// Report for the built-in contrast pairs and WCAG AA target
+pub fn export_accessibility_report(theme: &CucumberBitwigTheme, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, accessibility_report_html(theme, KNOWN_CONTRAST_PAIRS, DEFAULT_MIN_CONTRAST))?;
+    Ok(())
+}
+
+// Changed colors as Rust code, to turn a real world edit into a test fixture
+pub fn changed_colors_as_rust(changed_colors: &BTreeMap<String, AbsoluteColor>) -> String {
+    let mut code = String::from(
+        "fn changed_colors() -> Vec<(&'static str, cucumber::ColorComponents)> {\n    vec![\n",
+    );
+    for (name, AbsoluteColor { r, g, b, a }) in changed_colors {
+        code.push_str(&format!(
+            "        ({:?}, cucumber::ColorComponents::Rgbai({}, {}, {}, {})),\n",
+            name, r, g, b, a
+        ));
+    }
+    code.push_str("    ]\n}\n");
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extract_general_goodies_with_options, fixture, ExtractOptions};
+
+    fn fixture_theme() -> CucumberBitwigTheme {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let goodies = extract_general_goodies_with_options(&mut zip, &ExtractOptions::default(), |_| {}).unwrap();
+        CucumberBitwigTheme::from_goodies(&goodies)
+    }
+
+    #[test]
+    fn export_is_deterministic() {
+        // Themes built separately, so nothing depends on one instance
+        let exported = export_theme_json(&fixture_theme()).unwrap();
+        assert_eq!(export_theme_json(&fixture_theme()).unwrap(), exported);
+
+        // Shortest exact form: 0.1f32 would be 0.10000000149011612 as f64,
+        // doubles keep all of their digits
+        assert!(exported.contains("0.1\n"), "{}", exported);
+        assert!(!exported.contains("0.10000000149011612"), "{}", exported);
+        assert!(exported.contains("0.666333,"), "{}", exported);
+
+        // Reading it back changes nothing
+        let imported = import_theme_json(&exported).unwrap();
+        assert_eq!(imported.raw_color_floats, fixture_theme().raw_color_floats);
+        assert_eq!(imported.adjusted_colors, fixture_theme().adjusted_colors);
+        assert_eq!(export_theme_json(&imported).unwrap(), exported);
+    }
+
+    #[test]
+    fn export_leaves_out_extraction_info() {
+        let theme = fixture_theme();
+        assert!(!theme.anchors.is_empty() && !theme.origins.is_empty() && theme.palette_class.is_some());
+
+        let exported: Value = serde_json::from_str(&export_theme_json(&theme).unwrap()).unwrap();
+        let keys = exported.as_object().unwrap().keys().map(String::as_str).collect::<Vec<_>>();
+        let diagnostics = [
+            "anchors",
+            "parse_failures",
+            "use_counts",
+            "uneditable_colors",
+            "palette_class",
+            "origins",
+            "duplicate_origins",
+        ];
+        for key in diagnostics {
+            assert!(!keys.contains(&key), "{} in {:?}", key, keys);
+        }
+        assert!(keys.contains(&"named_colors"), "{:?}", keys);
+    }
+
+    #[test]
+    fn export_sorts_keys() {
+        let mut value = serde_json::json!({ "b": { "z": 1.25, "a": -0.5 }, "a": [{ "y": 1, "x": 2 }] });
+        sort_keys(&mut value);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":[{"x":2,"y":1}],"b":{"a":-0.5,"z":1.25}}"#
+        );
+    }
+}
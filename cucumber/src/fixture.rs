@@ -0,0 +1,369 @@
+// Tiny Bitwig-like JAR, so the whole pipeline can be exercised without
+// shipping the real one. Classes are written in Krakatau assembly and have
+// just enough in them to be detected by the same anchors.
+
+use std::{
+    io::{Cursor, Write},
+    path::Path,
+};
+
+use krakatau2::{
+    file_output_util::Writer,
+    zip::{write::FileOptions, ZipArchive, ZipWriter},
+};
+
+use crate::assemble_class;
+
+pub const COLOR_CLASS: &str = "com/bitwig/Color";
+pub const MORE_COLORS_CLASS: &str = "com/bitwig/MoreColors";
+pub const PALETTE_CLASS: &str = "com/bitwig/Palette";
+pub const INIT_CLASS: &str = "com/bitwig/Init";
+pub const TIMELINE_CLASS: &str = "com/bitwig/Timeline";
+pub const OVERRIDES_CLASS: &str = "com/bitwig/Overrides";
+
+// Color record with raw color constants, carries the raw color anchor
+const COLOR_SOURCE: &str = r#"
+.version 52 0
+.class public super com/bitwig/Color
+.super java/lang/Object
+.field public static final BLACK Lcom/bitwig/Color;
+.field public static final WHITE Lcom/bitwig/Color;
+.field public static final ODD Lcom/bitwig/Color;
+
+.method public <init> : (FFFF)V
+    .code stack 1 locals 5
+        aload_0
+        invokespecial Method java/lang/Object <init> ()V
+        return
+    .end code
+.end method
+
+.method public <init> : (DDDD)V
+    .code stack 1 locals 9
+        aload_0
+        invokespecial Method java/lang/Object <init> ()V
+        return
+    .end code
+.end method
+
+.method static <clinit> : ()V
+    .code stack 10 locals 0
+        new com/bitwig/Color
+        dup
+        fconst_0
+        fconst_0
+        fconst_0
+        fconst_1
+        invokespecial Method com/bitwig/Color <init> (FFFF)V
+        putstatic Field com/bitwig/Color BLACK Lcom/bitwig/Color;
+        new com/bitwig/Color
+        dup
+        fconst_1
+        fconst_1
+        fconst_1
+        fconst_1
+        invokespecial Method com/bitwig/Color <init> (FFFF)V
+        putstatic Field com/bitwig/Color WHITE Lcom/bitwig/Color;
+        new com/bitwig/Color
+        dup
+        ldc2_w 0.666333
+        dconst_0
+        dconst_0
+        dconst_1
+        invokespecial Method com/bitwig/Color <init> (DDDD)V
+        putstatic Field com/bitwig/Color ODD Lcom/bitwig/Color;
+        return
+    .end code
+.end method
+.end class
+"#;
+
+// More raw color constants, built with constructors of the color record, so
+// the class carries the raw color anchor but declares no constructors itself
+const MORE_COLORS_SOURCE: &str = r#"
+.version 52 0
+.class public super com/bitwig/MoreColors
+.super java/lang/Object
+.field public static final GRAY Lcom/bitwig/Color;
+.field public static final DEEP Lcom/bitwig/Color;
+
+.method static <clinit> : ()V
+    .code stack 10 locals 0
+        new com/bitwig/Color
+        dup
+        ldc 0.5f
+        ldc 0.5f
+        ldc 0.5f
+        fconst_1
+        invokespecial Method com/bitwig/Color <init> (FFFF)V
+        putstatic Field com/bitwig/MoreColors GRAY Lcom/bitwig/Color;
+        new com/bitwig/Color
+        dup
+        ldc2_w 0.666333
+        ldc2_w 0.1
+        ldc2_w 0.2
+        dconst_1
+        invokespecial Method com/bitwig/Color <init> (DDDD)V
+        putstatic Field com/bitwig/MoreColors DEEP Lcom/bitwig/Color;
+        return
+    .end code
+.end method
+.end class
+"#;
+
+// Main palette: second method defines colors through every palette method.
+// Three floats mean two different things here: plain RGB for "Accent (default)"
+// (String;FFF) and HSV deltas for the adjusted colors, which are told apart by
+// the extra base argument in the signature.
+const PALETTE_SOURCE: &str = r#"
+.version 52 0
+.class public super com/bitwig/Palette
+.super java/lang/Object
+
+.method public <init> : ()V
+    .code stack 1 locals 1
+        aload_0
+        invokespecial Method java/lang/Object <init> ()V
+        return
+    .end code
+.end method
+
+.method public define : ()V
+    .code stack 10 locals 1
+        aload_0
+        ldc "Device Tint Future"
+        bipush 100
+        invokevirtual Method com/bitwig/Palette gray (Ljava/lang/String;I)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Light Timeline Background"
+        bipush 50
+        bipush 52
+        bipush 54
+        invokevirtual Method com/bitwig/Palette rgb (Ljava/lang/String;III)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "On"
+        sipush 240
+        sipush 160
+        bipush 40
+        sipush 255
+        invokevirtual Method com/bitwig/Palette rgba (Ljava/lang/String;IIII)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Selection Fill"
+        sipush 240
+        sipush 160
+        bipush 40
+        sipush 254
+        invokevirtual Method com/bitwig/Palette rgba (Ljava/lang/String;IIII)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Accent (default)"
+        fconst_1
+        ldc 0.5f
+        fconst_0
+        invokevirtual Method com/bitwig/Palette rgb (Ljava/lang/String;FFF)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Accent Dimmed"
+        ldc "Accent (default)"
+        fconst_0
+        ldc -0.25f
+        ldc -0.25f
+        invokevirtual Method com/bitwig/Palette adjusted (Ljava/lang/String;Ljava/lang/String;FFF)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Shadow"
+        getstatic Field com/bitwig/Color BLACK Lcom/bitwig/Color;
+        fconst_0
+        fconst_0
+        ldc 0.1f
+        invokevirtual Method com/bitwig/Palette adjusted (Ljava/lang/String;Lcom/bitwig/Color;FFF)Lcom/bitwig/Color;
+        pop
+        return
+    .end code
+.end method
+
+.method public gray : (Ljava/lang/String;I)Lcom/bitwig/Color;
+    .code stack 1 locals 3
+        aconst_null
+        areturn
+    .end code
+.end method
+
+.method public rgb : (Ljava/lang/String;III)Lcom/bitwig/Color;
+    .code stack 1 locals 5
+        aconst_null
+        areturn
+    .end code
+.end method
+
+.method public rgba : (Ljava/lang/String;IIII)Lcom/bitwig/Color;
+    .code stack 1 locals 6
+        aconst_null
+        areturn
+    .end code
+.end method
+
+.method public rgb : (Ljava/lang/String;FFF)Lcom/bitwig/Color;
+    .code stack 1 locals 5
+        aconst_null
+        areturn
+    .end code
+.end method
+
+.method public adjusted : (Ljava/lang/String;Ljava/lang/String;FFF)Lcom/bitwig/Color;
+    .code stack 1 locals 6
+        aconst_null
+        areturn
+    .end code
+.end method
+
+.method public adjusted : (Ljava/lang/String;Lcom/bitwig/Color;FFF)Lcom/bitwig/Color;
+    .code stack 1 locals 6
+        aconst_null
+        areturn
+    .end code
+.end method
+.end class
+"#;
+
+const INIT_SOURCE: &str = r#"
+.version 52 0
+.class public super com/bitwig/Init
+.super java/lang/Object
+
+.method public static main : ([Ljava/lang/String;)V
+    .code stack 1 locals 1
+        ldc "Apply Device Remote Control Changes To All Devices"
+        pop
+        return
+    .end code
+.end method
+.end class
+"#;
+
+// Playhead is drawn with a raw color constant picked after comparing with 5L
+const TIMELINE_SOURCE: &str = r#"
+.version 52 0
+.class public super com/bitwig/Timeline
+.super java/lang/Object
+
+.method public drawPlayhead : (Lcom/bitwig/graphics/Canvas;D)V
+    .code stack 6 locals 4
+        dload_2
+        ldc2_w 5L
+        l2d
+        ddiv
+        ldc2_w 5L
+        l2d
+        dcmpg
+        ifgt LEnd
+        aload_1
+        getstatic Field com/bitwig/Color BLACK Lcom/bitwig/Color;
+        invokevirtual Method com/bitwig/graphics/Canvas setColor (Lcom/bitwig/Color;)V
+LEnd:
+        return
+    .end code
+.end method
+.end class
+"#;
+
+// Defines colors of the palette again, twice in the same method and through
+// different palette methods. Not part of the default fixture, tests add it to
+// the sources when they need a color defined at several sites.
+pub const OVERRIDES_SOURCE: &str = r#"
+.version 52 0
+.class public super com/bitwig/Overrides
+.super java/lang/Object
+
+.method public static define : (Lcom/bitwig/Palette;)V
+    .code stack 10 locals 1
+        aload_0
+        ldc "Selection Fill"
+        bipush 10
+        bipush 20
+        bipush 30
+        sipush 255
+        invokevirtual Method com/bitwig/Palette rgba (Ljava/lang/String;IIII)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Shadow"
+        getstatic Field com/bitwig/Color WHITE Lcom/bitwig/Color;
+        fconst_0
+        ldc 0.5f
+        fconst_0
+        invokevirtual Method com/bitwig/Palette adjusted (Ljava/lang/String;Lcom/bitwig/Color;FFF)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Selection Fill"
+        bipush 40
+        bipush 50
+        bipush 60
+        invokevirtual Method com/bitwig/Palette rgb (Ljava/lang/String;III)Lcom/bitwig/Color;
+        pop
+        aload_0
+        ldc "Shadow"
+        getstatic Field com/bitwig/MoreColors GRAY Lcom/bitwig/Color;
+        fconst_0
+        fconst_0
+        fconst_0
+        invokevirtual Method com/bitwig/Palette adjusted (Ljava/lang/String;Lcom/bitwig/Color;FFF)Lcom/bitwig/Color;
+        pop
+        return
+    .end code
+.end method
+.end class
+"#;
+
+// Class names with their assembly sources, tests tweak them to build variants
+pub fn fixture_sources() -> Vec<(&'static str, String)> {
+    [
+        // Goes first, before the class declaring the constructors
+        (MORE_COLORS_CLASS, MORE_COLORS_SOURCE),
+        (COLOR_CLASS, COLOR_SOURCE),
+        (PALETTE_CLASS, PALETTE_SOURCE),
+        (INIT_CLASS, INIT_SOURCE),
+        (TIMELINE_CLASS, TIMELINE_SOURCE),
+    ]
+    .map(|(class_name, source)| (class_name, source.to_string()))
+    .into()
+}
+
+// Class file names with their assembled contents
+pub fn assemble_classes(sources: &[(&str, String)]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut classes = Vec::new();
+    for (class_name, source) in sources {
+        let file_name = format!("{}.class", class_name);
+        let data = assemble_class(&file_name, source)?;
+        classes.push((file_name, data));
+    }
+    Ok(classes)
+}
+
+pub fn fixture_classes() -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    assemble_classes(&fixture_sources())
+}
+
+// JAR in memory with the entries in the given order
+pub fn zip_entries(entries: &[(String, Vec<u8>)]) -> anyhow::Result<ZipArchive<Cursor<Vec<u8>>>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (file_name, data) in entries {
+        writer.start_file(file_name.as_str(), FileOptions::default())?;
+        writer.write_all(data)?;
+    }
+    Ok(ZipArchive::new(writer.finish()?)?)
+}
+
+pub fn fixture_zip() -> anyhow::Result<ZipArchive<Cursor<Vec<u8>>>> {
+    zip_entries(&fixture_classes()?)
+}
+
+pub fn write_fixture_jar(path: &Path) -> anyhow::Result<()> {
+    let mut writer = Writer::new(path)?;
+    for (file_name, data) in fixture_classes()? {
+        writer.write(Some(&file_name), &data)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::anyhow;
+
+pub const LAUNCH_COMMAND_ENV: &str = "CUCUMBER_BITWIG_COMMAND";
+
+#[cfg(target_os = "linux")]
+const DEFAULT_LAUNCH_COMMAND: &str = "bitwig-studio";
+#[cfg(target_os = "macos")]
+const DEFAULT_LAUNCH_COMMAND: &str = "\"/Applications/Bitwig Studio.app/Contents/MacOS/BitwigStudio\"";
+#[cfg(target_os = "windows")]
+const DEFAULT_LAUNCH_COMMAND: &str = "\"C:\\Program Files\\Bitwig Studio\\Bitwig Studio.exe\"";
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+const DEFAULT_LAUNCH_COMMAND: &str = "bitwig-studio";
+
+// Command from CUCUMBER_BITWIG_COMMAND or the usual install location.
+// `{jar}` in it is replaced with the patched JAR path.
+pub fn launch_command() -> String {
+    std::env::var(LAUNCH_COMMAND_ENV).unwrap_or_else(|_| DEFAULT_LAUNCH_COMMAND.into())
+}
+
+// Whitespace separated, double quotes group words with spaces
+fn split_command(command: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut has_part = false;
+    for ch in command.chars() {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                has_part = true;
+            }
+            ch if ch.is_whitespace() && !quoted => {
+                if has_part {
+                    parts.push(std::mem::take(&mut current));
+                    has_part = false;
+                }
+            }
+            ch => {
+                current.push(ch);
+                has_part = true;
+            }
+        }
+    }
+    if has_part {
+        parts.push(current);
+    }
+    parts
+}
+
+// Runs Bitwig and waits for it to exit, its output is passed to on_line
+pub fn launch_bitwig(
+    command: &str,
+    patched_jar: &Path,
+    mut on_line: impl FnMut(&str),
+) -> anyhow::Result<ExitStatus> {
+    let jar = patched_jar.display().to_string();
+    let mut parts = split_command(command)
+        .into_iter()
+        .map(|part| part.replace("{jar}", &jar));
+    let program = parts
+        .next()
+        .ok_or_else(|| anyhow!("Empty launch command, set {}", LAUNCH_COMMAND_ENV))?;
+
+    let mut child = Command::new(&program)
+        .args(parts)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow!(
+                "Bitwig not found at \"{}\", set {} to the launch command",
+                program,
+                LAUNCH_COMMAND_ENV
+            ),
+            _ => anyhow!("Failed to launch \"{}\": {}", program, err),
+        })?;
+
+    let (tx, rx) = mpsc::channel();
+    let forward = |stream: Box<dyn Read + Send>, tx: mpsc::Sender<String>| {
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    if let Some(stdout) = child.stdout.take() {
+        forward(Box::new(stdout), tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward(Box::new(stderr), tx.clone());
+    }
+    drop(tx);
+
+    for line in rx {
+        on_line(&line);
+    }
+
+    Ok(child.wait()?)
+}
+
+pub const JAVA_COMMAND_ENV: &str = "CUCUMBER_JAVA";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassVerdict {
+    Passed,
+    // VerifyError or ClassFormatError, with the JVM message
+    Failed(String),
+    // Class couldn't be checked, e.g. a dependency lives outside the JAR
+    Inconclusive(String),
+}
+
+// Asks the JVM to load and link each class from the JAR, which runs the
+// bytecode verifier on it. Classes don't need a main method: the launcher
+// links the class before looking for it. None when there is no JVM.
+pub fn verify_classes<'a>(
+    jar: &Path,
+    class_files: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<Option<Vec<(String, ClassVerdict)>>> {
+    let java = std::env::var(JAVA_COMMAND_ENV).unwrap_or_else(|_| "java".into());
+    let mut verdicts = Vec::new();
+    for class_file in class_files {
+        let class_name = class_file.trim_end_matches(".class").replace('/', ".");
+        let output = match Command::new(&java).arg("-cp").arg(jar).arg(&class_name).output() {
+            Ok(output) => output,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(anyhow!("Failed to run \"{}\": {}", java, err)),
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let error_line = |marker: &str| {
+            stderr
+                .lines()
+                .find(|line| line.contains(marker))
+                .map(|line| line.trim().to_string())
+        };
+        let verdict = if let Some(line) = error_line("VerifyError").or_else(|| error_line("ClassFormatError")) {
+            ClassVerdict::Failed(line)
+        } else if stderr.contains("main method") || stderr.contains("Main method") || output.status.success() {
+            ClassVerdict::Passed
+        } else {
+            let reason = stderr.lines().find(|line| !line.trim().is_empty()).unwrap_or("no output");
+            ClassVerdict::Inconclusive(reason.trim().to_string())
+        };
+        verdicts.push((class_file.to_string(), verdict));
+    }
+    Ok(Some(verdicts))
+}
@@ -1,6 +1,7 @@
-use std::{collections::HashMap, env, fmt::Debug, fs, io::Read, path::Path, time::Instant};
+use std::{collections::{BTreeMap, HashMap, HashSet}, env, fmt::Debug, fs, io::{Read, Write}, path::Path, time::Instant};
 
 use anyhow::anyhow;
+use sha2::{Digest, Sha256};
 
 use colorsys::{ColorTransform, Rgb, SaturationInSpace};
 // use indicatif::ProgressBar;
@@ -13,16 +14,27 @@ use krakatau2::{
             attrs::{AttrBody, Attribute},
             code::{Bytecode, Instr, Pos},
             cpool::{BStr, Const, ConstPool},
-            parse::Class,
+            parse::{Class, Field},
         },
         disassemble::refprinter::{ConstData, FmimTag, PrimTag, RefPrinter, SingleTag},
         parse_utf8, AssemblerOptions, DisassemblerOptions, ParserOptions,
     },
-    zip::{self, ZipArchive},
+    zip::{self, write::FileOptions, CompressionMethod, ZipArchive, ZipWriter},
 };
 
+pub mod apply;
+pub mod exchange;
+pub mod fixture;
+pub mod launch;
+pub mod log;
+pub mod output;
+pub mod preview;
+pub mod transform;
 pub mod types;
 
+use output::{PatchManifest, PatchedClass};
+use types::{AbsoluteColor, CucumberBitwigTheme, UiTarget};
+
 // Will search constant pool for that (inside Utf8 entry)
 // Contain most of the colors and methods to set them
 const PALETTE_ANCHOR: &str = "Device Tint Future";
@@ -104,8 +116,9 @@ fn main() -> anyhow::Result<()> {
             ),
             &mut general_goodies.named_colors,
             &general_goodies.palette_color_methods,
+            LineNumbers::Clear,
         )
-        .is_none()
+        .is_err()
         {
             println!("failed to replace in {}", file_name_w_ext);
         }
@@ -114,8 +127,8 @@ fn main() -> anyhow::Result<()> {
         patched_classes.insert(file_name_w_ext, new_buffer);
     }
 
-    {
-        let file_name_w_ext = general_goodies.timeline_color_ref.class_filename.clone();
+    if let Some(timeline_color_ref) = &mut general_goodies.timeline_color_ref {
+        let file_name_w_ext = timeline_color_ref.class_filename.clone();
         let mut file = zip.by_name(&file_name_w_ext)?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
@@ -134,7 +147,7 @@ fn main() -> anyhow::Result<()> {
             .constants
             .consts
             .choose(&mut rng).unwrap();
-        switch_timeline_color(&mut class, &other_color.const_name, &mut general_goodies.timeline_color_ref);
+        switch_timeline_color(&mut class, other_color, timeline_color_ref);
         let new_buffer = reasm(&file_name_w_ext, &class)?;
         patched_classes.insert(file_name_w_ext, new_buffer);
     }
@@ -165,7 +178,470 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// Keeps the first (class, color) pair, order stays the same
+fn once_per_class<T>(replacements: Vec<(String, String, T)>) -> Vec<(String, String, T)> {
+    let mut seen = HashSet::new();
+    replacements
+        .into_iter()
+        .filter(|(class_name, color_name, _)| seen.insert((class_name.clone(), color_name.clone())))
+        .collect()
+}
+
+fn read_zip_entry<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut file = zip.by_name(name)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+// Patches every color of the theme which differs from the one found in the JAR
+// and writes the result into output_jar. Returned manifest describes patched classes.
+// All classes are patched in memory first, so a failure leaves neither the
+// output JAR nor the goodies half-updated.
+pub fn write_theme_to_jar<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    goodies: &mut GeneralGoodies,
+    theme: &CucumberBitwigTheme,
+    output_jar: &Path,
+    options: &WriteOptions,
+) -> anyhow::Result<PatchManifest> {
+    const PARSER_OPTIONS: ParserOptions = ParserOptions {
+        no_short_code_attr: true,
+    };
+
+    // Updated along with the classes, replaces goodies once the JAR is written
+    let mut general_goodies = goodies.clone();
+
+    let known_colors = general_goodies.known_colors();
+
+    // Adjusted colors stay references to raw constants, their resolved
+    // absolute values aren't patched separately
+    let adjustments_to_replace = general_goodies
+        .named_colors
+        .iter()
+        .filter_map(|color| {
+            let adjustment = theme.adjusted_colors.get(&color.color_name)?;
+            if let ColorComponents::RefAndAdjust(base, h, s, v) = &color.components {
+                let current = (base.as_str(), *h, *s, *v);
+                let wanted = (
+                    adjustment.base.as_str(),
+                    adjustment.hue,
+                    adjustment.saturation,
+                    adjustment.value,
+                );
+                if current == wanted {
+                    return None;
+                }
+            }
+            Some((color.class_name.clone(), color.color_name.clone(), adjustment.clone()))
+        })
+        .collect::<Vec<_>>();
+    let adjustments_to_replace = once_per_class(adjustments_to_replace);
+
+    let colors_to_replace = general_goodies
+        .named_colors
+        .iter()
+        .filter(|color| {
+            !adjustments_to_replace
+                .iter()
+                .any(|(_, name, _)| name == &color.color_name)
+        })
+        .filter_map(|color| {
+            let types::NamedColor::Absolute(AbsoluteColor { r, g, b, a }) =
+                theme.named_colors.get(&color.color_name)?
+            else {
+                return None;
+            };
+            // Colors without alpha get one when promoted to Rgbai
+            let a = match theme.promotion_compositing {
+                types::PromotionCompositing::Absolute if !color.components.has_alpha() => 255,
+                _ => *a,
+            };
+            let current_rgb = color.components.to_rgb(&known_colors);
+            let current_a = color.components.alpha().unwrap_or(255);
+            let flattened = theme.flattened_colors.contains(&color.color_name)
+                && !matches!(color.components, ColorComponents::Rgbai(..));
+            if current_rgb == (*r, *g, *b) && current_a == a && !flattened {
+                return None;
+            }
+            Some((
+                color.class_name.clone(),
+                color.color_name.clone(),
+                ColorComponents::Rgbai(*r, *g, *b, a),
+            ))
+        })
+        .collect::<Vec<_>>();
+    // A color may be defined in several classes, each of them is patched
+    // separately but only once, as that covers all definitions in the class
+    let colors_to_replace = once_per_class(colors_to_replace);
+
+    let mut patched_classes = HashMap::new();
+    // Class file name -> names of colors changed in it
+    let mut changes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (class_name, color_name, new_value) in colors_to_replace {
+        let file_name_w_ext = format!("{}.class", class_name);
+        let buffer = match patched_classes.remove(&file_name_w_ext) {
+            Some(patched) => patched,
+            None => read_zip_entry(zip, &file_name_w_ext)?,
+        };
+
+        let mut class = classfile::parse(&buffer, PARSER_OPTIONS)
+            .map_err(|err| anyhow!("Parse {}: {:?}", file_name_w_ext, err))?;
+
+        // Method ref for rgba_i, if the class doesn't reference it yet
+        ensure_cp_room(&class, 6)?;
+        replace_named_color(
+            &mut class,
+            &color_name,
+            new_value,
+            &mut general_goodies.named_colors,
+            &general_goodies.palette_color_methods,
+            options.line_numbers,
+        )
+        .map_err(|err| anyhow!("Failed to replace {} in {}: {}", color_name, file_name_w_ext, err))?;
+
+        let new_buffer = reasm(&file_name_w_ext, &class)
+            .map_err(|err| anyhow!("Failed to reassemble {} after changing {}: {}", file_name_w_ext, color_name, err))?;
+        changes.entry(file_name_w_ext.clone()).or_default().push(color_name);
+        patched_classes.insert(file_name_w_ext, new_buffer);
+    }
+
+    for (class_name, color_name, adjustment) in adjustments_to_replace {
+        // Constants are obfuscated names, a theme of another build may refer
+        // to ones this JAR doesn't have
+        let Some(base) = general_goodies
+            .raw_colors
+            .constants
+            .consts
+            .iter()
+            .find(|raw| raw.const_name == adjustment.base)
+        else {
+            log::log_message(&format!(
+                "No raw color constant {} in this JAR, {} skipped",
+                adjustment.base, color_name
+            ));
+            continue;
+        };
+
+        let file_name_w_ext = format!("{}.class", class_name);
+        let buffer = match patched_classes.remove(&file_name_w_ext) {
+            Some(patched) => patched,
+            None => read_zip_entry(zip, &file_name_w_ext)?,
+        };
+
+        let mut class = classfile::parse(&buffer, PARSER_OPTIONS)
+            .map_err(|err| anyhow!("Parse {}: {:?}", file_name_w_ext, err))?;
+
+        replace_color_reference(
+            &mut class,
+            &color_name,
+            base,
+            &general_goodies.raw_colors.methods.rgba_f.class,
+            (adjustment.hue, adjustment.saturation, adjustment.value),
+            &mut general_goodies.named_colors,
+            &general_goodies.palette_color_methods,
+            options.line_numbers,
+        )
+        .map_err(|err| anyhow!("Failed to replace {} in {}: {}", color_name, file_name_w_ext, err))?;
+
+        let new_buffer = reasm(&file_name_w_ext, &class)
+            .map_err(|err| anyhow!("Failed to reassemble {} after changing {}: {}", file_name_w_ext, color_name, err))?;
+        changes.entry(file_name_w_ext.clone()).or_default().push(color_name);
+        patched_classes.insert(file_name_w_ext, new_buffer);
+    }
+
+    // Only constants this JAR has and whose values differ are written
+    for (const_name, color) in &theme.raw_colors {
+        let Some(raw_const) = general_goodies
+            .raw_colors
+            .constants
+            .consts
+            .iter_mut()
+            .find(|raw| &raw.const_name == const_name)
+        else {
+            log::log_message(&format!("No raw color constant {} in this JAR, skipped", const_name));
+            continue;
+        };
+        // Edited float values are written as is, byte values only when those
+        // weren't touched, so untouched floats keep their precision
+        let precise = theme
+            .raw_color_floats
+            .get(const_name)
+            .filter(|floats| Some(**floats) != raw_const.color_comps.unit_rgba());
+        let comps = match precise {
+            Some(floats) => *floats,
+            None => {
+                let (r, g, b) = raw_const.color_comps.to_rgb(&known_colors);
+                let comps = (color.r, color.g, color.b, color.a);
+                if (r, g, b, raw_const.color_comps.alpha().unwrap_or(255)) == comps {
+                    continue;
+                }
+                [color.r, color.g, color.b, color.a].map(byte_to_unit)
+            }
+        };
+
+        let file_name_w_ext = format!("{}.class", raw_const.class_name);
+        let buffer = match patched_classes.remove(&file_name_w_ext) {
+            Some(patched) => patched,
+            None => read_zip_entry(zip, &file_name_w_ext)?,
+        };
+
+        let mut class = classfile::parse(&buffer, PARSER_OPTIONS)
+            .map_err(|err| anyhow!("Parse {}: {:?}", file_name_w_ext, err))?;
+        // Four doubles at most, two slots each
+        ensure_cp_room(&class, 8)?;
+        raw_const.color_comps = replace_raw_color_const(
+            &mut class,
+            const_name,
+            comps,
+            &general_goodies.raw_colors.methods,
+        )
+        .ok_or_else(|| anyhow!("Failed to replace {} in {}", const_name, file_name_w_ext))?;
+
+        let new_buffer = reasm(&file_name_w_ext, &class)
+            .map_err(|err| anyhow!("Failed to reassemble {} after changing {}: {}", file_name_w_ext, const_name, err))?;
+        changes.entry(file_name_w_ext.clone()).or_default().push(const_name.clone());
+        patched_classes.insert(file_name_w_ext, new_buffer);
+    }
+
+    let playhead = theme.constant_refs.get(&UiTarget::Playhead);
+    if playhead.is_some() && general_goodies.timeline_color_ref.is_none() {
+        log::log_message("Playhead color can't be changed in this JAR, skipped");
+    }
+    if let (Some(cnst), Some(timeline_color_ref)) = (playhead, &mut general_goodies.timeline_color_ref) {
+        let wanted = cnst.to_comps();
+        let existing = general_goodies
+            .raw_colors
+            .constants
+            .consts
+            .iter()
+            .find(|raw| {
+                let (r, g, b) = raw.color_comps.to_rgb(&known_colors);
+                (r, g, b, raw.color_comps.alpha().unwrap_or(255)) == wanted
+            })
+            .cloned();
+        let raw_const = match existing {
+            Some(raw_const) => raw_const,
+            // No constant of this color yet, a new one is added to the color record class
+            None => {
+                let (r, g, b, a) = wanted;
+                let [r_f, g_f, b_f, a_f] = [r, g, b, a].map(|comp| byte_to_unit(comp) as f32);
+                let raw_const = RawColorConst {
+                    class_name: general_goodies.raw_colors.methods.rgba_f.class.clone(),
+                    const_name: format!("CUCUMBER_{:02X}{:02X}{:02X}{:02X}", r, g, b, a),
+                    color_comps: ColorComponents::Rgbaf(r_f, g_f, b_f, a_f),
+                };
+
+                let file_name_w_ext = format!("{}.class", raw_const.class_name);
+                let buffer = match patched_classes.remove(&file_name_w_ext) {
+                    Some(patched) => patched,
+                    None => read_zip_entry(zip, &file_name_w_ext)?,
+                };
+
+                let mut class = classfile::parse(&buffer, PARSER_OPTIONS)
+                    .map_err(|err| anyhow!("Parse {}: {:?}", file_name_w_ext, err))?;
+                // Field ref, constructor ref and four floats
+                ensure_cp_room(&class, 14)?;
+                add_raw_color_const(&mut class, &raw_const, &general_goodies.raw_colors.methods)
+                    .ok_or_else(|| anyhow!("Failed to add {} to {}", raw_const.const_name, file_name_w_ext))?;
+                let new_buffer = reasm(&file_name_w_ext, &class)
+                    .map_err(|err| anyhow!("Failed to reassemble {} after adding {}: {}", file_name_w_ext, raw_const.const_name, err))?;
+                drop(class);
+                changes.entry(file_name_w_ext.clone()).or_default().push(raw_const.const_name.clone());
+                patched_classes.insert(file_name_w_ext, new_buffer);
+                general_goodies.raw_colors.constants.consts.push(raw_const.clone());
+                raw_const
+            }
+        };
+
+        if raw_const.const_name != timeline_color_ref.const_name {
+            let file_name_w_ext = timeline_color_ref.class_filename.clone();
+            let buffer = match patched_classes.remove(&file_name_w_ext) {
+                Some(patched) => patched,
+                None => read_zip_entry(zip, &file_name_w_ext)?,
+            };
+
+            let mut class = classfile::parse(&buffer, PARSER_OPTIONS)
+                .map_err(|err| anyhow!("Parse {}: {:?}", file_name_w_ext, err))?;
+            ensure_cp_room(&class, 4)?;
+            switch_timeline_color(&mut class, &raw_const, timeline_color_ref)
+                .ok_or_else(|| anyhow!("Failed to switch playhead color in {}", file_name_w_ext))?;
+            let new_buffer = reasm(&file_name_w_ext, &class)
+                .map_err(|err| anyhow!("Failed to reassemble {} after changing Playhead: {}", file_name_w_ext, err))?;
+            changes.entry(file_name_w_ext.clone()).or_default().push("Playhead".into());
+            patched_classes.insert(file_name_w_ext, new_buffer);
+        }
+    }
+
+    // Written under a temporary name, so a failed write doesn't leave a
+    // broken JAR where the output is expected
+    let partial_jar = output_jar.with_extension("jar.partial");
+    let written = write_patched_jar(zip, patched_classes, changes, &partial_jar, options).and_then(|manifest| {
+        fs::rename(&partial_jar, output_jar)?;
+        Ok(manifest)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&partial_jar);
+    }
+    let manifest = written?;
+
+    *goodies = general_goodies;
+    Ok(manifest)
+}
+
+// Copies the JAR with patched classes swapped in
+fn write_patched_jar<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    mut patched_classes: HashMap<String, Vec<u8>>,
+    mut changes: BTreeMap<String, Vec<String>>,
+    output_jar: &Path,
+    options: &WriteOptions,
+) -> anyhow::Result<PatchManifest> {
+    let mut writer = ZipWriter::new(fs::File::create(output_jar)?);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let patched_options = if options.store_patched_classes {
+        FileOptions::default().compression_method(CompressionMethod::Stored)
+    } else {
+        deflated
+    };
+    let mut manifest = PatchManifest::default();
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let name = file.name().to_owned();
+        // Written anew below
+        if name == output::PATCH_MARKER_ENTRY {
+            continue;
+        }
+
+        let (buffer, file_options) = match patched_classes.remove(&name) {
+            Some(patched) => {
+                manifest.classes.insert(
+                    name.clone(),
+                    PatchedClass {
+                        sha256: format!("{:x}", Sha256::digest(&patched)),
+                        colors: changes.remove(&name).unwrap_or_default(),
+                    },
+                );
+                (patched, patched_options)
+            }
+            None => {
+                let mut buffer = Vec::new();
+                file.read_to_end(&mut buffer)?;
+                (buffer, deflated)
+            }
+        };
+
+        writer.start_file(name, file_options)?;
+        writer.write_all(&buffer)?;
+    }
+
+    writer.start_file(output::PATCH_MARKER_ENTRY, deflated)?;
+    writer.write_all(output::PatchMarker::current().to_text().as_bytes())?;
+    writer.finish()?;
+
+    Ok(manifest)
+}
+
+#[derive(Debug, Clone)]
+pub struct ListingLine {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+// Disassembles method which defines the named color, lines from the color
+// name up to the palette method call are highlighted
+pub fn disassemble_color_method<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    class_name: &str,
+    method_idx: usize,
+    color_name: &str,
+) -> anyhow::Result<Vec<ListingLine>> {
+    let file_name_w_ext = format!("{}.class", class_name);
+    let buffer = read_zip_entry(zip, &file_name_w_ext)?;
+    let class = classfile::parse(
+        &buffer,
+        ParserOptions {
+            no_short_code_attr: true,
+        },
+    )
+    .map_err(|err| anyhow!("Parse: {:?}", err))?;
+
+    let mut out = Vec::new();
+    krakatau2::lib::disassemble::disassemble(
+        &mut out,
+        &class,
+        DisassemblerOptions { roundtrip: false },
+    )?;
+    let source = String::from_utf8(out)?;
+
+    let mut lines = Vec::new();
+    let mut seen_methods = 0;
+    let mut inside = false;
+    let mut highlighting = false;
+    let quoted_name = format!("\"{}\"", color_name);
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with(".method") {
+            inside = seen_methods == method_idx;
+            seen_methods += 1;
+        }
+        if !inside {
+            continue;
+        }
+        if trimmed.contains(&quoted_name) {
+            highlighting = true;
+        }
+        lines.push(ListingLine {
+            text: line.to_string(),
+            highlighted: highlighting,
+        });
+        if highlighting && trimmed.contains("invokevirtual") {
+            highlighting = false;
+        }
+        if trimmed.starts_with(".end method") {
+            break;
+        }
+    }
+
+    if lines.is_empty() {
+        return Err(anyhow!("No method #{} in {}", method_idx, file_name_w_ext));
+    }
+
+    Ok(lines)
+}
+
+// constant_pool_count is u16 and counts the unused zero slot too
+const MAX_CP_LEN: usize = u16::MAX as usize;
+
+// Checked before rewrites which append to the constant pool, indexes past
+// u16 would silently wrap when cast
+fn ensure_cp_room(class: &Class<'_>, new_entries: usize) -> anyhow::Result<()> {
+    let len = class.cp.0.len();
+    if len + new_entries <= MAX_CP_LEN {
+        return Ok(());
+    }
+    let class_name = class
+        .cp
+        .clsutf(class.this)
+        .and_then(parse_utf8)
+        .unwrap_or_else(|| "<unknown class>".into());
+    Err(anyhow!(
+        "Constant pool of {} is full: {} entries, {} more needed, limit is {}",
+        class_name,
+        len,
+        new_entries,
+        MAX_CP_LEN
+    ))
+}
+
 fn reasm(fname: &str, class: &Class<'_>) -> anyhow::Result<Vec<u8>> {
+    ensure_cp_room(class, 0)?;
     let mut out = Vec::new();
     krakatau2::lib::disassemble::disassemble(
         &mut out,
@@ -174,305 +650,1105 @@ fn reasm(fname: &str, class: &Class<'_>) -> anyhow::Result<Vec<u8>> {
     )?;
 
     let source = std::str::from_utf8(&out)?;
+    assemble_class(fname, source)
+}
+
+// Assembles the source of a single class, errors name the class file
+pub(crate) fn assemble_class(fname: &str, source: &str) -> anyhow::Result<Vec<u8>> {
     let mut assembled = assemble(source, AssemblerOptions {}).map_err(|err| {
         err.display(fname, source);
-        anyhow!("Asm: {:?}", err)
+        anyhow!("Asm {}: {:?}", fname, err)
     })?;
-    let (_name, data) = assembled.pop().unwrap();
+    let (_name, data) = assembled
+        .pop()
+        .ok_or_else(|| anyhow!("Nothing assembled for {}", fname))?;
 
     Ok(data)
 }
 
-fn find_method_by_sig(class: &Class<'_>, sig_start: &str) -> Option<(u16, MethodDescription)> {
+// Method references of the class constant pool, wherever they are called from.
+// color_rec_name makes ref_hsv_f (SRfff) recognizable, same as in extraction.
+fn constant_pool_methods(class: &Class<'_>, color_rec_name: Option<&str>) -> Vec<(u16, MethodDescription)> {
     let rp = init_refprinter(&class.cp, &class.attrs);
+    (0..class.cp.0.len())
+        .filter_map(|id| {
+            let id = u16::try_from(id).ok()?;
+            Some((id, find_method_description(&rp, id, color_rec_name)?))
+        })
+        .collect()
+}
 
-    let method = class.methods.iter().skip(1).next();
-    let method = method?;
-
-    let attr = method.attrs.first()?;
-    let classfile::attrs::AttrBody::Code((code_1, _code_2)) = &attr.body else {
+// Class and name of the field a Field constant refers to
+fn field_ref(class: &Class<'_>, field_idx: u16) -> Option<(String, String)> {
+    let Const::Field(class_idx, nat_idx) = class.cp.0.get(field_idx as usize)? else {
         return None;
     };
-    let bytecode = &code_1.bytecode;
-
-    for (_pos, ix) in &bytecode.0 {
-        if let Instr::Invokevirtual(method_id) = &ix {
-            let method_descr = find_method_description(&rp, *method_id, None)?;
-            if method_descr.signature.starts_with(sig_start) {
-                return Some((*method_id, method_descr));
-            }
-        }
-    }
-
-    None
+    let Const::NameAndType(name_idx, _) = class.cp.0.get(*nat_idx as usize)? else {
+        return None;
+    };
+    let field_class = class.cp.clsutf(*class_idx).and_then(parse_utf8)?;
+    let field_name = class.cp.utf8(*name_idx).and_then(parse_utf8)?;
+    Some((field_class, field_name))
 }
 
 fn switch_timeline_color<'a>(
     class: &mut Class<'a>,
-    new_const: &'a str,
+    new_const: &'a RawColorConst,
     timeline_color_ref: &mut TimelineColorReference,
 ) -> Option<()> {
+    // Constants may live in another raw color class than the current one
+    let (current_class, _) = field_ref(class, timeline_color_ref.fmim_idx)?;
+    let new_class_idx = if *current_class == *new_const.class_name {
+        None
+    } else {
+        let class_utf_idx = class.cp.0.len();
+        class.cp.0.push(Const::Utf8(BStr(new_const.class_name.as_bytes())));
+        let class_idx = class.cp.0.len();
+        class.cp.0.push(Const::Class(class_utf_idx as u16));
+        Some(class_idx as u16)
+    };
+
     let utf_data_idx = class.cp.0.len();
-    class.cp.0.push(Const::Utf8(BStr(new_const.as_bytes())));
+    class.cp.0.push(Const::Utf8(BStr(new_const.const_name.as_bytes())));
 
     let nat_idx = class.cp.0.len();
     class.cp.0.push(Const::NameAndType(utf_data_idx as u16, timeline_color_ref.field_type_cp_idx));
 
-    let Const::Field(_, old_nat_idx) = class.cp.0.get_mut(timeline_color_ref.fmim_idx as usize)? else {
-        panic!()
-    };
-    *old_nat_idx = nat_idx as u16;
+    let Const::Field(old_class_idx, old_nat_idx) = class.cp.0.get_mut(timeline_color_ref.fmim_idx as usize)? else {
+        return None;
+    };
+    *old_nat_idx = nat_idx as u16;
+    if let Some(new_class_idx) = new_class_idx {
+        *old_class_idx = new_class_idx;
+    }
+
+    timeline_color_ref.const_name = new_const.const_name.clone();
+    Some(())
+}
+
+// Components are read back with truncation, so aim a bit above the exact value
+fn byte_to_unit(comp: u8) -> f64 {
+    ((comp as f64 + 0.25) / 255.0).min(1.0)
+}
+
+// Adds new_const (Rgbaf) to the color record class as a public static final
+// field, built with the (FFFF) constructor at the end of the class initializer
+fn add_raw_color_const<'a>(
+    class: &mut Class<'a>,
+    new_const: &'a RawColorConst,
+    raw_color_methods: &'a RawColorMethods,
+) -> Option<()> {
+    let ColorComponents::Rgbaf(r, g, b, a) = new_const.color_comps else {
+        return None;
+    };
+    // Other constants of the class have the same type, so its descriptor is there
+    let field_type = format!("L{};", new_const.class_name);
+    let type_utf_idx = class
+        .cp
+        .0
+        .iter()
+        .position(|cnst| matches!(cnst, Const::Utf8(BStr(text)) if *text == field_type.as_bytes()))?;
+    let clinit_idx = class
+        .methods
+        .iter()
+        .position(|method| class.cp.utf8(method.name).and_then(parse_utf8).as_deref() == Some("<clinit>"))?;
+
+    let constructor_id = method_ref_id(class, &raw_color_methods.rgba_f, Some(new_const.class_name.as_str()));
+
+    let name_utf_idx = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(new_const.const_name.as_bytes())));
+    let nat_idx = class.cp.0.len();
+    class.cp.0.push(Const::NameAndType(name_utf_idx as u16, type_utf_idx as u16));
+    let field_idx = class.cp.0.len();
+    class.cp.0.push(Const::Field(class.this, nat_idx as u16));
+    let comp_ids = [r, g, b, a].map(|comp| {
+        let id = class.cp.0.len();
+        class.cp.0.push(Const::Float(comp.to_bits()));
+        id as u16
+    });
+
+    class.fields.push(Field {
+        // public static final
+        access: 0x0019,
+        name: name_utf_idx as u16,
+        desc: type_utf_idx as u16,
+        attrs: Vec::new(),
+    });
+
+    let this_class = class.this;
+    let method = class.methods.get_mut(clinit_idx)?;
+    let AttrBody::Code((code_1, _)) = &mut method.attrs.first_mut()?.body else {
+        return None;
+    };
+    // Object, its copy and four floats
+    if code_1.stack < 6 {
+        code_1.stack = 6;
+    }
+    let bytecode = &mut code_1.bytecode.0;
+    if !matches!(bytecode.last(), Some((_, Instr::Return))) {
+        return None;
+    }
+    // New code takes the place of the final return, so positions of the
+    // existing instructions stay the same
+    let (Pos(mut next_pos), _) = bytecode.pop()?;
+    let mut new_ixs = vec![Instr::New(this_class), Instr::Dup];
+    new_ixs.extend(comp_ids.map(Instr::LdcW));
+    new_ixs.extend([
+        Instr::Invokespecial(constructor_id),
+        Instr::Putstatic(field_idx as u16),
+        Instr::Return,
+    ]);
+    for ix in new_ixs {
+        bytecode.push((Pos(next_pos), ix));
+        next_pos += 1;
+    }
+
+    Some(())
+}
+
+// Re-emits arguments of the (FFFF)/(DDDD) constructor call which initializes
+// raw color constant in the class initializer. Returns new components.
+fn replace_raw_color_const(
+    class: &mut Class<'_>,
+    const_name: &str,
+    comps: [f64; 4],
+    raw_color_methods: &RawColorMethods,
+) -> Option<ColorComponents> {
+    let rp = init_refprinter(&class.cp, &class.attrs);
+    // Constructors are declared by the color record class itself
+    let color_rec_name = Some(raw_color_methods.rgba_f.class.as_str());
+
+    let mut found = None;
+    'methods: for (method_idx, method) in class.methods.iter().enumerate() {
+        if class.cp.utf8(method.name).and_then(parse_utf8).as_deref() != Some("<clinit>") {
+            continue;
+        }
+        let Some(attr) = method.attrs.first() else {
+            continue;
+        };
+        let AttrBody::Code((code_1, _)) = &attr.body else {
+            continue;
+        };
+        let bytecode = &code_1.bytecode;
+        for (idx, (_pos, ix)) in bytecode.0.iter().enumerate() {
+            let Instr::Invokespecial(method_id) = ix else {
+                continue;
+            };
+            let Some(desc) = find_method_description(&rp, *method_id, color_rec_name) else {
+                continue;
+            };
+            let Some(raw_color_meth) = raw_color_methods.all().into_iter().find(|meth| **meth == desc) else {
+                continue;
+            };
+            let Some((_, Instr::Putstatic(const_idx))) = bytecode.0.get(idx + 1) else {
+                continue;
+            };
+            if find_const_name(&rp, *const_idx).as_deref() == Some(const_name) {
+                found = Some((method_idx, idx, raw_color_meth.signature_kind.clone()?));
+                break 'methods;
+            }
+        }
+    }
+    drop(rp);
+
+    let (method_idx, invoke_idx, sig_kind) = found?;
+
+    let (new_ixs, new_value) = match sig_kind {
+        MethodSignatureKind::Ffff => {
+            let ixs = comps.map(|comp| {
+                let id = class.cp.0.len();
+                class.cp.0.push(Const::Float((comp as f32).to_bits()));
+                Instr::LdcW(id as u16)
+            });
+            let [r, g, b, a] = comps.map(|comp| comp as f32);
+            (ixs, ColorComponents::Rgbaf(r, g, b, a))
+        }
+        MethodSignatureKind::Dddd => {
+            let ixs = comps.map(|comp| {
+                let id = class.cp.0.len();
+                class.cp.0.push(Const::Double(comp.to_bits()));
+                // Doubles take two constant pool slots
+                class.cp.0.push(Const::Null);
+                Instr::Ldc2W(id as u16)
+            });
+            let [r, g, b, a] = comps;
+            (ixs, ColorComponents::Rgbad(r, g, b, a))
+        }
+        _ => return None,
+    };
+
+    let method = class.methods.get_mut(method_idx)?;
+    let AttrBody::Code((code_1, _)) = &mut method.attrs.first_mut()?.body else {
+        return None;
+    };
+    // Same amount of instructions, so positions and line numbers stay valid
+    let args = code_1.bytecode.0.get_mut(invoke_idx.checked_sub(4)?..invoke_idx)?;
+    for ((_pos, ix), new_ix) in args.iter_mut().zip(new_ixs) {
+        *ix = new_ix;
+    }
+
+    Some(new_value)
+}
+
+// What to do with LineNumberTable of a method after its bytecode was changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineNumbers {
+    #[default]
+    Clear,
+    // Keep entries of surviving instructions, moved to their new positions
+    Preserve,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    pub line_numbers: LineNumbers,
+    // Patched classes go into the JAR without compression, a larger file in
+    // exchange for less work when Bitwig loads them
+    pub store_patched_classes: bool,
+}
+
+fn replace_named_color<'a>(
+    class: &mut Class<'a>,
+    name: &str,
+    new_value: ColorComponents,
+    named_colors: &mut [NamedColor],
+    palette_color_meths: &'a PaletteColorMethods,
+    line_numbers: LineNumbers,
+) -> anyhow::Result<()> {
+    if !matches!(new_value, ColorComponents::Rgbai(..)) {
+        todo!("Only Rgbai supported for the moment");
+    }
+    let color_rec_name = palette_color_meths.color_record_class();
+    let rgbai_method_id = method_ref_id(class, &palette_color_meths.rgba_i, color_rec_name);
+
+    let new_ixs = || {
+        let mut new_ixs = new_value.to_ixs();
+        // Now invoke correct method instead of old
+        new_ixs.push(Instr::Invokevirtual(rgbai_method_id));
+        new_ixs
+    };
+
+    rewrite_definitions(
+        class,
+        name,
+        named_colors,
+        palette_color_meths,
+        new_ixs,
+        new_value.clone(),
+        line_numbers,
+    )
+}
+
+// Rewrites every definition of the color in the class: all methods defining
+// it and all definitions within each of them. Fails unless each definition
+// known from the scan is rewritten, those are updated to new_value.
+fn rewrite_definitions(
+    class: &mut Class<'_>,
+    name: &str,
+    named_colors: &mut [NamedColor],
+    palette_color_meths: &PaletteColorMethods,
+    new_ixs: impl Fn() -> Vec<Instr>,
+    new_value: ColorComponents,
+    line_numbers: LineNumbers,
+) -> anyhow::Result<()> {
+    let class_name = class
+        .cp
+        .clsutf(class.this)
+        .and_then(parse_utf8)
+        .ok_or_else(|| anyhow!("Class without name"))?;
+    let mut definitions_by_method: BTreeMap<usize, Vec<&mut NamedColor>> = BTreeMap::new();
+    let definitions = named_colors
+        .iter_mut()
+        .filter(|color| color.color_name == name && color.class_name == class_name);
+    for named_color in definitions {
+        definitions_by_method.entry(named_color.method_idx).or_default().push(named_color);
+    }
+    if definitions_by_method.is_empty() {
+        return Err(anyhow!("Unknown color {}", name));
+    }
+
+    for (method_idx, definitions) in definitions_by_method {
+        // Definitions in one method may go through different palette methods
+        let old_descs = definitions
+            .iter()
+            .map(|named_color| {
+                palette_color_meths.from_components(&named_color.components).ok_or_else(|| {
+                    anyhow!(
+                        "Palette method for {} is missing in this JAR",
+                        named_color.components.variant_name()
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let rewritten = rewrite_color_call(
+            class,
+            method_idx,
+            name,
+            &old_descs,
+            palette_color_meths.color_record_class(),
+            &new_ixs,
+            line_numbers,
+        )
+        .ok_or_else(|| anyhow!("Failed to rewrite definitions of {}", name))?;
+        if rewritten != definitions.len() {
+            return Err(anyhow!(
+                "Found {} of {} definitions of {} in method #{}",
+                rewritten,
+                definitions.len(),
+                name,
+                method_idx
+            ));
+        }
+
+        for named_color in definitions {
+            named_color.components = new_value.clone();
+        }
+    }
+
+    Ok(())
+}
+
+// Makes the color a HSV adjusted version of raw color constant (ref_hsv_f call).
+// base_class is the class of raw color objects, i.e. type of the constant.
+fn replace_color_reference<'a>(
+    class: &mut Class<'a>,
+    name: &str,
+    base: &'a RawColorConst,
+    base_class: &str,
+    (h, s, v): (f32, f32, f32),
+    named_colors: &mut [NamedColor],
+    palette_color_meths: &'a PaletteColorMethods,
+    line_numbers: LineNumbers,
+) -> anyhow::Result<()> {
+    let ref_hsv_f = palette_color_meths
+        .ref_hsv_f
+        .as_ref()
+        .ok_or_else(|| anyhow!("No ref_hsv_f palette method in this JAR"))?;
+    // Field type is taken from the signature, so constant pool gets exactly
+    // the type ref_hsv_f expects (and the string lives as long as the class)
+    let base_type = format!("L{};", base_class);
+    let type_start = ref_hsv_f.signature.find(&base_type).ok_or_else(|| {
+        anyhow!(
+            "{} of type {} can't be passed to {}",
+            base.const_name,
+            base_type,
+            ref_hsv_f.signature
+        )
+    })?;
+    let base_type = &ref_hsv_f.signature[type_start..type_start + base_type.len()];
+
+    let class_name = class
+        .cp
+        .clsutf(class.this)
+        .and_then(parse_utf8)
+        .ok_or_else(|| anyhow!("Class without name"))?;
+    if !named_colors
+        .iter()
+        .any(|color| color.color_name == name && color.class_name == class_name)
+    {
+        return Err(anyhow!("Unknown color {}", name));
+    }
+
+    // Method ref, field ref and three floats
+    ensure_cp_room(class, 15)?;
+    let color_rec_name = palette_color_meths.color_record_class();
+    let ref_hsv_f_id = method_ref_id(class, ref_hsv_f, color_rec_name);
+
+    let class_utf_id = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(base.class_name.as_bytes())));
+    let class_id = class.cp.0.len();
+    class.cp.0.push(Const::Class(class_utf_id as u16));
+    let name_utf_id = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(base.const_name.as_bytes())));
+    let type_utf_id = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(base_type.as_bytes())));
+    let nat_id = class.cp.0.len();
+    class.cp.0.push(Const::NameAndType(name_utf_id as u16, type_utf_id as u16));
+    let field_id = class.cp.0.len();
+    class.cp.0.push(Const::Field(class_id as u16, nat_id as u16));
+    // Bitwig would load whatever the entries point at, so check they resolve
+    // to the chosen constant before emitting anything
+    let resolved = field_ref(class, field_id as u16);
+    if resolved.as_ref() != Some(&(base.class_name.clone(), base.const_name.clone())) {
+        return Err(anyhow!(
+            "Reference to {}.{} resolves to {:?}",
+            base.class_name,
+            base.const_name,
+            resolved
+        ));
+    }
+
+    let comp_ids = [h, s, v].map(|comp| {
+        let id = class.cp.0.len();
+        class.cp.0.push(Const::Float(comp.to_bits()));
+        id as u16
+    });
+    let new_ixs = || {
+        let mut new_ixs = vec![Instr::Getstatic(field_id as u16)];
+        new_ixs.extend(comp_ids.map(Instr::LdcW));
+        new_ixs.push(Instr::Invokevirtual(ref_hsv_f_id));
+        new_ixs
+    };
+
+    rewrite_definitions(
+        class,
+        name,
+        named_colors,
+        palette_color_meths,
+        new_ixs,
+        ColorComponents::RefAndAdjust(base.const_name.clone(), h, s, v),
+        line_numbers,
+    )
+}
+
+// Constant pool id of method reference, added if the class doesn't have it yet
+fn method_ref_id<'a>(class: &mut Class<'a>, desc: &'a MethodDescription, color_rec_name: Option<&str>) -> u16 {
+    // Whole reference has to match, signature alone may belong to another class
+    let existing = constant_pool_methods(class, color_rec_name).into_iter().find(|(_, known)| {
+        known.class == desc.class && known.method == desc.method && known.signature == desc.signature
+    });
+    if let Some((method_id, _)) = existing {
+        return method_id;
+    }
+
+    let class_utf_id = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(desc.class.as_bytes())));
+
+    let method_utf_id = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(desc.method.as_bytes())));
+
+    let sig_utf_id = class.cp.0.len();
+    class.cp.0.push(Const::Utf8(BStr(desc.signature.as_bytes())));
+
+    let class_id = class.cp.0.len();
+    class.cp.0.push(Const::Class(class_utf_id as u16));
+
+    let name_and_type_id = class.cp.0.len();
+    class
+        .cp
+        .0
+        .push(Const::NameAndType(method_utf_id as u16, sig_utf_id as u16));
 
-    timeline_color_ref.const_name = new_const.to_string();
-    Some(())
+    let method_id = class.cp.0.len();
+    class
+        .cp
+        .0
+        .push(Const::Method(class_id as u16, name_and_type_id as u16));
+
+    method_id as u16
 }
 
-fn replace_named_color<'a>(
-    class: &mut Class<'a>,
+// Replaces everything between the color name and the end of each call
+// defining it (through any of old_descs) with new_ixs, which should end with a
+// call too. Returns how many definitions were rewritten.
+fn rewrite_color_call(
+    class: &mut Class<'_>,
+    method_idx: usize,
     name: &str,
-    new_value: ColorComponents,
-    named_colors: &mut [NamedColor],
-    palette_color_meths: &'a PaletteColorMethods,
-) -> Option<()> {
-    if !matches!(new_value, ColorComponents::Rgbai(..)) {
-        todo!("Only Rgbai supported for the moment");
-    }
-    let named_color = named_colors
-        .iter_mut()
-        .find(|color| color.color_name == name)?;
-
-    let (rgbai_method_id, _rgbai_method_desc) =
-        match find_method_by_sig(class, &palette_color_meths.rgba_i.signature) {
-            Some(met) => met,
-            None => {
-                let rgbai_method_desc = &palette_color_meths.rgba_i;
-
-                let class_utf_id = class.cp.0.len();
-                class
-                    .cp
-                    .0
-                    .push(Const::Utf8(BStr(rgbai_method_desc.class.as_bytes())));
-
-                let method_utf_id = class.cp.0.len();
-                class
-                    .cp
-                    .0
-                    .push(Const::Utf8(BStr(rgbai_method_desc.method.as_bytes())));
-
-                let sig_utf_id = class.cp.0.len();
-                class
-                    .cp
-                    .0
-                    .push(Const::Utf8(BStr(rgbai_method_desc.signature.as_bytes())));
-
-                let class_id = class.cp.0.len();
-                class.cp.0.push(Const::Class(class_utf_id as u16));
-
-                let name_and_type_id = class.cp.0.len();
-                class
-                    .cp
-                    .0
-                    .push(Const::NameAndType(method_utf_id as u16, sig_utf_id as u16));
-
-                let method_id = class.cp.0.len();
-                class
-                    .cp
-                    .0
-                    .push(Const::Method(class_id as u16, name_and_type_id as u16));
-
-                (method_id as u16, rgbai_method_desc.clone())
-            }
-        };
-
+    old_descs: &[&MethodDescription],
+    color_rec_name: Option<&str>,
+    new_ixs: impl Fn() -> Vec<Instr>,
+    line_numbers: LineNumbers,
+) -> Option<usize> {
     let rp = init_refprinter(&class.cp, &class.attrs);
 
-    let old_desc = palette_color_meths.from_components(&named_color.components);
-
-    let method = class.methods.get_mut(named_color.method_idx)?;
+    let method = class.methods.get_mut(method_idx)?;
 
     let attr = method.attrs.first_mut()?;
     let classfile::attrs::AttrBody::Code((code_1, _code_2)) = &mut attr.body else {
         return None;
     };
+
+    // (name index, call index) of every definition, located the same way as
+    // by the scanner, so a later use of the name is never taken for one
+    let bytecode = &code_1.bytecode;
+    let definitions = bytecode
+        .0
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, (_, ix))| {
+            let Instr::Invokevirtual(method_id) = ix else {
+                return None;
+            };
+            // Looked up the same way as in extraction, so ref_hsv_f calls match too
+            let desc = find_method_description(&rp, *method_id, color_rec_name)?;
+            let old_desc = old_descs.iter().find(|old_desc| ***old_desc == desc)?;
+            let offset = old_desc.signature_kind.as_ref()?.color_name_ix_offset(idx, bytecode);
+            let name_idx = idx.checked_sub(offset)?;
+            let name_id = match bytecode.0.get(name_idx)?.1 {
+                Instr::Ldc(id) => id as u16,
+                Instr::LdcW(id) => id,
+                _ => return None,
+            };
+            (find_utf_ldc(&rp, name_id).as_deref() == Some(name)).then_some((name_idx, idx))
+        })
+        .collect::<Vec<_>>();
+    if definitions.is_empty() {
+        return Some(0);
+    }
+
     if code_1.stack < 7 {
         code_1.stack = 7;
     }
-    let bytecode = &mut code_1.bytecode;
-    let mut old_bytecode = bytecode.0.drain(..);
-    let mut new_bytecode: Vec<(Pos, Instr)> = vec![];
+    let old_bytecode = std::mem::take(&mut code_1.bytecode.0);
+    let mut new_bytecode: Vec<(Pos, Instr)> = Vec::with_capacity(old_bytecode.len());
     let mut pos_gen = 0..;
-
-    let mut ready = false;
-
-    while let Some((_, ix)) = old_bytecode.next() {
-        new_bytecode.push((Pos(pos_gen.next()?), ix));
-        if ready {
-            continue;
-        }
-
-        if let Instr::Ldc(id) = new_bytecode.last()?.1 {
-            let Some(text) = find_utf_ldc(&rp, id as u16) else {
-                continue;
-            };
-            if text == name {
-                loop {
-                    let ix = old_bytecode.next().unwrap();
-                    if let Instr::Invokevirtual(method_id) = ix.1 {
-                        let desc = find_method_description(&rp, method_id, None).unwrap();
-                        if desc.signature == old_desc.signature {
-                            break;
-                        }
+    // Old instruction position -> new one, to fix up line numbers afterwards
+    let mut pos_map = HashMap::new();
+
+    let mut pending = definitions.iter().peekable();
+    // Position of the replacement while instructions it replaces are skipped
+    let mut replacement_pos = None;
+    for (idx, (old_pos, ix)) in old_bytecode.into_iter().enumerate() {
+        match pending.peek() {
+            Some(&&(name_idx, call_idx)) if name_idx < idx && idx <= call_idx => {
+                if replacement_pos.is_none() {
+                    for new_ix in new_ixs() {
+                        let new_pos = pos_gen.next()?;
+                        replacement_pos.get_or_insert(new_pos);
+                        new_bytecode.push((Pos(new_pos), new_ix));
                     }
                 }
-                let ixs_to_push = new_value.to_ixs();
-                for ix in ixs_to_push {
-                    new_bytecode.push((Pos(pos_gen.next()?), ix));
+                pos_map.insert(old_pos.0, replacement_pos?);
+                if idx == call_idx {
+                    pending.next();
+                    replacement_pos = None;
                 }
-
-                // Now invoke correct method instead of old
-                new_bytecode.push((Pos(pos_gen.next()?), Instr::Invokevirtual(rgbai_method_id)));
-                named_color.components = new_value.clone();
-                ready = true;
+            }
+            _ => {
+                let new_pos = pos_gen.next()?;
+                pos_map.insert(old_pos.0, new_pos);
+                new_bytecode.push((Pos(new_pos), ix));
             }
         }
     }
-    drop(old_bytecode);
 
-    bytecode.0 = new_bytecode;
+    code_1.bytecode.0 = new_bytecode;
 
     for attr in &mut code_1.attrs {
         let classfile::attrs::AttrBody::LineNumberTable(table) = &mut attr.body else {
             continue;
         };
-        table.clear();
+        match line_numbers {
+            LineNumbers::Clear => table.clear(),
+            LineNumbers::Preserve => {
+                table.retain_mut(|(pos, _line)| match pos_map.get(&pos.0) {
+                    Some(new_pos) => {
+                        *pos = Pos(*new_pos);
+                        true
+                    }
+                    None => false,
+                });
+            }
+        }
     }
 
-    Some(())
+    Some(definitions.len())
+}
+
+// How many files to process between progress reports by default
+pub const DEFAULT_PROGRESS_REPORT_EVERY: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    SearchingUsefulClasses,
+    ScanningNamedColors,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::SearchingUsefulClasses => write!(f, "Searching useful classes"),
+            Stage::ScanningNamedColors => write!(f, "Scanning named colors"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub stage: Stage,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn percentage(&self) -> f32 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.done as f32 / self.total as f32 * 100.0
+    }
+
+    // Seconds left assuming the rest goes at the same pace, None until
+    // something is done or when the stage is already finished
+    pub fn remaining_secs(&self, elapsed_secs: f64) -> Option<f64> {
+        if self.done == 0 || self.done >= self.total {
+            return None;
+        }
+        Some(elapsed_secs / self.done as f64 * (self.total - self.done) as f64)
+    }
 }
 
 pub fn extract_general_goodies<R: std::io::Read + std::io::Seek>(
     zip: &mut ZipArchive<R>,
 ) -> anyhow::Result<GeneralGoodies> {
+    extract_general_goodies_with_progress(zip, |_| {})
+}
+
+pub fn extract_general_goodies_with_progress<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    on_progress: impl FnMut(Progress),
+) -> anyhow::Result<GeneralGoodies> {
+    extract_general_goodies_with_progress_every(zip, DEFAULT_PROGRESS_REPORT_EVERY, on_progress)
+}
+
+// Progress is reported every `report_every` files and once more when each stage is done
+pub fn extract_general_goodies_with_progress_every<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    report_every: usize,
+    on_progress: impl FnMut(Progress),
+) -> anyhow::Result<GeneralGoodies> {
+    let options = ExtractOptions {
+        report_every,
+        ..Default::default()
+    };
+    extract_general_goodies_with_options(zip, &options, on_progress)
+}
+
+// Constants by which useful classes are recognized. Can be overridden when
+// a new Bitwig version breaks detection and the new values are known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorSet {
+    pub palette: String,
+    pub init: String,
+    pub raw_color: f64,
+}
+
+impl Default for AnchorSet {
+    fn default() -> Self {
+        AnchorSet {
+            palette: PALETTE_ANCHOR.into(),
+            init: INIT_ANCHOR.into(),
+            raw_color: RAW_COLOR_ANCHOR,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub report_every: usize,
+    // Record classes which failed to parse instead of silently skipping them
+    pub strict: bool,
+    pub anchors: AnchorSet,
+    // Read class files only, each into a buffer of its own size. Otherwise one
+    // buffer is reused, which ends up as big as the largest entry of the JAR.
+    pub low_memory: bool,
+    // File name of the palette class to use when several match the palette
+    // anchor, otherwise the last one found is used
+    pub palette_class: Option<String>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            report_every: DEFAULT_PROGRESS_REPORT_EVERY,
+            strict: false,
+            anchors: AnchorSet::default(),
+            low_memory: false,
+            palette_class: None,
+        }
+    }
+}
+
+fn read_entry(file: &mut impl Read, size: u64, data: &mut Vec<u8>, low_memory: bool) -> std::io::Result<()> {
+    if low_memory {
+        // Previous buffer is dropped here instead of being reused
+        *data = Vec::with_capacity(size as usize);
+    } else {
+        data.clear();
+    }
+    file.read_to_end(data)?;
+    Ok(())
+}
+
+pub fn extract_general_goodies_with_options<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    options: &ExtractOptions,
+    mut on_progress: impl FnMut(Progress),
+) -> anyhow::Result<GeneralGoodies> {
+    let mut scan = JarScan::new(zip, options.clone());
+    while let Some(progress) = scan.step(zip)? {
+        on_progress(progress);
+    }
+    scan.finish()
+}
+
+// Scan of a JAR done in steps of `report_every` entries, so callers which
+// can't block for the whole scan (the web editor) get control back in
+// between. Each step returns progress, same as reported by
+// extract_general_goodies_with_options, until the scan is over.
+pub struct JarScan {
+    options: ExtractOptions,
+    file_names: Vec<String>,
+    // Stage and entry index the next step reports, None once the scan is over
+    next: Option<(Stage, usize)>,
+    // Reported by the last step, not scanned yet
+    reported: Option<(Stage, usize)>,
+    data: Vec<u8>,
+
+    // Every class matching the palette anchor, one is picked after the first pass
+    palette_candidates: Vec<(String, PaletteColorMethods)>,
+    // Constructors and contents of every raw color class, constants are read
+    // after the first pass as they may be built with constructors of another class
+    raw_color_constructors: (Option<MethodDescription>, Option<MethodDescription>),
+    raw_color_classes: Vec<Vec<u8>>,
+    timeline_color_ref: Option<TimelineColorReference>,
+    init_class_name: Option<String>,
+    anchors: BTreeMap<String, Vec<String>>,
+    parse_failures: Vec<types::ParseFailure>,
+
+    // Known once the first pass is done
+    raw_color_goodies: Option<RawColorGoodies>,
+    palette_class: Option<String>,
+    palette_color_meths: Option<PaletteColorMethods>,
+
+    all_named_colors: Vec<NamedColor>,
+    uneditable_colors: Vec<types::UneditableColor>,
+    // String constant -> how many times it is loaded, color names are
+    // looked up by their string both by getters and by other definitions
+    string_uses: HashMap<String, usize>,
+    known_colors: HashMap<String, ColorComponents>,
+}
+
+impl JarScan {
     const PARSER_OPTIONS: ParserOptions = ParserOptions {
         no_short_code_attr: true,
     };
 
-    let file_names = zip.file_names().map(Into::into).collect::<Vec<String>>();
+    pub fn new<R: std::io::Read + std::io::Seek>(zip: &ZipArchive<R>, options: ExtractOptions) -> Self {
+        JarScan {
+            options,
+            file_names: zip.file_names().map(Into::into).collect(),
+            next: Some((Stage::SearchingUsefulClasses, 0)),
+            reported: None,
+            data: Vec::new(),
+            palette_candidates: Vec::new(),
+            raw_color_constructors: (None, None),
+            raw_color_classes: Vec::new(),
+            timeline_color_ref: None,
+            init_class_name: None,
+            anchors: BTreeMap::new(),
+            parse_failures: Vec::new(),
+            raw_color_goodies: None,
+            palette_class: None,
+            palette_color_meths: None,
+            all_named_colors: Vec::new(),
+            uneditable_colors: Vec::new(),
+            string_uses: HashMap::new(),
+            known_colors: HashMap::new(),
+        }
+    }
 
-    let mut palette_color_meths = None;
-    let mut raw_color_goodies = None;
-    let mut timeline_color_ref = None;
+    // Scans the batch of entries reported by the previous step and reports
+    // the next one, which is scanned by the following step. Progress comes
+    // before the work it describes, the same as in a single pass, and once
+    // more when a stage is done. None when there's nothing left to scan.
+    // Has to get the same zip the scan was created for.
+    pub fn step<R: std::io::Read + std::io::Seek>(
+        &mut self,
+        zip: &mut ZipArchive<R>,
+    ) -> anyhow::Result<Option<Progress>> {
+        let total = self.file_names.len();
+        if let Some((stage, start)) = self.reported.take() {
+            if start == total {
+                self.next = match stage {
+                    Stage::SearchingUsefulClasses => {
+                        self.finish_searching();
+                        // Named colors can't be found without palette methods
+                        self.palette_color_meths
+                            .is_some()
+                            .then_some((Stage::ScanningNamedColors, 0))
+                    }
+                    Stage::ScanningNamedColors => None,
+                };
+            } else {
+                let end = (start + self.options.report_every.max(1)).min(total);
+                for file_idx in start..end {
+                    match stage {
+                        Stage::SearchingUsefulClasses => self.search_useful_class(zip, file_idx),
+                        Stage::ScanningNamedColors => self.scan_named_colors(zip, file_idx),
+                    }
+                }
+                self.next = Some((stage, end));
+            }
+        }
 
-    let mut data = Vec::new();
+        let Some((stage, done)) = self.next.take() else {
+            return Ok(None);
+        };
+        self.reported = Some((stage, done));
+        Ok(Some(Progress { stage, done, total }))
+    }
 
-    // let progress_bar = ProgressBar::new(file_names.len() as u64);
-    let mut init_class_name = None;
-    for file_name in &file_names {
-        let mut file = zip.by_name(file_name).unwrap();
+    // Reads the entry into data, None if it's skipped without reading
+    fn read_file<R: std::io::Read + std::io::Seek>(
+        &mut self,
+        zip: &mut ZipArchive<R>,
+        file_idx: usize,
+    ) -> Option<anyhow::Result<()>> {
+        let file_name = &self.file_names[file_idx];
+        if self.options.low_memory && !file_name.ends_with(".class") {
+            return None;
+        }
+        let low_memory = self.options.low_memory;
+        let data = &mut self.data;
+        Some(zip.by_name(file_name).map_err(anyhow::Error::from).and_then(|mut file| {
+            let size = file.size();
+            Ok(read_entry(&mut file, size, data, low_memory)?)
+        }))
+    }
 
-        data.clear();
-        file.read_to_end(&mut data)?;
+    fn search_useful_class<R: std::io::Read + std::io::Seek>(&mut self, zip: &mut ZipArchive<R>, file_idx: usize) {
+        let Some(read) = self.read_file(zip, file_idx) else {
+            return;
+        };
+        let file_name = &self.file_names[file_idx];
+        if let Err(err) = read {
+            // Damaged entry only takes colors defined in it along
+            log::log_message(&format!("Skipping unreadable {}: {}", file_name, err));
+            self.parse_failures.push(types::ParseFailure {
+                file_name: file_name.clone(),
+                error: format!("Read: {}", err),
+            });
+            return;
+        }
 
-        let Ok(class) = classfile::parse(&data, PARSER_OPTIONS) else {
-            continue;
+        let class = match classfile::parse(&self.data, Self::PARSER_OPTIONS) {
+            Ok(class) => class,
+            // Only class files are expected to parse, the rest are resources
+            Err(err) if self.options.strict && file_name.ends_with(".class") => {
+                self.parse_failures.push(types::ParseFailure {
+                    file_name: file_name.clone(),
+                    error: format!("{:?}", err),
+                });
+                return;
+            }
+            Err(_) => return,
         };
 
-        if let Some(useful_file_type) = is_useful_file(&class) {
-            match useful_file_type {
-                UsefulFileType::MainPalette => {
-                    println!("Found main palette: {}", file_name);
-                    if let Some(methods) = extract_palette_color_methods(&class) {
-                        // println!("{:#?}", methods);
-                        palette_color_meths = Some(methods);
-                    }
+        let Some(useful_file_type) = is_useful_file(&class, &self.options.anchors) else {
+            return;
+        };
+        self.anchors
+            .entry(useful_file_type.anchor_name().into())
+            .or_default()
+            .push(file_name.clone());
+        match useful_file_type {
+            UsefulFileType::MainPalette => {
+                log::log_message(&format!("Found main palette: {}", file_name));
+                if let Some(methods) = extract_palette_color_methods(&class, &self.options.anchors.palette) {
+                    self.palette_candidates.push((file_name.clone(), methods));
                 }
-                UsefulFileType::Init => {
-                    println!("Found init: {}", file_name);
-                    init_class_name = Some(file_name.clone());
+            }
+            UsefulFileType::Init => {
+                log::log_message(&format!("Found init: {}", file_name));
+                self.init_class_name = Some(file_name.clone());
+            }
+            UsefulFileType::RawColor => {
+                log::log_message(&format!("Found raw color: {}", file_name));
+                let (rgba_f, rgba_d) = extract_raw_color_methods(&class);
+                let (known_rgba_f, known_rgba_d) = &mut self.raw_color_constructors;
+                if known_rgba_f.is_none() {
+                    *known_rgba_f = rgba_f;
                 }
-                UsefulFileType::RawColor => {
-                    println!("Found raw color: {}", file_name);
-                    if let Some(goodies) = extract_raw_color_goodies(&class) {
-                        raw_color_goodies = Some(goodies);
-                    }
+                if known_rgba_d.is_none() {
+                    *known_rgba_d = rgba_d;
                 }
-                UsefulFileType::TimelineColorCnst {
+                self.raw_color_classes.push(self.data.clone());
+            }
+            UsefulFileType::TimelineColorCnst {
+                field_type_cp_idx,
+                fmim_idx: class_cp_idx,
+                cnst_name,
+            } => {
+                log::log_message(&format!("Found timeline color const: {}", file_name));
+                self.timeline_color_ref = Some(TimelineColorReference {
+                    class_filename: file_name.clone(),
+                    const_name: cnst_name,
                     field_type_cp_idx,
                     fmim_idx: class_cp_idx,
-                    cnst_name,
-                } => {
-                    println!("Found timeline color const: {}", file_name);
-                    timeline_color_ref = Some(TimelineColorReference {
-                        class_filename: file_name.clone(),
-                        const_name: cnst_name,
-                        field_type_cp_idx,
-                        fmim_idx: class_cp_idx,
-                    });
-                }
+                });
             }
         }
-        // progress_bar.inc(1);
-        drop(file);
     }
-    // progress_bar.finish();
-    println!("------------");
 
-    let mut all_named_colors = Vec::new();
+    // Raw color constants and the palette, once every class was seen
+    fn finish_searching(&mut self) {
+        self.raw_color_goodies = match std::mem::take(&mut self.raw_color_constructors) {
+            (Some(rgba_f), Some(rgba_d)) => {
+                let methods = RawColorMethods { rgba_f, rgba_d };
+                let mut constants = RawColorConstants { consts: Vec::new() };
+                for data in &self.raw_color_classes {
+                    let Ok(class) = classfile::parse(data, Self::PARSER_OPTIONS) else {
+                        continue;
+                    };
+                    constants.add_unique(extract_raw_color_consts(&class, &methods));
+                }
+                Some(RawColorGoodies { methods, constants })
+            }
+            _ => None,
+        };
 
-    let mut known_colors = HashMap::new();
+        let palette_candidates = &mut self.palette_candidates;
+        let wanted_palette = self.options.palette_class.as_ref().and_then(|wanted| {
+            let position = palette_candidates.iter().position(|(file_name, _)| file_name == wanted);
+            if position.is_none() {
+                log::log_message(&format!("Palette class {} not found, using the last one", wanted));
+            }
+            position
+        });
+        let palette_idx = wanted_palette.or(palette_candidates.len().checked_sub(1));
+        if palette_candidates.len() > 1 {
+            let names = palette_candidates.iter().map(|(file_name, _)| file_name.as_str()).collect::<Vec<_>>();
+            log::log_message(&format!("Several palette classes found: {}", names.join(", ")));
+        }
+        if let Some(idx) = palette_idx {
+            let (file_name, methods) = palette_candidates.swap_remove(idx);
+            log::log_message(&format!("Using palette: {}", file_name));
+            self.palette_class = Some(file_name);
+            self.palette_color_meths = Some(methods);
+        }
+        log::log_message("------------");
+
+        // Raw constants go first, so references to them can be resolved
+        self.known_colors = self
+            .raw_color_goodies
+            .as_ref()
+            .map(raw_color_consts_by_name)
+            .unwrap_or_default();
+    }
 
-    if let Some(palette_color_meths) = &palette_color_meths {
-        for file_name in &file_names {
-            let mut file = zip.by_name(&file_name).unwrap();
+    fn scan_named_colors<R: std::io::Read + std::io::Seek>(&mut self, zip: &mut ZipArchive<R>, file_idx: usize) {
+        // Unreadable entries were already reported by the first pass
+        let Some(Ok(())) = self.read_file(zip, file_idx) else {
+            return;
+        };
+        let Some(palette_color_meths) = &self.palette_color_meths else {
+            return;
+        };
+        let Ok(class) = classfile::parse(&self.data, Self::PARSER_OPTIONS) else {
+            return;
+        };
 
-            data.clear();
-            file.read_to_end(&mut data)?;
+        count_string_uses(&class, &mut self.string_uses);
 
-            let Ok(class) = classfile::parse(&data, PARSER_OPTIONS) else {
-                continue;
-            };
+        let found = scan_for_named_color_defs(
+            &class,
+            palette_color_meths,
+            &self.file_names[file_idx],
+            &mut self.known_colors,
+            &mut self.uneditable_colors,
+        );
+        self.all_named_colors.extend(found);
+    }
 
-            let found = scan_for_named_color_defs(
-                &class,
-                &palette_color_meths,
-                &file_name,
-                &mut known_colors,
-            );
-            all_named_colors.extend(found);
-            drop(file);
+    // Goodies found by the scan, fails unless all steps were done
+    pub fn finish(self) -> anyhow::Result<GeneralGoodies> {
+        if self.next.is_some() || self.reported.is_some() {
+            return Err(anyhow!("Scan isn't finished yet"));
+        }
+        let all_named_colors = self.all_named_colors;
+
+        for (name, definitions) in duplicate_definitions(&all_named_colors) {
+            let sites = definitions
+                .iter()
+                .map(|color| format!("{}#{}", color.class_name, color.method_idx))
+                .collect::<Vec<_>>();
+            log::log_message(&format!(
+                "Color {} is defined {} times, all of them are edited: {}",
+                name,
+                definitions.len(),
+                sites.join(", ")
+            ));
         }
-    }
 
-    for named_color in &all_named_colors {
-        debug_print_color(
-            &named_color.class_name,
-            &named_color.color_name,
-            &named_color.components,
-            &known_colors,
-        );
-    }
+        // Loading the name for the definition itself isn't a use
+        let mut color_uses = BTreeMap::new();
+        for named_color in &all_named_colors {
+            let uses = color_uses
+                .entry(named_color.color_name.clone())
+                .or_insert_with(|| self.string_uses.get(&named_color.color_name).copied().unwrap_or(0));
+            *uses = uses.saturating_sub(1);
+        }
+
+        if self.timeline_color_ref.is_none() {
+            log::log_message("Timeline color const not found, playhead color can't be changed for this version");
+        }
 
-    if let Some(raw_color_goodies) = &raw_color_goodies {
-        for cnst in &raw_color_goodies.constants.consts {
+        for named_color in &all_named_colors {
             debug_print_color(
-                &cnst.class_name,
-                &cnst.const_name,
-                &cnst.color_comps,
-                &known_colors,
+                &named_color.class_name,
+                &named_color.color_name,
+                &named_color.components,
+                &self.known_colors,
             );
         }
+
+        if let Some(raw_color_goodies) = &self.raw_color_goodies {
+            for cnst in &raw_color_goodies.constants.consts {
+                debug_print_color(
+                    &cnst.class_name,
+                    &cnst.const_name,
+                    &cnst.color_comps,
+                    &self.known_colors,
+                );
+            }
+        }
+
+        let init_class = self.init_class_name.ok_or_else(|| {
+            anyhow!("No class matched the {} anchor", UsefulFileType::Init.anchor_name())
+        })?;
+        let palette_color_methods = self.palette_color_meths.ok_or_else(|| {
+            anyhow!("No class matched the {} anchor", UsefulFileType::MainPalette.anchor_name())
+        })?;
+        let raw_colors = self.raw_color_goodies.ok_or_else(|| {
+            anyhow!("No class matched the {} anchor", UsefulFileType::RawColor.anchor_name())
+        })?;
+
+        Ok(GeneralGoodies {
+            init_class,
+            named_colors: all_named_colors,
+            palette_color_methods,
+            raw_colors,
+            timeline_color_ref: self.timeline_color_ref,
+            anchors: self.anchors,
+            parse_failures: self.parse_failures,
+            color_uses,
+            uneditable_colors: self.uneditable_colors,
+            palette_class: self.palette_class,
+        })
     }
+}
 
-    Ok(GeneralGoodies {
-        init_class: init_class_name.unwrap(),
-        named_colors: all_named_colors,
-        palette_color_methods: palette_color_meths.unwrap(),
-        raw_colors: raw_color_goodies.unwrap(),
-        timeline_color_ref: timeline_color_ref.unwrap(),
-    })
+// How often each ColorComponents variant is used by named and raw colors,
+// helps to decide which variants are worth supporting in the editor
+pub fn variant_coverage(goodies: &GeneralGoodies) -> BTreeMap<&'static str, usize> {
+    let mut coverage = BTreeMap::new();
+    let named = goodies.named_colors.iter().map(|color| &color.components);
+    let raw = goodies
+        .raw_colors
+        .constants
+        .consts
+        .iter()
+        .map(|cnst| &cnst.color_comps);
+    for components in named.chain(raw) {
+        *coverage.entry(components.variant_name()).or_insert(0) += 1;
+    }
+    coverage
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TimelineColorReference {
     pub class_filename: String,
     pub const_name: String,
@@ -480,14 +1756,57 @@ pub struct TimelineColorReference {
     pub fmim_idx: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GeneralGoodies {
     #[allow(dead_code)]
     pub init_class: String,
     pub named_colors: Vec<NamedColor>,
     pub palette_color_methods: PaletteColorMethods,
     pub raw_colors: RawColorGoodies,
-    pub timeline_color_ref: TimelineColorReference,
+    // Only the playhead color depends on it, so it's fine to miss
+    pub timeline_color_ref: Option<TimelineColorReference>,
+    // Anchor -> class files which matched it
+    pub anchors: BTreeMap<String, Vec<String>>,
+    // Unreadable entries are always collected, parse failures only in strict mode
+    pub parse_failures: Vec<types::ParseFailure>,
+    // Color name -> how many times it's referenced outside of its definition
+    pub color_uses: BTreeMap<String, usize>,
+    // Definitions found by the scanner which can't be edited, with the reason
+    pub uneditable_colors: Vec<types::UneditableColor>,
+    // File name of the palette class in use, candidates are under the
+    // palette anchor in anchors
+    pub palette_class: Option<String>,
+}
+
+impl GeneralGoodies {
+    // Named colors and raw color constants, which named colors may refer to
+    pub fn known_colors(&self) -> HashMap<String, ColorComponents> {
+        let mut known_colors = raw_color_consts_by_name(&self.raw_colors);
+        for color in &self.named_colors {
+            known_colors.insert(color.color_name.clone(), color.components.clone());
+        }
+        known_colors
+    }
+}
+
+// Color names defined more than once (by other classes or methods), the editor
+// shows a single value for them
+pub fn duplicate_definitions(named_colors: &[NamedColor]) -> BTreeMap<&str, Vec<&NamedColor>> {
+    let mut definitions: BTreeMap<&str, Vec<&NamedColor>> = BTreeMap::new();
+    for color in named_colors {
+        definitions.entry(&color.color_name).or_default().push(color);
+    }
+    definitions.retain(|_, definitions| definitions.len() > 1);
+    definitions
+}
+
+fn raw_color_consts_by_name(raw_colors: &RawColorGoodies) -> HashMap<String, ColorComponents> {
+    raw_colors
+        .constants
+        .consts
+        .iter()
+        .map(|cnst| (cnst.const_name.clone(), cnst.color_comps.clone()))
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -550,26 +1869,29 @@ impl IxToInt for Instr {
 
 impl IxToFloat for Instr {
     fn to_float(&self, refprinter: &RefPrinter) -> f32 {
+        let constant = |ind: usize| {
+            let data = refprinter.cpool.get(ind).unwrap();
+            match &data.data {
+                ConstData::Prim(_prim_tag, text) => {
+                    match text.trim_end_matches("f").parse::<f32>() {
+                        Ok(val) => val,
+                        Err(err) => {
+                            panic!("err parse f32 [{}]: {}", text, err);
+                        }
+                    }
+                }
+                _ => unimplemented!(),
+            }
+        };
         match self {
             Instr::Fconst0 => 0.0,
             Instr::Fconst1 => 1.0,
             Instr::Fconst2 => 2.0,
             Instr::Dconst0 => 0.0,
             Instr::Dconst1 => 1.0,
-            Instr::Ldc(ind) => {
-                let data = refprinter.cpool.get(*ind as usize).unwrap();
-                match &data.data {
-                    ConstData::Prim(_prim_tag, text) => {
-                        match text.trim_end_matches("f").parse::<f32>() {
-                            Ok(val) => val,
-                            Err(err) => {
-                                panic!("err parse f32 [{}]: {}", text, err);
-                            }
-                        }
-                    }
-                    _ => unimplemented!(),
-                }
-            }
+            Instr::Ldc(ind) => constant(*ind as usize),
+            // Patched classes load floats this way
+            Instr::LdcW(ind) => constant(*ind as usize),
             x => unimplemented!("instr: {:?}", x),
         }
     }
@@ -603,13 +1925,19 @@ impl IxToDouble for Instr {
 }
 
 impl MethodSignatureKind {
-    fn color_name_ix_offset(&self) -> usize {
+    // Distance from the call back to the color name
+    fn color_name_ix_offset(&self, idx: usize, bytecode: &Bytecode) -> usize {
         match self {
             MethodSignatureKind::Si => 2,
             MethodSignatureKind::Siii => 4,
             MethodSignatureKind::Siiii => 5,
             MethodSignatureKind::Sfff => 4,
-            MethodSignatureKind::SRfff => 6,
+            // ldc name; getstatic base; 3 floats; invokevirtual. Base read from
+            // a field of an object takes one more instruction (aload; getfield).
+            MethodSignatureKind::SRfff => match idx.checked_sub(4).and_then(|base_idx| bytecode.0.get(base_idx)) {
+                Some((_, Instr::Getfield(_))) => 6,
+                _ => 5,
+            },
             MethodSignatureKind::SSfff => 5,
             MethodSignatureKind::Ffff | MethodSignatureKind::Dddd => unreachable!(),
         }
@@ -620,7 +1948,7 @@ impl MethodSignatureKind {
         idx: usize,
         bytecode: &Bytecode,
         refprinter: &RefPrinter,
-    ) -> ColorComponents {
+    ) -> Option<ColorComponents> {
         let int = |offset: usize| bytecode.0.get(idx - offset).unwrap().1.to_int();
         let float = |offset: usize| bytecode.0.get(idx - offset).unwrap().1.to_float(refprinter);
         let double = |offset: usize| {
@@ -631,12 +1959,20 @@ impl MethodSignatureKind {
                 .1
                 .to_double(refprinter)
         };
-        match self {
+        Some(match self {
             MethodSignatureKind::Si => ColorComponents::Grayscale(int(1)),
             MethodSignatureKind::Siii => ColorComponents::Rgbi(int(3), int(2), int(1)),
             MethodSignatureKind::Siiii => ColorComponents::Rgbai(int(4), int(3), int(2), int(1)),
             MethodSignatureKind::Sfff => ColorComponents::Rgbf(float(3), float(2), float(1)),
-            MethodSignatureKind::SRfff => unimplemented!(),
+            MethodSignatureKind::SRfff => {
+                // Already constructed color is loaded from a field right before
+                // the floats, owner of a non-static one is loaded before that
+                let const_name = match &bytecode.0.get(idx.checked_sub(4)?)?.1 {
+                    Instr::Getstatic(id) | Instr::Getfield(id) => find_const_name(refprinter, *id)?,
+                    _ => return None,
+                };
+                ColorComponents::RefAndAdjust(const_name, float(3), float(2), float(1))
+            }
             MethodSignatureKind::SSfff => {
                 let ix = &bytecode.0.get(idx - 4).unwrap().1;
                 if let Instr::Ldc(ind) = ix {
@@ -644,13 +1980,10 @@ impl MethodSignatureKind {
                     let h = float(3);
                     let s = float(2);
                     let v = float(1);
-                    if let Some(color_name) = text {
-                        ColorComponents::StringAndAdjust(color_name, h, s, v)
-                    } else {
-                        unimplemented!("string ref without text?: {:?}", ix);
-                    }
+                    ColorComponents::StringAndAdjust(text?, h, s, v)
                 } else {
-                    unimplemented!("string ref with unexpected ix: {:?}", ix);
+                    // Base color name is only known at runtime
+                    return None;
                 }
             }
             MethodSignatureKind::Ffff => {
@@ -659,11 +1992,11 @@ impl MethodSignatureKind {
             MethodSignatureKind::Dddd => {
                 ColorComponents::Rgbad(double(4), double(3), double(2), double(1))
             }
-        }
+        })
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ColorComponents {
     Grayscale(u8),
     Rgbi(u8, u8, u8),
@@ -671,20 +2004,49 @@ pub enum ColorComponents {
     Rgbf(f32, f32, f32),
     Rgbaf(f32, f32, f32, f32),
     Rgbad(f64, f64, f64, f64),
-    #[allow(dead_code)]
     RefAndAdjust(String, f32, f32, f32),
     StringAndAdjust(String, f32, f32, f32),
 }
 
 impl ColorComponents {
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            ColorComponents::Grayscale(_) => "Grayscale",
+            ColorComponents::Rgbi(_, _, _) => "Rgbi",
+            ColorComponents::Rgbai(_, _, _, _) => "Rgbai",
+            ColorComponents::Rgbf(_, _, _) => "Rgbf",
+            ColorComponents::Rgbaf(_, _, _, _) => "Rgbaf",
+            ColorComponents::Rgbad(_, _, _, _) => "Rgbad",
+            ColorComponents::RefAndAdjust(_, _, _, _) => "RefAndAdjust",
+            ColorComponents::StringAndAdjust(_, _, _, _) => "StringAndAdjust",
+        }
+    }
+
+    // Variants defined with alpha, others are promoted to Rgbai when edited
+    pub fn has_alpha(&self) -> bool {
+        matches!(
+            self,
+            ColorComponents::Rgbai(..) | ColorComponents::Rgbaf(..) | ColorComponents::Rgbad(..)
+        )
+    }
+
+    // Exact components of colors defined with floats or doubles
+    pub fn unit_rgba(&self) -> Option<[f64; 4]> {
+        match self {
+            ColorComponents::Rgbaf(r, g, b, a) => Some([*r, *g, *b, *a].map(f64::from)),
+            ColorComponents::Rgbad(r, g, b, a) => Some([*r, *g, *b, *a]),
+            _ => None,
+        }
+    }
+
     pub fn alpha(&self) -> Option<u8> {
         Some(match self {
             ColorComponents::Grayscale(_) => 255,
             ColorComponents::Rgbi(_, _, _) => 255,
             ColorComponents::Rgbai(_, _, _, a) => *a,
             ColorComponents::Rgbf(_, _, _) => 255,
-            ColorComponents::Rgbaf(_, _, _, a) => (a * 255.0) as u8,
-            ColorComponents::Rgbad(_, _, _, a) => (a * 255.0) as u8,
+            ColorComponents::Rgbaf(_, _, _, a) => unit_to_byte(*a as f64),
+            ColorComponents::Rgbad(_, _, _, a) => unit_to_byte(*a),
             ColorComponents::RefAndAdjust(_, _, _, _) => return None,
             ColorComponents::StringAndAdjust(_, _, _, _) => return None,
         })
@@ -707,34 +2069,157 @@ impl ColorComponents {
         }
     }
 
+    // Translucent colors are drawn over something in Bitwig, so plain RGB
+    // doesn't tell how they actually look. Composite them if background is known.
+    pub fn to_rgb_on_background(
+        &self,
+        known_colors: &HashMap<String, ColorComponents>,
+        background: Option<(u8, u8, u8)>,
+    ) -> (u8, u8, u8) {
+        let rgb = self.to_rgb(known_colors);
+        match (background, self.alpha()) {
+            (Some(background), Some(a)) => blend_on_background(rgb, a, background),
+            _ => rgb,
+        }
+    }
+
     pub fn to_rgb(&self, known_colors: &HashMap<String, ColorComponents>) -> (u8, u8, u8) {
         match self {
             ColorComponents::Grayscale(v) => (*v, *v, *v),
             ColorComponents::Rgbi(r, g, b) => (*r, *g, *b),
             ColorComponents::Rgbai(r, g, b, _a) => (*r, *g, *b),
             ColorComponents::Rgbf(r, g, b) => {
-                ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+                (unit_to_byte(*r as f64), unit_to_byte(*g as f64), unit_to_byte(*b as f64))
             }
-            ColorComponents::RefAndAdjust(_, _, _, _) => todo!(),
-            ColorComponents::StringAndAdjust(ref_name, h, s, v) => {
+            ColorComponents::RefAndAdjust(ref_name, h, s, v)
+            | ColorComponents::StringAndAdjust(ref_name, h, s, v) => {
                 let Some(known) = known_colors.get(ref_name) else {
                     panic!("Unknown color ref: {}", ref_name);
                 };
-                let (r, g, b) = known.to_rgb(&known_colors);
-                let mut rgb = Rgb::from((r, g, b));
-                rgb.adjust_hue(*h as f64);
-                rgb.saturate(SaturationInSpace::Hsl(*s as f64 * 100.));
-                rgb.lighten(*v as f64 * 100.);
-                rgb.into()
+                adjust_hsv(known.to_rgb(known_colors), *h, *s, *v)
             }
             ColorComponents::Rgbaf(r, g, b, _a) => {
-                ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+                (unit_to_byte(*r as f64), unit_to_byte(*g as f64), unit_to_byte(*b as f64))
             }
             ColorComponents::Rgbad(r, g, b, _a) => {
-                ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+                (unit_to_byte(*r), unit_to_byte(*g), unit_to_byte(*b))
             }
         }
     }
+
+    // Float components outside of 0..1 get clamped when converted to bytes
+    pub fn is_in_range(&self) -> bool {
+        let unit = |comps: &[f64]| comps.iter().all(|comp| (0.0..=1.0).contains(comp));
+        match self {
+            ColorComponents::Rgbf(r, g, b) => unit(&[*r as f64, *g as f64, *b as f64]),
+            ColorComponents::Rgbaf(r, g, b, a) => unit(&[*r as f64, *g as f64, *b as f64, *a as f64]),
+            ColorComponents::Rgbad(r, g, b, a) => unit(&[*r, *g, *b, *a]),
+            _ => true,
+        }
+    }
+}
+
+// Same adjustment Bitwig applies to the base color in ref_hsv_f
+pub fn adjust_hsv(rgb: (u8, u8, u8), h: f32, s: f32, v: f32) -> (u8, u8, u8) {
+    let mut rgb = Rgb::from(rgb);
+    rgb.adjust_hue(h as f64);
+    rgb.saturate(SaturationInSpace::Hsl(s as f64 * 100.));
+    rgb.lighten(v as f64 * 100.);
+    rgb.into()
+}
+
+// Float component (0..1) to byte, out of range values are clamped instead of
+// being silently saturated by the cast
+pub(crate) fn unit_to_byte(value: f64) -> u8 {
+    debug_assert!(!value.is_nan(), "NaN color component");
+    (value * 255.0).clamp(0.0, 255.0) as u8
+}
+
+pub fn blend_on_background(rgb: (u8, u8, u8), alpha: u8, background: (u8, u8, u8)) -> (u8, u8, u8) {
+    let a = alpha as f32 / 255.0;
+    let blend = |fg: u8, bg: u8| (fg as f32 * a + bg as f32 * (1.0 - a)).round() as u8;
+    (
+        blend(rgb.0, background.0),
+        blend(rgb.1, background.1),
+        blend(rgb.2, background.2),
+    )
+}
+
+// WCAG relative luminance of sRGB color
+fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+// WCAG contrast, 1.0 (same) to 21.0 (black on white)
+pub fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorVisionDeficiency {
+    #[default]
+    None,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorVisionDeficiency {
+    pub const ALL: [ColorVisionDeficiency; 4] = [
+        ColorVisionDeficiency::None,
+        ColorVisionDeficiency::Protanopia,
+        ColorVisionDeficiency::Deuteranopia,
+        ColorVisionDeficiency::Tritanopia,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorVisionDeficiency::None => "None",
+            ColorVisionDeficiency::Protanopia => "Protanopia",
+            ColorVisionDeficiency::Deuteranopia => "Deuteranopia",
+            ColorVisionDeficiency::Tritanopia => "Tritanopia",
+        }
+    }
+}
+
+// How the color looks with the given deficiency, Machado et al. (2009)
+// matrices at full severity applied to linear RGB
+pub fn simulate_cvd(rgb: (u8, u8, u8), kind: ColorVisionDeficiency) -> (u8, u8, u8) {
+    let matrix = match kind {
+        ColorVisionDeficiency::None => return rgb,
+        ColorVisionDeficiency::Protanopia => [
+            [0.152286, 1.052583, -0.204868],
+            [0.114503, 0.786281, 0.099216],
+            [-0.003882, -0.048116, 1.051998],
+        ],
+        ColorVisionDeficiency::Deuteranopia => [
+            [0.367322, 0.860646, -0.227968],
+            [0.280085, 0.672501, 0.047413],
+            [-0.011820, 0.042940, 0.968881],
+        ],
+        ColorVisionDeficiency::Tritanopia => [
+            [1.255528, -0.076749, -0.178779],
+            [-0.078411, 0.930809, 0.147602],
+            [0.004733, 0.691367, 0.303900],
+        ],
+    };
+    let to_linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    let to_srgb = |c: f64| {
+        let c = c.clamp(0.0, 1.0);
+        let c = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+        (c * 255.0).round() as u8
+    };
+    let linear = [to_linear(rgb.0), to_linear(rgb.1), to_linear(rgb.2)];
+    let [r, g, b] = matrix.map(|row| row.iter().zip(linear).map(|(m, c)| m * c).sum::<f64>());
+    (to_srgb(r), to_srgb(g), to_srgb(b))
 }
 
 fn init_refprinter<'a>(cp: &ConstPool<'a>, attrs: &'a [Attribute<'a>]) -> RefPrinter<'a> {
@@ -831,6 +2316,26 @@ fn find_method_description(
     })
 }
 
+fn count_string_uses(class: &Class, string_uses: &mut HashMap<String, usize>) {
+    let rp = init_refprinter(&class.cp, &class.attrs);
+    for method in &class.methods {
+        let Some(AttrBody::Code((code_1, _))) = method.attrs.first().map(|attr| &attr.body) else {
+            continue;
+        };
+        for (_, ix) in &code_1.bytecode.0 {
+            let id = match ix {
+                Instr::Ldc(id) => *id as u16,
+                // Patched classes load names this way
+                Instr::LdcW(id) => *id,
+                _ => continue,
+            };
+            if let Some(text) = find_utf_ldc(&rp, id) {
+                *string_uses.entry(text).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
 fn find_utf_ldc(rp: &RefPrinter<'_>, id: u16) -> Option<String> {
     let const_line = rp.cpool.get(id as usize)?;
     let ConstData::Single(SingleTag::String, idx) = const_line.data else {
@@ -956,6 +2461,7 @@ fn scan_for_named_color_defs(
     palette_color_meths: &PaletteColorMethods,
     filename: &str,
     known_colors: &mut HashMap<String, ColorComponents>,
+    uneditable: &mut Vec<types::UneditableColor>,
 ) -> Vec<NamedColor> {
     let mut found = Vec::new();
     let rp = init_refprinter(&class.cp, &class.attrs);
@@ -963,6 +2469,7 @@ fn scan_for_named_color_defs(
     let class_name = class.cp.clsutf(class.this).and_then(parse_utf8).unwrap();
 
     let all_meths = palette_color_meths.all();
+    let color_rec_name = palette_color_meths.color_record_class();
 
     for (method_idx, method) in class.methods.iter().enumerate() {
         let Some(attr) = method.attrs.first() else {
@@ -978,26 +2485,60 @@ fn scan_for_named_color_defs(
             let Instr::Invokevirtual(method_id) = ix else {
                 continue;
             };
-            let Some(method_descr) = find_method_description(&rp, *method_id, None) else {
+            let Some(method_descr) = find_method_description(&rp, *method_id, color_rec_name) else {
                 continue;
             };
 
             for meth in &all_meths {
                 if method_descr == **meth {
                     if let Some(sig_kind) = &meth.signature_kind {
-                        let offset = sig_kind.color_name_ix_offset();
-                        let Some((_, ix)) = bytecode.0.get(idx - offset) else {
-                            println!("{}: offset out of bounds", filename);
+                        let offset = sig_kind.color_name_ix_offset(idx, bytecode);
+                        // Color method may be called right at the start of the method
+                        let Some((_, ix)) = idx.checked_sub(offset).and_then(|name_idx| bytecode.0.get(name_idx)) else {
+                            let method_name = class.cp.utf8(method.name).and_then(parse_utf8).unwrap_or_default();
+                            log::log_message(&format!(
+                                "{}: offset out of bounds in {}.{}",
+                                filename, class_name, method_name
+                            ));
                             continue;
                         };
+                        let mut skip = |name: Option<&String>, reason: String| {
+                            uneditable.push(types::UneditableColor {
+                                name: name.cloned(),
+                                class_name: class_name.clone(),
+                                method_name: class.cp.utf8(method.name).and_then(parse_utf8).unwrap_or_default(),
+                                reason,
+                            });
+                        };
                         match ix {
                             Instr::Ldc(id) => {
                                 let text = find_utf_ldc(&rp, *id as u16);
-                                let components =
-                                    sig_kind.extract_color_components(idx, bytecode, &rp);
+                                let Some(components) =
+                                    sig_kind.extract_color_components(idx, bytecode, &rp)
+                                else {
+                                    log::log_message(&format!("{}: unreadable components of {:?}", filename, text));
+                                    let reason = if matches!(sig_kind, MethodSignatureKind::SSfff) {
+                                        "Derived from a color chosen at runtime".to_string()
+                                    } else {
+                                        "Components aren't constants".to_string()
+                                    };
+                                    skip(text.as_ref(), reason);
+                                    continue;
+                                };
+                                // Only references to colors we know about are editable
+                                if let ColorComponents::RefAndAdjust(ref_name, ..) = &components {
+                                    if !known_colors.contains_key(ref_name) {
+                                        log::log_message(&format!("{}: unknown color reference {}", filename, ref_name));
+                                        skip(text.as_ref(), format!("Refers to unknown color constant {}", ref_name));
+                                        continue;
+                                    }
+                                }
 
                                 // If not in-place color name defined, then it's a method call inside other delegate method
                                 // so it's not interesting to us (I guess?).
+                                if text.is_none() {
+                                    skip(None, "Name isn't a string constant".into());
+                                }
                                 if let Some(color_name) = &text {
                                     found.push(NamedColor {
                                         class_name: class_name.clone(),
@@ -1009,7 +2550,8 @@ fn scan_for_named_color_defs(
                                 }
                             }
                             _other => {
-                                // println!("{}: {:?}", filename, other);
+                                // Delegate method which gets the name as an argument
+                                skip(None, "Name is passed at runtime".into());
                             }
                         }
                     } else {
@@ -1035,14 +2577,34 @@ fn debug_print_color(
 
     let comp_line = format!("{} {} {} {}", r, g, b, a);
 
-    let debug_line = if (r as u16 + g as u16 + b as u16) > 384 {
-        format!("{} {}", comp_line, color_name).black().on_truecolor(r, g, b)
-    } else {
-        format!("{} {}", comp_line, color_name).on_truecolor(r, g, b)
-    };
-    println!("{} ({})", debug_line, class_name);
+    match log::log_format() {
+        log::LogFormat::Json => log::log_event(
+            "color",
+            serde_json::json!({
+                "class": class_name,
+                "name": color_name,
+                "r": r,
+                "g": g,
+                "b": b,
+                "a": a,
+            }),
+            "",
+        ),
+        log::LogFormat::Plain => println!("{} {} ({})", comp_line, color_name, class_name),
+        log::LogFormat::Color => {
+            let debug_line = if (r as u16 + g as u16 + b as u16) > 384 {
+                format!("{} {}", comp_line, color_name).black().on_truecolor(r, g, b)
+            } else {
+                format!("{} {}", comp_line, color_name).on_truecolor(r, g, b)
+            };
+            println!("{} ({})", debug_line, class_name);
+        }
+    }
 }
 
+// Key of palette class candidates in GeneralGoodies::anchors
+pub const PALETTE_ANCHOR_NAME: &str = "Main palette";
+
 #[derive(Debug)]
 enum UsefulFileType {
     MainPalette,
@@ -1055,17 +2617,30 @@ enum UsefulFileType {
         cnst_name: String },
 }
 
-fn is_useful_file(class: &Class) -> Option<UsefulFileType> {
-    if let Some(mtch) = has_any_string_in_constant_pool(class, &[PALETTE_ANCHOR, INIT_ANCHOR]) {
-        let useful_file_type = match mtch {
-            PALETTE_ANCHOR => UsefulFileType::MainPalette,
-            INIT_ANCHOR => UsefulFileType::Init,
-            _ => return None,
+impl UsefulFileType {
+    fn anchor_name(&self) -> &'static str {
+        match self {
+            UsefulFileType::MainPalette => PALETTE_ANCHOR_NAME,
+            UsefulFileType::RawColor => "Raw color",
+            UsefulFileType::Init => "Init",
+            UsefulFileType::TimelineColorCnst { .. } => "Timeline color",
+        }
+    }
+}
+
+fn is_useful_file(class: &Class, anchors: &AnchorSet) -> Option<UsefulFileType> {
+    if let Some(mtch) = has_any_string_in_constant_pool(class, &[anchors.palette.as_str(), anchors.init.as_str()]) {
+        let useful_file_type = if mtch == anchors.palette {
+            UsefulFileType::MainPalette
+        } else if mtch == anchors.init {
+            UsefulFileType::Init
+        } else {
+            return None;
         };
         return Some(useful_file_type);
     }
 
-    if let Some(_) = has_any_double_in_constant_pool(class, &[RAW_COLOR_ANCHOR]) {
+    if let Some(_) = has_any_double_in_constant_pool(class, &[anchors.raw_color]) {
         return Some(UsefulFileType::RawColor);
     }
 
@@ -1081,15 +2656,14 @@ fn is_useful_file(class: &Class) -> Option<UsefulFileType> {
 }
 
 // Color methods and defined static colors (contain important black color)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawColorGoodies {
-    #[allow(dead_code)]
     pub methods: RawColorMethods,
     pub constants: RawColorConstants,
 }
 
 // Color methods and defined static colors (contain important black color)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawColorMethods {
     // rgb_i: MethodDescription,
     // grayscale_i: MethodDescription,
@@ -1105,58 +2679,90 @@ impl RawColorMethods {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawColorConstants {
     pub consts: Vec<RawColorConst>,
 }
 
-#[derive(Debug)]
+impl RawColorConstants {
+    // Bitwig may split raw color constants across several classes, the first
+    // constant of each name is kept
+    fn add_unique(&mut self, consts: Vec<RawColorConst>) {
+        for cnst in consts {
+            if !self.consts.iter().any(|known| known.const_name == cnst.const_name) {
+                self.consts.push(cnst);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RawColorConst {
     pub class_name: String,
     pub const_name: String,
     pub color_comps: ColorComponents,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PaletteColorMethods {
+    // Required: it gives away color record class
     pub grayscale_i: MethodDescription,
-    pub rgb_i: MethodDescription,
+    // Required: edited colors are re-emitted through it
     pub rgba_i: MethodDescription,
-    pub rgb_f: MethodDescription,
-    pub ref_hsv_f: MethodDescription,
-    pub name_hsv_f: MethodDescription,
+    // Others may be absent or merged in some builds, colors defined
+    // through missing ones are just not found
+    pub rgb_i: Option<MethodDescription>,
+    pub rgb_f: Option<MethodDescription>,
+    pub ref_hsv_f: Option<MethodDescription>,
+    pub name_hsv_f: Option<MethodDescription>,
 }
 
 impl PaletteColorMethods {
     fn all(&self) -> Vec<&MethodDescription> {
-        vec![
-            &self.grayscale_i,
-            &self.rgb_i,
-            &self.rgba_i,
-            &self.rgb_f,
-            &self.ref_hsv_f,
-            &self.name_hsv_f,
+        [
+            Some(&self.grayscale_i),
+            self.rgb_i.as_ref(),
+            Some(&self.rgba_i),
+            self.rgb_f.as_ref(),
+            self.ref_hsv_f.as_ref(),
+            self.name_hsv_f.as_ref(),
         ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    // Every lookup of palette calls passes it, otherwise ref_hsv_f calls
+    // aren't recognized as SRfff and don't match the extracted method
+    fn color_record_class(&self) -> Option<&str> {
+        color_record_class(&self.grayscale_i)
     }
 
-    fn from_components(&self, comps: &ColorComponents) -> &MethodDescription {
+    // None when the JAR has no method for these components. No other method is
+    // substituted: a wrong signature would swallow the wrong arguments.
+    fn from_components(&self, comps: &ColorComponents) -> Option<&MethodDescription> {
         match comps {
-            ColorComponents::Grayscale(_) => &self.grayscale_i,
-            ColorComponents::Rgbi(_, _, _) => &self.rgb_i,
-            ColorComponents::Rgbai(_, _, _, _) => &self.rgba_i,
-            ColorComponents::Rgbf(_, _, _) => &self.rgb_f,
-            ColorComponents::Rgbaf(_, _, _, _) => unreachable!(),
-            ColorComponents::Rgbad(_, _, _, _) => unreachable!(),
-            ColorComponents::RefAndAdjust(_, _, _, _) => &self.ref_hsv_f,
-            ColorComponents::StringAndAdjust(_, _, _, _) => &self.name_hsv_f,
+            ColorComponents::Grayscale(_) => Some(&self.grayscale_i),
+            ColorComponents::Rgbi(_, _, _) => self.rgb_i.as_ref(),
+            ColorComponents::Rgbai(_, _, _, _) => Some(&self.rgba_i),
+            ColorComponents::Rgbf(_, _, _) => self.rgb_f.as_ref(),
+            // Raw color constructors, not palette methods
+            ColorComponents::Rgbaf(_, _, _, _) => None,
+            ColorComponents::Rgbad(_, _, _, _) => None,
+            ColorComponents::RefAndAdjust(_, _, _, _) => self.ref_hsv_f.as_ref(),
+            ColorComponents::StringAndAdjust(_, _, _, _) => self.name_hsv_f.as_ref(),
         }
     }
 }
 
-fn extract_raw_color_goodies(class: &Class) -> Option<RawColorGoodies> {
+// (FFFF) and (DDDD) constructors declared by the class, a class holding only
+// constants built with constructors of another one has neither
+fn extract_raw_color_methods(class: &Class) -> (Option<MethodDescription>, Option<MethodDescription>) {
     let rp = init_refprinter(&class.cp, &class.attrs);
 
-    let class_name = class.cp.clsutf(class.this).and_then(parse_utf8)?;
+    let Some(class_name) = class.cp.clsutf(class.this).and_then(parse_utf8) else {
+        return (None, None);
+    };
 
     let mut rgbaf_desc = None;
     let mut rgbad_desc = None;
@@ -1208,10 +2814,18 @@ fn extract_raw_color_goodies(class: &Class) -> Option<RawColorGoodies> {
         }
     }
 
-    let raw_color_methods = RawColorMethods {
-        rgba_f: rgbaf_desc.unwrap(),
-        rgba_d: rgbad_desc.unwrap(),
+    (rgbaf_desc, rgbad_desc)
+}
+
+// Constants initialized in the class initializer with raw_color_methods
+fn extract_raw_color_consts(class: &Class, raw_color_methods: &RawColorMethods) -> Vec<RawColorConst> {
+    let rp = init_refprinter(&class.cp, &class.attrs);
+
+    let Some(class_name) = class.cp.clsutf(class.this).and_then(parse_utf8) else {
+        return Vec::new();
     };
+    // Constructors are declared by the color record class
+    let color_rec_name = Some(raw_color_methods.rgba_f.class.as_str());
 
     let mut consts = Vec::new();
 
@@ -1236,20 +2850,31 @@ fn extract_raw_color_goodies(class: &Class) -> Option<RawColorGoodies> {
         let bytecode = &code_1.bytecode;
         for (idx, (_pos, ix)) in (bytecode.0).iter().enumerate() {
             if let Instr::Invokespecial(method_id) = ix {
-                let Some(desc) = find_method_description(&rp, *method_id, None) else {
+                let Some(desc) = find_method_description(&rp, *method_id, color_rec_name) else {
                     continue;
                 };
                 for raw_color_meth in raw_color_methods.all() {
                     if &desc == raw_color_meth {
-                        let comps = raw_color_meth
+                        let Some(comps) = raw_color_meth
                             .signature_kind
                             .as_ref()
                             .unwrap()
-                            .extract_color_components(idx, bytecode, &rp);
-                        let Instr::Putstatic(const_idx) = bytecode.0.get(idx + 1).unwrap().1 else {
-                            panic!("Expected const name (Putstatic)");
+                            .extract_color_components(idx, bytecode, &rp)
+                        else {
+                            continue;
+                        };
+                        // Constructed color which isn't stored right away isn't a constant
+                        let Some((_, Instr::Putstatic(const_idx))) = bytecode.0.get(idx + 1) else {
+                            log::log_message(&format!(
+                                "{}: color constructed at #{} isn't stored to a static field, skipped",
+                                class_name, idx
+                            ));
+                            break;
+                        };
+                        let Some(const_name) = find_const_name(&rp, *const_idx) else {
+                            log::log_message(&format!("{}: unreadable field of color at #{}, skipped", class_name, idx));
+                            break;
                         };
-                        let const_name = find_const_name(&rp, const_idx).unwrap();
                         consts.push(RawColorConst {
                             class_name: class_name.clone(),
                             const_name: const_name.clone(),
@@ -1271,13 +2896,10 @@ fn extract_raw_color_goodies(class: &Class) -> Option<RawColorGoodies> {
         // Static init, should contain statics initialization
     }
 
-    Some(RawColorGoodies {
-        methods: raw_color_methods,
-        constants: RawColorConstants { consts },
-    })
+    consts
 }
 
-fn extract_palette_color_methods(class: &Class) -> Option<PaletteColorMethods> {
+fn extract_palette_color_methods(class: &Class, anchor: &str) -> Option<PaletteColorMethods> {
     // println!("Searching palette color methods");
 
     let rp = init_refprinter(&class.cp, &class.attrs);
@@ -1285,13 +2907,7 @@ fn extract_palette_color_methods(class: &Class) -> Option<PaletteColorMethods> {
     let _class_name = class.cp.clsutf(class.this).and_then(parse_utf8)?;
     // println!("Class >>>>> {}", class_name);
 
-    let main_palette_method = class.methods.iter().skip(1).next()?;
-    let attr = main_palette_method.attrs.first()?;
-    let AttrBody::Code((code_1, _)) = &attr.body else {
-        return None;
-    };
-
-    let bytecode = &code_1.bytecode;
+    let bytecode = main_palette_bytecode(class, &rp, anchor)?;
 
     let invokes = bytecode.0.iter().filter_map(|(_, ix)| match ix {
         Instr::Invokevirtual(method_id) => Some(method_id),
@@ -1310,34 +2926,156 @@ fn extract_palette_color_methods(class: &Class) -> Option<PaletteColorMethods> {
         })
     };
 
-    let grayscale_i = find_method("(Ljava/lang/String;I)", None)?;
-    let color_record_class_name = grayscale_i
-        .signature
-        .split_once("I)L")
-        .map(|(_, suffix)| suffix.strip_suffix(";"))
-        .flatten()?;
-    let rgb_i = find_method("(Ljava/lang/String;III)", Some(color_record_class_name))?;
-    let rgba_i = find_method("(Ljava/lang/String;IIII)", Some(color_record_class_name))?;
-    let rgb_f = find_method("(Ljava/lang/String;FFF)", Some(color_record_class_name))?;
-    let ref_hsv_f = find_method(
-        &format!("(Ljava/lang/String;L{};FFF)", color_record_class_name),
-        Some(color_record_class_name),
-    )?;
-    let name_hsv_f = find_method(
-        "(Ljava/lang/String;Ljava/lang/String;FFF)",
-        Some(color_record_class_name),
-    )?;
+    let grayscale_i = find_method(GRAYSCALE_I_SIGNATURE, None)?;
+    let color_record_class_name = color_record_class(&grayscale_i)?;
+    let [rgba_i, rgb_i, rgb_f, ref_hsv_f, name_hsv_f] = palette_role_signatures(color_record_class_name)
+        .map(|(_, signature)| find_method(&signature, Some(color_record_class_name)));
+    let rgba_i = rgba_i?;
+
+    for (role, meth) in [
+        ("rgb_i", &rgb_i),
+        ("rgb_f", &rgb_f),
+        ("ref_hsv_f", &ref_hsv_f),
+        ("name_hsv_f", &name_hsv_f),
+    ] {
+        if meth.is_none() {
+            log::log_message(&format!("Palette method {} not found, its colors will be skipped", role));
+        }
+    }
 
     Some(PaletteColorMethods {
         grayscale_i,
-        rgb_i,
         rgba_i,
+        rgb_i,
         rgb_f,
         ref_hsv_f,
         name_hsv_f,
     })
 }
 
+const GRAYSCALE_I_SIGNATURE: &str = "(Ljava/lang/String;I)";
+
+// grayscale_i returns the color record
+fn color_record_class(grayscale_i: &MethodDescription) -> Option<&str> {
+    grayscale_i
+        .signature
+        .split_once("I)L")
+        .and_then(|(_, suffix)| suffix.strip_suffix(';'))
+}
+
+// Signature starts of palette methods besides grayscale_i, by role
+fn palette_role_signatures(color_record_class_name: &str) -> [(&'static str, String); 5] {
+    [
+        ("rgba_i", "(Ljava/lang/String;IIII)".into()),
+        ("rgb_i", "(Ljava/lang/String;III)".into()),
+        ("rgb_f", "(Ljava/lang/String;FFF)".into()),
+        ("ref_hsv_f", format!("(Ljava/lang/String;L{};FFF)", color_record_class_name)),
+        ("name_hsv_f", "(Ljava/lang/String;Ljava/lang/String;FFF)".into()),
+    ]
+}
+
+// Palette method of one role as found by extraction (calls in the method
+// loading the anchor) and all methods of the class referencing a matching
+// signature. More than one candidate means the role is ambiguous.
+#[derive(Debug, Clone)]
+pub struct PaletteRoleProbe {
+    pub role: &'static str,
+    pub extracted: Option<MethodDescription>,
+    pub candidates: Vec<MethodDescription>,
+}
+
+impl PaletteRoleProbe {
+    pub fn agrees(&self) -> bool {
+        match &self.extracted {
+            Some(extracted) => self.candidates.len() == 1 && self.candidates[0] == *extracted,
+            None => self.candidates.is_empty(),
+        }
+    }
+}
+
+// Diagnostic for version drift: both ways of finding palette methods, side by side
+pub fn probe_palette_methods<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    general_goodies: &GeneralGoodies,
+) -> anyhow::Result<Vec<PaletteRoleProbe>> {
+    const PARSER_OPTIONS: ParserOptions = ParserOptions {
+        no_short_code_attr: true,
+    };
+
+    // The class the methods were taken from, or the one the scan would pick
+    let file_name = general_goodies
+        .palette_class
+        .as_ref()
+        .or_else(|| {
+            general_goodies
+                .anchors
+                .get(UsefulFileType::MainPalette.anchor_name())
+                .and_then(|file_names| file_names.last())
+        })
+        .ok_or_else(|| anyhow!("Main palette class is unknown"))?;
+    let buffer = read_zip_entry(zip, file_name)?;
+    let class = classfile::parse(&buffer, PARSER_OPTIONS).map_err(|err| anyhow!("Parse: {:?}", err))?;
+
+    let meths = &general_goodies.palette_color_methods;
+    let color_record_class_name = meths
+        .color_record_class()
+        .ok_or_else(|| anyhow!("Unexpected grayscale_i signature {}", meths.grayscale_i.signature))?;
+    let extracted = [
+        Some(&meths.rgba_i),
+        meths.rgb_i.as_ref(),
+        meths.rgb_f.as_ref(),
+        meths.ref_hsv_f.as_ref(),
+        meths.name_hsv_f.as_ref(),
+    ];
+    let roles = [("grayscale_i", GRAYSCALE_I_SIGNATURE.to_string())]
+        .into_iter()
+        .chain(palette_role_signatures(color_record_class_name));
+    let extracted = [Some(&meths.grayscale_i)].into_iter().chain(extracted);
+    let referenced = constant_pool_methods(&class, Some(color_record_class_name));
+
+    Ok(roles
+        .zip(extracted)
+        .map(|((role, signature), extracted)| PaletteRoleProbe {
+            role,
+            extracted: extracted.cloned(),
+            candidates: referenced
+                .iter()
+                .filter(|(_, desc)| desc.signature.starts_with(&signature))
+                .map(|(_, desc)| desc.clone())
+                .collect(),
+        })
+        .collect())
+}
+
+// Method which defines palette colors: the one loading the anchor string, so
+// it doesn't matter where it's placed in the class. Falls back to the second
+// method, where it used to be.
+fn main_palette_bytecode<'a>(class: &'a Class<'_>, rp: &RefPrinter<'_>, anchor: &str) -> Option<&'a Bytecode> {
+    let mut bytecodes = class.methods.iter().filter_map(|method| {
+        let attr = method.attrs.first()?;
+        let AttrBody::Code((code_1, _)) = &attr.body else {
+            return None;
+        };
+        Some(&code_1.bytecode)
+    });
+
+    let loads_anchor = |bytecode: &&Bytecode| {
+        bytecode.0.iter().any(|(_, ix)| match ix {
+            Instr::Ldc(id) => find_utf_ldc(rp, *id as u16).as_deref() == Some(anchor),
+            Instr::LdcW(id) => find_utf_ldc(rp, *id).as_deref() == Some(anchor),
+            _ => false,
+        })
+    };
+
+    bytecodes.find(loads_anchor).or_else(|| {
+        let method = class.methods.get(1)?;
+        let AttrBody::Code((code_1, _)) = &method.attrs.first()?.body else {
+            return None;
+        };
+        Some(&code_1.bytecode)
+    })
+}
+
 fn has_any_string_in_constant_pool<'a>(class: &Class, strings: &[&'a str]) -> Option<&'a str> {
     for entry in &class.cp.0 {
         if let classfile::cpool::Const::Utf8(txt) = entry {
@@ -1364,3 +3102,870 @@ fn has_any_double_in_constant_pool<'a>(class: &Class, doubles: &[f64]) -> Option
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::fixture;
+
+    fn scan(zip: &mut ZipArchive<Cursor<Vec<u8>>>) -> GeneralGoodies {
+        extract_general_goodies_with_options(zip, &ExtractOptions::default(), |_| {}).unwrap()
+    }
+
+    // Colors defined by the class, by name
+    fn colors_of(goodies: &GeneralGoodies, class_name: &str) -> BTreeMap<String, ColorComponents> {
+        goodies
+            .named_colors
+            .iter()
+            .filter(|color| color.class_name == class_name)
+            .map(|color| (color.color_name.clone(), color.components.clone()))
+            .collect()
+    }
+
+    fn raw_consts(goodies: &GeneralGoodies) -> BTreeMap<String, ColorComponents> {
+        goodies
+            .raw_colors
+            .constants
+            .consts
+            .iter()
+            .map(|cnst| (cnst.const_name.clone(), cnst.color_comps.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn scans_fixture() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let goodies = scan(&mut zip);
+
+        assert_eq!(goodies.init_class, format!("{}.class", fixture::INIT_CLASS));
+        assert_eq!(goodies.palette_class, Some(format!("{}.class", fixture::PALETTE_CLASS)));
+        let timeline_color_ref = goodies.timeline_color_ref.as_ref().unwrap();
+        assert_eq!(timeline_color_ref.class_filename, format!("{}.class", fixture::TIMELINE_CLASS));
+        assert_eq!(timeline_color_ref.const_name, "BLACK");
+
+        assert_eq!(
+            raw_consts(&goodies),
+            BTreeMap::from([
+                ("BLACK".to_string(), ColorComponents::Rgbaf(0.0, 0.0, 0.0, 1.0)),
+                ("WHITE".to_string(), ColorComponents::Rgbaf(1.0, 1.0, 1.0, 1.0)),
+                ("ODD".to_string(), ColorComponents::Rgbad(0.666333, 0.0, 0.0, 1.0)),
+                ("GRAY".to_string(), ColorComponents::Rgbaf(0.5, 0.5, 0.5, 1.0)),
+                ("DEEP".to_string(), ColorComponents::Rgbad(0.666333, 0.1, 0.2, 1.0)),
+            ])
+        );
+
+        // The same three floats are plain RGB for (String;FFF) and HSV deltas
+        // when the signature takes a base color first
+        assert_eq!(
+            colors_of(&goodies, fixture::PALETTE_CLASS),
+            BTreeMap::from([
+                ("Device Tint Future".to_string(), ColorComponents::Grayscale(100)),
+                ("Light Timeline Background".to_string(), ColorComponents::Rgbi(50, 52, 54)),
+                ("On".to_string(), ColorComponents::Rgbai(240, 160, 40, 255)),
+                ("Selection Fill".to_string(), ColorComponents::Rgbai(240, 160, 40, 254)),
+                ("Accent (default)".to_string(), ColorComponents::Rgbf(1.0, 0.5, 0.0)),
+                (
+                    "Accent Dimmed".to_string(),
+                    ColorComponents::StringAndAdjust("Accent (default)".into(), 0.0, -0.25, -0.25),
+                ),
+                ("Shadow".to_string(), ColorComponents::RefAndAdjust("BLACK".into(), 0.0, 0.0, 0.1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn palette_roles_resolve_to_distinct_kinds() {
+        use MethodSignatureKind::*;
+
+        let mut zip = fixture::fixture_zip().unwrap();
+        let goodies = scan(&mut zip);
+        let meths = &goodies.palette_color_methods;
+        let raw_meths = &goodies.raw_colors.methods;
+        let roles = [
+            Some(&meths.grayscale_i),
+            Some(&meths.rgba_i),
+            meths.rgb_i.as_ref(),
+            meths.rgb_f.as_ref(),
+            meths.ref_hsv_f.as_ref(),
+            meths.name_hsv_f.as_ref(),
+            Some(&raw_meths.rgba_f),
+            Some(&raw_meths.rgba_d),
+        ];
+        assert_eq!(
+            roles.map(|meth| meth.and_then(|meth| meth.signature_kind.clone())),
+            [Si, Siiii, Siii, Sfff, SRfff, SSfff, Ffff, Dddd].map(Some)
+        );
+
+        // Lookup over the whole constant pool finds the same single method per role
+        let probes = probe_palette_methods(&mut zip, &goodies).unwrap();
+        assert_eq!(probes.len(), 6);
+        for probe in probes {
+            assert!(probe.agrees(), "{:?}", probe);
+        }
+    }
+
+    #[test]
+    fn probes_the_chosen_palette() {
+        // Another palette right before the real one, with its own methods
+        let mut sources = fixture::fixture_sources();
+        let position = sources.iter().position(|(name, _)| *name == fixture::PALETTE_CLASS).unwrap();
+        let other = sources[position].1.replace(fixture::PALETTE_CLASS, "com/bitwig/OtherPalette");
+        sources.insert(position, ("com/bitwig/OtherPalette", other));
+        let mut zip = fixture::zip_entries(&fixture::assemble_classes(&sources).unwrap()).unwrap();
+
+        let options = ExtractOptions {
+            palette_class: Some(format!("{}.class", fixture::PALETTE_CLASS)),
+            ..Default::default()
+        };
+        let goodies = extract_general_goodies_with_options(&mut zip, &options, |_| {}).unwrap();
+        assert_eq!(goodies.anchors[PALETTE_ANCHOR_NAME].len(), 2);
+        for probe in probe_palette_methods(&mut zip, &goodies).unwrap() {
+            assert!(probe.agrees(), "{:?}", probe);
+        }
+    }
+
+    // Writes the theme over the JAR, returns the manifest and the written JAR
+    fn write_theme(
+        zip: &mut ZipArchive<Cursor<Vec<u8>>>,
+        goodies: &mut GeneralGoodies,
+        theme: &CucumberBitwigTheme,
+        options: &WriteOptions,
+    ) -> (PatchManifest, ZipArchive<Cursor<Vec<u8>>>) {
+        static WRITTEN: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let serial = WRITTEN.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let output_jar = env::temp_dir().join(format!("cucumber-test-{}-{}.jar", std::process::id(), serial));
+        let manifest = write_theme_to_jar(zip, goodies, theme, &output_jar, options).unwrap();
+        let written = fs::read(&output_jar).unwrap();
+        fs::remove_file(&output_jar).unwrap();
+        (manifest, ZipArchive::new(Cursor::new(written)).unwrap())
+    }
+
+    fn parse_entry<'a>(buffer: &'a [u8]) -> Class<'a> {
+        classfile::parse(buffer, ParserOptions { no_short_code_attr: true }).unwrap()
+    }
+
+    #[test]
+    fn reads_raw_colors_of_two_classes() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        let const_classes = goodies
+            .raw_colors
+            .constants
+            .consts
+            .iter()
+            .map(|cnst| (cnst.const_name.as_str(), cnst.class_name.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            const_classes,
+            BTreeMap::from([
+                ("BLACK", fixture::COLOR_CLASS),
+                ("WHITE", fixture::COLOR_CLASS),
+                ("ODD", fixture::COLOR_CLASS),
+                ("GRAY", fixture::MORE_COLORS_CLASS),
+                ("DEEP", fixture::MORE_COLORS_CLASS),
+            ])
+        );
+
+        // Playhead switched from BLACK of Color to GRAY of MoreColors
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        theme.constant_refs.insert(UiTarget::Playhead, types::ColorConst::Gray);
+        let (_, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        let rescanned = scan(&mut written);
+        let timeline_color_ref = rescanned.timeline_color_ref.unwrap();
+        assert_eq!(timeline_color_ref.const_name, "GRAY");
+        let buffer = read_zip_entry(&mut written, &timeline_color_ref.class_filename).unwrap();
+        assert_eq!(
+            field_ref(&parse_entry(&buffer), timeline_color_ref.fmim_idx),
+            Some((fixture::MORE_COLORS_CLASS.to_string(), "GRAY".to_string()))
+        );
+    }
+
+    #[test]
+    fn switches_playhead_to_existing_constant() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        assert_eq!(theme.constant_refs.get(&UiTarget::Playhead), Some(&types::ColorConst::Black));
+
+        theme.constant_refs.insert(UiTarget::Playhead, types::ColorConst::White);
+        let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        assert_eq!(
+            manifest.classes.keys().collect::<Vec<_>>(),
+            [&format!("{}.class", fixture::TIMELINE_CLASS)]
+        );
+        let rescanned = scan(&mut written);
+        assert_eq!(rescanned.timeline_color_ref.unwrap().const_name, "WHITE");
+    }
+
+    #[test]
+    fn adds_custom_playhead_constant() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        let custom = types::ColorConst::Custom(AbsoluteColor { r: 10, g: 200, b: 30, a: 255 });
+        theme.constant_refs.insert(UiTarget::Playhead, custom.clone());
+
+        let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        assert_eq!(
+            manifest.classes[&format!("{}.class", fixture::COLOR_CLASS)].colors,
+            ["CUCUMBER_0AC81EFF"]
+        );
+        assert!(manifest.classes.contains_key(&format!("{}.class", fixture::TIMELINE_CLASS)));
+
+        let rescanned = scan(&mut written);
+        assert_eq!(rescanned.timeline_color_ref.as_ref().unwrap().const_name, "CUCUMBER_0AC81EFF");
+        let added = rescanned
+            .raw_colors
+            .constants
+            .consts
+            .iter()
+            .find(|cnst| cnst.const_name == "CUCUMBER_0AC81EFF")
+            .unwrap();
+        assert_eq!(added.class_name, fixture::COLOR_CLASS);
+        assert_eq!(raw_consts(&rescanned).len(), raw_consts(&goodies).len());
+        assert_eq!(
+            CucumberBitwigTheme::from_goodies(&rescanned).constant_refs.get(&UiTarget::Playhead),
+            Some(&custom)
+        );
+    }
+
+    // Fields read with getstatic anywhere in the class
+    fn static_reads(class: &Class<'_>) -> Vec<(String, String)> {
+        class
+            .methods
+            .iter()
+            .filter_map(|method| match &method.attrs.first()?.body {
+                AttrBody::Code((code_1, _)) => Some(code_1),
+                _ => None,
+            })
+            .flat_map(|code_1| code_1.bytecode.0.iter())
+            .filter_map(|(_, ix)| match ix {
+                Instr::Getstatic(id) => field_ref(class, *id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_adjustments_are_not_patched() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        let theme = CucumberBitwigTheme::from_goodies(&goodies);
+        let (manifest, _) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        assert!(manifest.classes.is_empty(), "{:?}", manifest.classes.keys());
+    }
+
+    #[test]
+    fn adjusted_color_refers_to_chosen_base() {
+        let palette_file = format!("{}.class", fixture::PALETTE_CLASS);
+        for (base, base_class) in [("WHITE", fixture::COLOR_CLASS), ("GRAY", fixture::MORE_COLORS_CLASS)] {
+            let mut zip = fixture::fixture_zip().unwrap();
+            let mut goodies = scan(&mut zip);
+            let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+            let adjustment = types::ColorAdjustment {
+                base: base.into(),
+                hue: 0.1,
+                saturation: 0.2,
+                value: -0.3,
+            };
+            theme.adjusted_colors.insert("Shadow".into(), adjustment);
+
+            let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+            assert_eq!(manifest.classes[&palette_file].colors, ["Shadow"]);
+
+            // Field and NameAndType entries point at the chosen constant
+            let buffer = read_zip_entry(&mut written, &palette_file).unwrap();
+            assert_eq!(
+                static_reads(&parse_entry(&buffer)),
+                [(base_class.to_string(), base.to_string())]
+            );
+            let rescanned = scan(&mut written);
+            assert_eq!(
+                colors_of(&rescanned, fixture::PALETTE_CLASS).get("Shadow"),
+                Some(&ColorComponents::RefAndAdjust(base.into(), 0.1, 0.2, -0.3))
+            );
+        }
+    }
+
+    // Fixture JAR with the source of one class edited
+    fn fixture_zip_with(class_name: &str, edit: impl Fn(&str) -> String) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut sources = fixture::fixture_sources();
+        for (name, source) in &mut sources {
+            if *name == class_name {
+                *source = edit(source);
+            }
+        }
+        fixture::zip_entries(&fixture::assemble_classes(&sources).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reads_colors_built_from_constants() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let goodies = scan(&mut zip);
+        assert_eq!(
+            colors_of(&goodies, fixture::PALETTE_CLASS).get("Shadow"),
+            Some(&ColorComponents::RefAndAdjust("BLACK".into(), 0.0, 0.0, 0.1))
+        );
+
+        // Base taken from a field of the palette itself (aload; getfield), the
+        // field is named after the constant so the reference still resolves
+        let mut zip = fixture_zip_with(fixture::PALETTE_CLASS, |source| {
+            source.replace(
+                "getstatic Field com/bitwig/Color BLACK Lcom/bitwig/Color;",
+                "aload_0\n        getfield Field com/bitwig/Palette BLACK Lcom/bitwig/Color;",
+            )
+        });
+        let goodies = scan(&mut zip);
+        assert_eq!(
+            colors_of(&goodies, fixture::PALETTE_CLASS).get("Shadow"),
+            Some(&ColorComponents::RefAndAdjust("BLACK".into(), 0.0, 0.0, 0.1))
+        );
+    }
+
+    // Fixture JAR with a class defining some palette colors again
+    fn overrides_zip() -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut sources = fixture::fixture_sources();
+        sources.push((fixture::OVERRIDES_CLASS, fixture::OVERRIDES_SOURCE.to_string()));
+        fixture::zip_entries(&fixture::assemble_classes(&sources).unwrap()).unwrap()
+    }
+
+    // Components of every definition of the color, ordered by class (in
+    // bytecode order within one), as entries may come in any order
+    fn definitions_of(goodies: &GeneralGoodies, name: &str) -> Vec<(String, ColorComponents)> {
+        let mut definitions = goodies
+            .named_colors
+            .iter()
+            .filter(|color| color.color_name == name)
+            .map(|color| (color.class_name.clone(), color.components.clone()))
+            .collect::<Vec<_>>();
+        definitions.sort_by(|(a, _), (b, _)| a.cmp(b));
+        definitions
+    }
+
+    #[test]
+    fn rewrites_every_definition() {
+        let mut zip = overrides_zip();
+        let mut goodies = scan(&mut zip);
+        assert_eq!(definitions_of(&goodies, "Selection Fill").len(), 3);
+        assert_eq!(definitions_of(&goodies, "Shadow").len(), 3);
+
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        theme.named_colors.insert(
+            "Selection Fill".into(),
+            types::NamedColor::Absolute(AbsoluteColor { r: 1, g: 2, b: 3, a: 255 }),
+        );
+        let adjustment = types::ColorAdjustment {
+            base: "DEEP".into(),
+            hue: 0.1,
+            saturation: 0.2,
+            value: -0.3,
+        };
+        theme.adjusted_colors.insert("Shadow".into(), adjustment);
+        let (_, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+
+        let rescanned = scan(&mut written);
+        let selection_fill = ColorComponents::Rgbai(1, 2, 3, 255);
+        assert_eq!(
+            definitions_of(&rescanned, "Selection Fill"),
+            [
+                (fixture::OVERRIDES_CLASS.to_string(), selection_fill.clone()),
+                (fixture::OVERRIDES_CLASS.to_string(), selection_fill.clone()),
+                (fixture::PALETTE_CLASS.to_string(), selection_fill),
+            ]
+        );
+        let shadow = ColorComponents::RefAndAdjust("DEEP".into(), 0.1, 0.2, -0.3);
+        assert_eq!(
+            definitions_of(&rescanned, "Shadow"),
+            [
+                (fixture::OVERRIDES_CLASS.to_string(), shadow.clone()),
+                (fixture::OVERRIDES_CLASS.to_string(), shadow.clone()),
+                (fixture::PALETTE_CLASS.to_string(), shadow),
+            ]
+        );
+        // Goodies were updated the same way
+        assert_eq!(definitions_of(&goodies, "Selection Fill"), definitions_of(&rescanned, "Selection Fill"));
+        assert_eq!(definitions_of(&goodies, "Shadow"), definitions_of(&rescanned, "Shadow"));
+    }
+
+    #[test]
+    fn patches_every_class_defining_a_color() {
+        let mut zip = overrides_zip();
+        let mut goodies = scan(&mut zip);
+        let duplicates = duplicate_definitions(&goodies.named_colors);
+        let mut classes = duplicates["Selection Fill"]
+            .iter()
+            .map(|color| color.class_name.as_str())
+            .collect::<Vec<_>>();
+        classes.sort();
+        assert_eq!(classes, [fixture::OVERRIDES_CLASS, fixture::OVERRIDES_CLASS, fixture::PALETTE_CLASS]);
+
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        // Shadow differs between the classes, leave it out of this one
+        theme.adjusted_colors.remove("Shadow");
+        theme.named_colors.remove("Shadow");
+        theme.named_colors.insert(
+            "Selection Fill".into(),
+            types::NamedColor::Absolute(AbsoluteColor { r: 1, g: 2, b: 3, a: 255 }),
+        );
+        let (manifest, _) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        let patched = manifest
+            .classes
+            .iter()
+            .map(|(file_name, patched)| (file_name.clone(), patched.colors.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            patched,
+            [
+                (format!("{}.class", fixture::OVERRIDES_CLASS), vec!["Selection Fill".to_string()]),
+                (format!("{}.class", fixture::PALETTE_CLASS), vec!["Selection Fill".to_string()]),
+            ]
+        );
+    }
+
+    // Palette with line 100 + n at the start of the n-th definition of define()
+    fn palette_with_line_numbers(source: &str) -> String {
+        let mut in_define = false;
+        let mut definitions = 0;
+        let mut lines = Vec::new();
+        for line in source.lines() {
+            if line.starts_with(".method") {
+                in_define = line.contains(" define ");
+            }
+            if in_define && line.trim() == "aload_0" {
+                lines.push(format!("LN{}:    aload_0", definitions));
+                definitions += 1;
+                continue;
+            }
+            if in_define && line.trim() == ".end code" {
+                lines.push("        .linenumbertable".to_string());
+                lines.extend((0..definitions).map(|n| format!("            LN{} {}", n, 100 + n)));
+                lines.push("        .end linenumbertable".to_string());
+            }
+            lines.push(line.to_string());
+        }
+        lines.join("\n")
+    }
+
+    // Line numbers of the method, after checking each of them points at an instruction
+    fn line_numbers_of(class: &Class<'_>, method_name: &str) -> Vec<u16> {
+        let method = class
+            .methods
+            .iter()
+            .find(|method| class.cp.utf8(method.name).and_then(parse_utf8).as_deref() == Some(method_name))
+            .unwrap();
+        let AttrBody::Code((code_1, _)) = &method.attrs.first().unwrap().body else {
+            panic!("No code in {}", method_name);
+        };
+        code_1
+            .attrs
+            .iter()
+            .filter_map(|attr| match &attr.body {
+                AttrBody::LineNumberTable(table) => Some(table),
+                _ => None,
+            })
+            .flatten()
+            .map(|(pos, line)| {
+                assert!(code_1.bytecode.0.iter().any(|(ix_pos, _)| ix_pos.0 == pos.0), "line {}", line);
+                *line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn remaps_line_numbers_when_preserved() {
+        let palette_file = format!("{}.class", fixture::PALETTE_CLASS);
+        for (line_numbers, expected) in [(LineNumbers::Preserve, (100..107).collect::<Vec<u16>>()), (LineNumbers::Clear, vec![])] {
+            let mut zip = fixture_zip_with(fixture::PALETTE_CLASS, palette_with_line_numbers);
+            let original = read_zip_entry(&mut zip, &palette_file).unwrap();
+            assert_eq!(line_numbers_of(&parse_entry(&original), "define"), (100..107).collect::<Vec<_>>());
+
+            let mut goodies = scan(&mut zip);
+            let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+            // Second definition of the method grows from three ints to four
+            theme.named_colors.insert(
+                "Light Timeline Background".into(),
+                types::NamedColor::Absolute(AbsoluteColor { r: 1, g: 2, b: 3, a: 255 }),
+            );
+            let options = WriteOptions {
+                line_numbers,
+                ..Default::default()
+            };
+            let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &options);
+            assert_eq!(manifest.classes[&palette_file].colors, ["Light Timeline Background"]);
+
+            let buffer = read_zip_entry(&mut written, &palette_file).unwrap();
+            assert_eq!(line_numbers_of(&parse_entry(&buffer), "define"), expected, "{:?}", line_numbers);
+        }
+    }
+
+    #[test]
+    fn raw_color_edit_round_trips() {
+        let color_file = format!("{}.class", fixture::COLOR_CLASS);
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        // Float constant and double constant
+        theme.raw_colors.insert("WHITE".into(), AbsoluteColor { r: 255, g: 0, b: 0, a: 255 });
+        theme.raw_colors.insert("ODD".into(), AbsoluteColor { r: 0, g: 128, b: 255, a: 200 });
+
+        let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        assert_eq!(manifest.classes[&color_file].colors, ["ODD", "WHITE"]);
+
+        let rescanned = scan(&mut written);
+        let known_colors = rescanned.known_colors();
+        let consts = raw_consts(&rescanned);
+        let rgba = |name: &str| {
+            let comps = &consts[name];
+            let (r, g, b) = comps.to_rgb(&known_colors);
+            (r, g, b, comps.alpha().unwrap())
+        };
+        assert!(matches!(consts["WHITE"], ColorComponents::Rgbaf(..)), "{:?}", consts["WHITE"]);
+        assert!(matches!(consts["ODD"], ColorComponents::Rgbad(..)), "{:?}", consts["ODD"]);
+        assert_eq!(rgba("WHITE"), (255, 0, 0, 255));
+        assert_eq!(rgba("ODD"), (0, 128, 255, 200));
+        // Goodies got the values written
+        assert_eq!(consts, raw_consts(&goodies));
+
+        // Nothing left to patch for the same theme
+        let theme = CucumberBitwigTheme::from_goodies(&rescanned);
+        let (manifest, _) = write_theme(&mut written, &mut goodies, &theme, &WriteOptions::default());
+        assert!(manifest.classes.is_empty(), "{:?}", manifest.classes.keys());
+    }
+
+    #[test]
+    fn untouched_raw_color_floats_keep_precision() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        assert_eq!(raw_consts(&goodies)["ODD"], ColorComponents::Rgbad(0.666333, 0.0, 0.0, 1.0));
+
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        // Only green of ODD is edited, BLACK gets values no byte maps to
+        let mut changed_floats = BTreeMap::new();
+        changed_floats.insert("ODD".to_string(), [0.666333, 0.5, 0.0, 1.0]);
+        changed_floats.insert("BLACK".to_string(), [0.2, 0.4, 0.6, 1.0]);
+        theme.apply_raw_color_floats(&changed_floats);
+
+        let (_, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        let consts = raw_consts(&scan(&mut written));
+        assert_eq!(consts["ODD"], ColorComponents::Rgbad(0.666333, 0.5, 0.0, 1.0));
+        assert_eq!(consts["BLACK"], ColorComponents::Rgbaf(0.2, 0.4, 0.6, 1.0));
+        // Untouched constants aren't rewritten at all
+        assert_eq!(consts["DEEP"], ColorComponents::Rgbad(0.666333, 0.1, 0.2, 1.0));
+    }
+
+    #[test]
+    fn skips_color_call_at_method_start() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let expected = colors_of(&scan(&mut zip), fixture::PALETTE_CLASS);
+
+        // Call with nothing before it to take the name from
+        let early = r#"
+.method public early : ()V
+    .code stack 10 locals 1
+        invokevirtual Method com/bitwig/Palette gray (Ljava/lang/String;I)Lcom/bitwig/Color;
+        pop
+        return
+    .end code
+.end method
+"#;
+        let mut zip = fixture_zip_with(fixture::PALETTE_CLASS, |source| {
+            source.replace(".end class", &format!("{}.end class", early))
+        });
+        let goodies = scan(&mut zip);
+        assert_eq!(colors_of(&goodies, fixture::PALETTE_CLASS), expected);
+    }
+
+    #[test]
+    fn skips_raw_color_not_stored_to_constant() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let expected = raw_consts(&scan(&mut zip));
+
+        // Color built and dropped right away, before the final return
+        let dropped = "        new com/bitwig/Color
+        dup
+        fconst_0
+        fconst_1
+        fconst_0
+        fconst_1
+        invokespecial Method com/bitwig/Color <init> (FFFF)V
+        pop
+        return
+    .end code";
+        let mut zip = fixture_zip_with(fixture::COLOR_CLASS, |source| {
+            let (head, tail) = source.rsplit_once("        return\n    .end code").unwrap();
+            format!("{}{}{}", head, dropped, tail)
+        });
+        assert_eq!(raw_consts(&scan(&mut zip)), expected);
+    }
+
+    #[test]
+    fn adjusted_colors_need_their_palette_methods() {
+        let palette_file = format!("{}.class", fixture::PALETTE_CLASS);
+        let mut zip = fixture::fixture_zip().unwrap();
+        let full_theme = CucumberBitwigTheme::from_goodies(&scan(&mut zip));
+
+        // Adjusted colors moved out of the method palette methods are found in
+        let mut zip = fixture_zip_with(fixture::PALETTE_CLASS, |source| {
+            let start = source.find("        aload_0\n        ldc \"Accent Dimmed\"").unwrap();
+            let end = start + source[start..].find("        return").unwrap();
+            let extra = format!(
+                ".method public extra : ()V\n    .code stack 10 locals 1\n{}        return\n    .end code\n.end method\n",
+                &source[start..end]
+            );
+            format!("{}{}", &source[..start], &source[end..]).replace(".end class", &format!("{}.end class", extra))
+        });
+        let mut goodies = scan(&mut zip);
+        let meths = &goodies.palette_color_methods;
+        assert!(meths.ref_hsv_f.is_none() && meths.name_hsv_f.is_none());
+        assert_eq!(meths.from_components(&ColorComponents::RefAndAdjust("BLACK".into(), 0.0, 0.0, 0.1)), None);
+        let colors = colors_of(&goodies, fixture::PALETTE_CLASS);
+        assert!(!colors.contains_key("Shadow") && !colors.contains_key("Accent Dimmed"), "{:?}", colors);
+
+        // Theme of a JAR having them changes nothing here
+        let mut theme = full_theme;
+        theme.adjusted_colors.insert(
+            "Shadow".into(),
+            types::ColorAdjustment {
+                base: "WHITE".into(),
+                hue: 0.1,
+                saturation: 0.2,
+                value: -0.3,
+            },
+        );
+        let original = read_zip_entry(&mut zip, &palette_file).unwrap();
+        let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        assert!(!manifest.classes.contains_key(&palette_file), "{:?}", manifest.classes);
+        assert_eq!(read_zip_entry(&mut written, &palette_file).unwrap(), original);
+    }
+
+    #[test]
+    fn finds_palette_regardless_of_ordering() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let expected = colors_of(&scan(&mut zip), fixture::PALETTE_CLASS);
+        assert_eq!(expected.len(), 7);
+
+        // Defining method after the palette methods instead of second
+        let mut zip = fixture_zip_with(fixture::PALETTE_CLASS, |source| {
+            let start = source.find(".method public define").unwrap();
+            let end = start + source[start..].find(".end method\n").unwrap() + ".end method\n".len();
+            format!("{}{}", &source[..start], &source[end..])
+                .replace(".end class", &format!("{}.end class", &source[start..end]))
+        });
+        assert_eq!(colors_of(&scan(&mut zip), fixture::PALETTE_CLASS), expected);
+
+        // JAR entries in reverse, palette and its users before the color record
+        let mut classes = fixture::fixture_classes().unwrap();
+        classes.reverse();
+        let mut zip = fixture::zip_entries(&classes).unwrap();
+        let goodies = scan(&mut zip);
+        assert_eq!(colors_of(&goodies, fixture::PALETTE_CLASS), expected);
+        assert_eq!(raw_consts(&goodies).len(), 5);
+    }
+
+    #[test]
+    fn refuses_to_overflow_constant_pool() {
+        let buffer = fixture::fixture_classes()
+            .unwrap()
+            .into_iter()
+            .find(|(file_name, _)| *file_name == format!("{}.class", fixture::PALETTE_CLASS))
+            .unwrap()
+            .1;
+        let mut class = parse_entry(&buffer);
+        let free = MAX_CP_LEN - class.cp.0.len();
+        assert!(ensure_cp_room(&class, free).is_ok());
+
+        // Pool filled up to six entries short of the limit
+        let padding = MAX_CP_LEN - 6 - class.cp.0.len();
+        class.cp.0.extend(std::iter::repeat_with(|| Const::Null).take(padding));
+        assert!(ensure_cp_room(&class, 6).is_ok());
+        let err = ensure_cp_room(&class, 7).unwrap_err().to_string();
+        assert!(err.contains(fixture::PALETTE_CLASS), "{}", err);
+
+        // Already past the limit, nothing gets assembled
+        class.cp.0.extend(std::iter::repeat_with(|| Const::Null).take(7));
+        assert!(reasm("Palette.class", &class).is_err());
+    }
+
+    #[test]
+    fn assembling_nothing_is_an_error() {
+        for source in ["", "\n\n", ".version 52 0"] {
+            let err = assemble_class("Empty.class", source).unwrap_err().to_string();
+            assert!(err.contains("Empty.class"), "{:?}: {}", source, err);
+        }
+        let (_, palette) = fixture::fixture_sources()
+            .into_iter()
+            .find(|(name, _)| *name == fixture::PALETTE_CLASS)
+            .unwrap();
+        assert!(assemble_class("Palette.class", &palette).is_ok());
+    }
+
+    // Fixture JAR with a resource and an entry which fails to read
+    fn fixture_zip_with_damaged(damaged_name: &str) -> ZipArchive<Cursor<Vec<u8>>> {
+        let payload = b"Stored as is, so it can be found and damaged. ".repeat(4);
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (file_name, data) in fixture::fixture_classes().unwrap() {
+            writer.start_file(file_name, FileOptions::default()).unwrap();
+            writer.write_all(&data).unwrap();
+        }
+        writer.start_file("res/about.txt", FileOptions::default()).unwrap();
+        writer.write_all(b"Not a class").unwrap();
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file(damaged_name, stored).unwrap();
+        writer.write_all(&payload).unwrap();
+        let mut jar = writer.finish().unwrap().into_inner();
+
+        // Checksum no longer matches once a byte of the data is changed
+        let offset = jar.windows(payload.len()).position(|window| window == payload).unwrap();
+        jar[offset] ^= 0xff;
+        ZipArchive::new(Cursor::new(jar)).unwrap()
+    }
+
+    #[test]
+    fn records_unreadable_entries() {
+        let mut zip = fixture_zip_with_damaged("com/bitwig/Broken.class");
+        let goodies = scan(&mut zip);
+        let failures = goodies
+            .parse_failures
+            .iter()
+            .map(|failure| failure.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(failures, ["com/bitwig/Broken.class"]);
+        assert!(goodies.parse_failures[0].error.starts_with("Read: "), "{:?}", goodies.parse_failures);
+        // The rest of the JAR is still scanned
+        assert_eq!(colors_of(&goodies, fixture::PALETTE_CLASS).len(), 7);
+    }
+
+    // Low memory mode reads class files only, so it finds the same as the
+    // regular scan, except that unreadable entries other than classes are
+    // never read and so never recorded as failures
+    #[test]
+    fn low_memory_scan_finds_the_same() {
+        for damaged_name in ["com/bitwig/Broken.class", "res/broken.png"] {
+            let mut zip = fixture_zip_with_damaged(damaged_name);
+            let mut regular = scan(&mut zip);
+            let options = ExtractOptions {
+                low_memory: true,
+                ..Default::default()
+            };
+            let mut low_memory = extract_general_goodies_with_options(&mut zip, &options, |_| {}).unwrap();
+
+            let failures = |goodies: &mut GeneralGoodies| {
+                std::mem::take(&mut goodies.parse_failures)
+                    .into_iter()
+                    .map(|failure| failure.file_name)
+                    .collect::<Vec<_>>()
+            };
+            let (regular_failures, low_memory_failures) = (failures(&mut regular), failures(&mut low_memory));
+            assert_eq!(regular_failures, [damaged_name]);
+            if damaged_name.ends_with(".class") {
+                assert_eq!(low_memory_failures, [damaged_name]);
+            } else {
+                assert!(low_memory_failures.is_empty(), "{:?}", low_memory_failures);
+            }
+
+            assert_eq!(regular.named_colors.len(), 7);
+            assert_eq!(format!("{:#?}", low_memory), format!("{:#?}", regular));
+        }
+    }
+
+    #[test]
+    fn scans_in_steps() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let options = ExtractOptions {
+            report_every: 2,
+            ..Default::default()
+        };
+        assert!(JarScan::new(&zip, options.clone()).finish().is_err());
+
+        let mut scan = JarScan::new(&zip, options.clone());
+        let mut reports = Vec::new();
+        while let Some(progress) = scan.step(&mut zip).unwrap() {
+            reports.push((progress.stage, progress.done, progress.total));
+        }
+        let (searching, scanning) = (Stage::SearchingUsefulClasses, Stage::ScanningNamedColors);
+        assert_eq!(
+            reports,
+            [
+                (searching, 0, 5),
+                (searching, 2, 5),
+                (searching, 4, 5),
+                (searching, 5, 5),
+                (scanning, 0, 5),
+                (scanning, 2, 5),
+                (scanning, 4, 5),
+                (scanning, 5, 5),
+            ]
+        );
+        // Nothing more once it's over
+        assert!(scan.step(&mut zip).unwrap().is_none());
+
+        let mut single_pass_reports = Vec::new();
+        let goodies = extract_general_goodies_with_options(&mut zip, &options, |progress| {
+            single_pass_reports.push((progress.stage, progress.done, progress.total))
+        })
+        .unwrap();
+        assert_eq!(single_pass_reports, reports);
+        assert_eq!(format!("{:#?}", scan.finish().unwrap()), format!("{:#?}", goodies));
+    }
+
+    #[test]
+    fn exported_theme_applies_without_changes() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        let exported = exchange::export_theme_json(&CucumberBitwigTheme::from_goodies(&goodies)).unwrap();
+        let theme = exchange::import_theme_json(&exported).unwrap();
+
+        let (manifest, _) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        // Float and double raw constants included, none of them looks edited
+        assert!(manifest.classes.is_empty(), "{:?}", manifest.classes);
+    }
+
+    #[test]
+    fn skips_constants_missing_from_jar() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let mut goodies = scan(&mut zip);
+        // Theme of another build, its constants have other names
+        let mut theme = CucumberBitwigTheme::from_goodies(&goodies);
+        theme.raw_colors.insert("a1".into(), AbsoluteColor { r: 1, g: 2, b: 3, a: 255 });
+        theme.raw_color_floats.insert("a1".into(), [0.1, 0.2, 0.3, 1.0]);
+        theme.adjusted_colors.insert(
+            "Shadow".into(),
+            types::ColorAdjustment {
+                base: "a1".into(),
+                hue: 0.1,
+                saturation: 0.2,
+                value: -0.3,
+            },
+        );
+        theme.named_colors.insert(
+            "On".into(),
+            types::NamedColor::Absolute(AbsoluteColor { r: 1, g: 2, b: 3, a: 255 }),
+        );
+
+        let (manifest, mut written) = write_theme(&mut zip, &mut goodies, &theme, &WriteOptions::default());
+        // The rest of the theme is still written
+        assert_eq!(
+            manifest.classes.keys().collect::<Vec<_>>(),
+            [&format!("{}.class", fixture::PALETTE_CLASS)]
+        );
+        let palette_colors = colors_of(&scan(&mut written), fixture::PALETTE_CLASS);
+        assert_eq!(palette_colors["On"], ColorComponents::Rgbai(1, 2, 3, 255));
+        assert_eq!(palette_colors["Shadow"], ColorComponents::RefAndAdjust("BLACK".into(), 0.0, 0.0, 0.1));
+    }
+
+    #[test]
+    fn missing_anchor_is_an_error() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let options = ExtractOptions {
+            anchors: AnchorSet {
+                init: "Not in any class".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = extract_general_goodies_with_options(&mut zip, &options, |_| {}).unwrap_err();
+        assert_eq!(err.to_string(), "No class matched the Init anchor");
+    }
+}
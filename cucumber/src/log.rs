@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Color,
+    Plain,
+    Json,
+}
+
+static LOG_FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Color as u8);
+
+pub fn set_log_format(format: LogFormat) {
+    LOG_FORMAT.store(format as u8, Ordering::Relaxed);
+    colored::control::set_override(format == LogFormat::Color);
+}
+
+pub fn log_format() -> LogFormat {
+    match LOG_FORMAT.load(Ordering::Relaxed) {
+        0 => LogFormat::Color,
+        1 => LogFormat::Plain,
+        _ => LogFormat::Json,
+    }
+}
+
+// --json and --no-color CLI flags, also respects NO_COLOR (https://no-color.org)
+pub fn log_format_from_flags(flags: &[String]) -> LogFormat {
+    if flags.iter().any(|flag| flag == "--json") {
+        LogFormat::Json
+    } else if flags.iter().any(|flag| flag == "--no-color")
+        || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+    {
+        LogFormat::Plain
+    } else {
+        LogFormat::Color
+    }
+}
+
+// Free-form message, wrapped into JSON line when needed
+pub fn log_message(message: &str) {
+    log_event("message", json!({ "text": message }), message);
+}
+
+// `fields` are used for JSON output, `text` otherwise
+pub fn log_event(event: &str, fields: Value, text: &str) {
+    match log_format() {
+        LogFormat::Json => {
+            let mut line = json!({ "event": event });
+            if let (Value::Object(line), Value::Object(fields)) = (&mut line, fields) {
+                line.extend(fields);
+            }
+            println!("{}", line);
+        }
+        LogFormat::Color | LogFormat::Plain => println!("{}", text),
+    }
+}
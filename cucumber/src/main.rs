@@ -442,7 +442,9 @@ fn reasm(class: &Class<'_>) -> Result<Vec<u8>> {
     let source = std::str::from_utf8(&out)?;
     let mut assembled =
         assemble(source, AssemblerOptions {}).map_err(|err| anyhow!("Asm: {:?}", err))?;
-    let (_name, data) = assembled.pop().unwrap();
+    let (_name, data) = assembled
+        .pop()
+        .ok_or_else(|| anyhow!("Nothing assembled"))?;
 
     Ok(data)
 }
@@ -496,3 +498,62 @@ fn patch_class(name: &str, class: &mut Class<'_>) {
         bytecode.0 = new_bytecode;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use cucumber::{extract_general_goodies, fixture, ColorComponents};
+
+    use super::*;
+
+    // Pins the library scanner against this one while they are consolidated.
+    // Expected differences:
+    // - this scanner reads the single method it's given, the library walks
+    //   every method and records the index of the one defining each color
+    // - (String;FFF) is taken for HSV deltas here and its first float is read
+    //   three times, the library reads it as plain RGB
+    // - colors adjusted from a base color aren't recognized here at all
+    #[test]
+    fn agrees_with_library_scanner() {
+        let mut zip = fixture::fixture_zip().unwrap();
+        let goodies = extract_general_goodies(&mut zip).unwrap();
+        let palette_colors = goodies
+            .named_colors
+            .iter()
+            .filter(|color| color.class_name == fixture::PALETTE_CLASS)
+            .collect::<Vec<_>>();
+        assert!(palette_colors.iter().all(|color| color.method_idx == 1));
+        let mut library = palette_colors
+            .into_iter()
+            .map(|color| (color.color_name.clone(), color.components.clone()))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut data = Vec::new();
+        zip.by_name(&format!("{}.class", fixture::PALETTE_CLASS))
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let rgba_method = find_rgba_method_in_data(&data).unwrap();
+        let mut class = classfile::parse(&data, ParserOptions { no_short_code_attr: true }).unwrap();
+        let old = colorize_class(fixture::PALETTE_CLASS, &mut class, 1, &rgba_method, &HashMap::new()).unwrap();
+
+        let mut hsv_adjustments = Vec::new();
+        for def in old {
+            let components = match def.color {
+                Color::Grayscale(v) => ColorComponents::Grayscale(v),
+                Color::Rgbu(r, g, b) => ColorComponents::Rgbi(r, g, b),
+                Color::Rgbau(r, g, b, a) => ColorComponents::Rgbai(r, g, b, a),
+                Color::HsvfAdjustment(h, s, v) => {
+                    hsv_adjustments.push((def.name, (h, s, v)));
+                    continue;
+                }
+            };
+            assert_eq!(library.remove(&def.name), Some(components), "{}", def.name);
+        }
+
+        assert_eq!(hsv_adjustments, [("Accent (default)".to_string(), (1.0, 1.0, 1.0))]);
+        assert_eq!(library.remove("Accent (default)"), Some(ColorComponents::Rgbf(1.0, 0.5, 0.0)));
+        assert_eq!(library.into_keys().collect::<Vec<_>>(), ["Accent Dimmed", "Shadow"]);
+    }
+}
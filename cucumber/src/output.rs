@@ -0,0 +1,409 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use krakatau2::zip::ZipArchive;
+use serde::Serialize;
+
+use crate::exchange::{export_theme_json, THEME_FORMAT_VERSION};
+use crate::log;
+use crate::types::{CucumberBitwigTheme, UiTarget};
+
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{name}-{version}-patched.jar";
+
+const KNOWN_PLACEHOLDERS: &[&str] = &["name", "version", "date"];
+
+// Where to put patched JAR when output path isn't given explicitly
+#[derive(Debug, Clone)]
+pub struct OutputSettings {
+    pub dir: Option<PathBuf>,
+    pub filename_template: String,
+    // Write applied theme as <output>.theme.json next to the JAR
+    pub save_theme_json: bool,
+    // Run patched classes through the JVM bytecode verifier after writing
+    pub verify_classes: bool,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            dir: None,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.into(),
+            save_theme_json: true,
+            verify_classes: false,
+        }
+    }
+}
+
+impl OutputSettings {
+    // CUCUMBER_OUTPUT_DIR, CUCUMBER_OUTPUT_TEMPLATE and CUCUMBER_SAVE_THEME_JSON
+    // (0 or false to disable) override the defaults
+    pub fn from_env() -> Self {
+        let mut settings = OutputSettings::default();
+        if let Ok(dir) = std::env::var("CUCUMBER_OUTPUT_DIR") {
+            settings.dir = Some(dir.into());
+        }
+        if let Ok(template) = std::env::var("CUCUMBER_OUTPUT_TEMPLATE") {
+            settings.filename_template = template;
+        }
+        if let Ok(save) = std::env::var("CUCUMBER_SAVE_THEME_JSON") {
+            settings.save_theme_json = !matches!(save.trim(), "0" | "false");
+        }
+        settings
+    }
+
+    pub fn resolve(&self, input_jar: &Path, version: Option<&str>) -> PathBuf {
+        let name = input_jar
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "bitwig".into());
+        let version = version.unwrap_or("unknown");
+        let date = today();
+        let vars = [
+            ("name", name.as_str()),
+            ("version", version),
+            ("date", date.as_str()),
+        ];
+
+        let file_name = match render_filename_template(&self.filename_template, &vars) {
+            Ok(file_name) => file_name,
+            Err(err) => {
+                log::log_message(&format!(
+                    "Bad output filename template \"{}\" ({}), using \"{}\"",
+                    self.filename_template, err, DEFAULT_FILENAME_TEMPLATE
+                ));
+                render_filename_template(DEFAULT_FILENAME_TEMPLATE, &vars).unwrap()
+            }
+        };
+
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => input_jar.parent().map(Into::into).unwrap_or_default(),
+        };
+
+        dir.join(file_name)
+    }
+}
+
+// Written next to the patched JAR, so a distributed JAR can be checked
+// against what was actually built
+#[derive(Debug, Default, Serialize)]
+pub struct PatchManifest {
+    // Class file name -> its hash after patching
+    pub classes: BTreeMap<String, PatchedClass>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PatchedClass {
+    pub sha256: String,
+    pub colors: Vec<String>,
+}
+
+impl PatchManifest {
+    pub fn path_for(output_jar: &Path) -> PathBuf {
+        output_jar.with_extension("manifest.json")
+    }
+
+    pub fn write_next_to(&self, output_jar: &Path) -> anyhow::Result<PathBuf> {
+        let path = Self::path_for(output_jar);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+pub fn theme_json_path_for(output_jar: &Path) -> PathBuf {
+    output_jar.with_extension("theme.json")
+}
+
+// Portable record of the colors applied to the JAR
+pub fn write_theme_json_next_to(
+    theme: &CucumberBitwigTheme,
+    output_jar: &Path,
+) -> anyhow::Result<PathBuf> {
+    let path = theme_json_path_for(output_jar);
+    fs::write(&path, export_theme_json(theme)?)?;
+    Ok(path)
+}
+
+pub fn render_filename_template(template: &str, vars: &[(&str, &str)]) -> anyhow::Result<String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(anyhow!("unmatched '}}'"));
+        }
+        rendered.push_str(&rest[..open]);
+        let after_open = &rest[open + 1..];
+        let close = after_open
+            .find('}')
+            .ok_or_else(|| anyhow!("unmatched '{{'"))?;
+        let placeholder = &after_open[..close];
+        if !KNOWN_PLACEHOLDERS.contains(&placeholder) {
+            return Err(anyhow!("unknown placeholder {{{}}}", placeholder));
+        }
+        let value = vars
+            .iter()
+            .find(|(name, _)| *name == placeholder)
+            .map(|(_, value)| *value)
+            .unwrap_or_default();
+        rendered.push_str(value);
+        rest = &after_open[close + 1..];
+    }
+    rendered.push_str(rest);
+
+    if rendered.is_empty() || rendered.contains(['/', '\\']) {
+        return Err(anyhow!("rendered file name \"{}\" is not usable", rendered));
+    }
+
+    Ok(rendered)
+}
+
+// Opens OS file manager at the directory of the given file
+pub fn reveal_in_file_manager(file: &Path) -> anyhow::Result<()> {
+    let dir = file
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(file);
+        command
+    };
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", file.display()));
+        command
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(dir);
+        command
+    };
+
+    command
+        .spawn()
+        .map_err(|err| anyhow!("Failed to open {}: {}", dir.display(), err))?;
+    Ok(())
+}
+
+pub const PATCH_MARKER_ENTRY: &str = "META-INF/cucumber-patched";
+
+// Written into every patched JAR, so stock and patched JARs can be told apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchMarker {
+    pub cucumber_version: String,
+    pub theme_format_version: u64,
+}
+
+impl PatchMarker {
+    pub fn current() -> Self {
+        PatchMarker {
+            cucumber_version: env!("CARGO_PKG_VERSION").into(),
+            theme_format_version: THEME_FORMAT_VERSION,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "Cucumber-Version: {}\nTheme-Format-Version: {}\n",
+            self.cucumber_version, self.theme_format_version
+        )
+    }
+}
+
+// Marker left by a previous patching, if any
+pub fn read_patch_marker<R: std::io::Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+) -> Option<PatchMarker> {
+    let mut text = String::new();
+    zip.by_name(PATCH_MARKER_ENTRY)
+        .ok()?
+        .read_to_string(&mut text)
+        .ok()?;
+    let field = |key: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(key))
+            .map(|value| value.trim().to_string())
+    };
+    Some(PatchMarker {
+        cucumber_version: field("Cucumber-Version:").unwrap_or_else(|| "unknown".into()),
+        theme_format_version: field("Theme-Format-Version:")
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(1),
+    })
+}
+
+// Bitwig version as written into JAR manifest
+pub fn release_version<R: std::io::Read + std::io::Seek>(zip: &mut ZipArchive<R>) -> Option<String> {
+    read_release_info(zip)?.version
+}
+
+// What JAR manifest tells about the Bitwig build. Layouts differ between
+// releases, so each field is looked up under several keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReleaseInfo {
+    pub version: Option<String>,
+    pub build: Option<String>,
+    pub os: Option<String>,
+    pub jvm: Option<String>,
+    // All manifest attributes, in file order
+    pub raw: Vec<(String, String)>,
+}
+
+const VERSION_KEYS: &[&str] = &["Implementation-Version", "Bundle-Version", "Specification-Version"];
+const BUILD_KEYS: &[&str] = &["Build-Number", "Implementation-Build", "Build-Revision"];
+const OS_KEYS: &[&str] = &["Build-OS", "Target-OS", "OS-Name"];
+const JVM_KEYS: &[&str] = &["Build-Jdk", "Build-Jdk-Spec", "Created-By"];
+
+impl ReleaseInfo {
+    pub fn parse(manifest: &str) -> Self {
+        // Lines starting with a space continue the previous one
+        let mut raw: Vec<(String, String)> = Vec::new();
+        for line in manifest.lines() {
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, value)) = raw.last_mut() {
+                    value.push_str(continuation);
+                }
+            } else if let Some((key, value)) = line.split_once(':') {
+                raw.push((key.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        let field = |keys: &[&str]| {
+            keys.iter().find_map(|key| {
+                raw.iter()
+                    .find(|(name, value)| name.eq_ignore_ascii_case(key) && !value.is_empty())
+                    .map(|(_, value)| value.clone())
+            })
+        };
+        ReleaseInfo {
+            version: field(VERSION_KEYS),
+            build: field(BUILD_KEYS),
+            os: field(OS_KEYS),
+            jvm: field(JVM_KEYS),
+            raw,
+        }
+    }
+
+    // Warnings about features which won't work with this build
+    pub fn compatibility_notes(&self, theme: &CucumberBitwigTheme) -> Vec<String> {
+        let build = match (&self.version, &self.os) {
+            (Some(version), Some(os)) => format!("Bitwig {} ({})", version, os),
+            (Some(version), None) => format!("Bitwig {}", version),
+            _ => "this build".to_string(),
+        };
+        let mut notes = Vec::new();
+        if self.version.is_none() {
+            notes.push("Bitwig version is unknown, output file names will lack it".to_string());
+        }
+        if !theme.constant_refs.contains_key(&UiTarget::Playhead) {
+            notes.push(format!("Timeline color editing is unsupported on {}", build));
+        }
+        if theme.named_colors.is_empty() {
+            notes.push(format!("No palette colors were found in {}", build));
+        }
+        notes
+    }
+}
+
+pub fn read_release_info<R: std::io::Read + std::io::Seek>(zip: &mut ZipArchive<R>) -> Option<ReleaseInfo> {
+    let mut manifest = String::new();
+    zip.by_name("META-INF/MANIFEST.MF")
+        .ok()?
+        .read_to_string(&mut manifest)
+        .ok()?;
+    Some(ReleaseInfo::parse(&manifest))
+}
+
+// YYYY-MM-DD in UTC, no need to pull a date crate just for that
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or_default();
+    let days = (secs / 86400) as i64;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_implementation_manifest() {
+        let manifest = "Manifest-Version: 1.0\r\n\
+            Created-By: 17.0.6 (Eclipse Adoptium)\r\n\
+            Implementation-Title: Bitwig Studio\r\n\
+            Implementation-Version: 5.1.9\r\n\
+            Build-Number: 15183\r\n\
+            Build-OS: Linux\r\n\
+            \r\n";
+        let info = ReleaseInfo::parse(manifest);
+        assert_eq!(info.version.as_deref(), Some("5.1.9"));
+        assert_eq!(info.build.as_deref(), Some("15183"));
+        assert_eq!(info.os.as_deref(), Some("Linux"));
+        assert_eq!(info.jvm.as_deref(), Some("17.0.6 (Eclipse Adoptium)"));
+        assert_eq!(info.raw.len(), 6);
+        assert_eq!(info.raw[0], ("Manifest-Version".to_string(), "1.0".to_string()));
+    }
+
+    #[test]
+    fn parses_bundle_manifest_with_continuations() {
+        // Keys in other case, fallback keys, an empty preferred key and a
+        // value wrapped at 72 bytes as jar tools do
+        let manifest = "Manifest-Version: 1.0\n\
+            Bundle-Version: 4.4.10\n\
+            Build-Jdk:\n\
+            build-jdk-spec: 11\n\
+            implementation-build: 4410-release-20230810-very-long-build-identifier-th\n at-wraps\n\
+            Target-OS: Windows\n";
+        let info = ReleaseInfo::parse(manifest);
+        assert_eq!(info.version.as_deref(), Some("4.4.10"));
+        assert_eq!(
+            info.build.as_deref(),
+            Some("4410-release-20230810-very-long-build-identifier-that-wraps")
+        );
+        assert_eq!(info.os.as_deref(), Some("Windows"));
+        assert_eq!(info.jvm.as_deref(), Some("11"));
+        assert_eq!(info.raw.len(), 6);
+    }
+
+    #[test]
+    fn notes_missing_features() {
+        let info = ReleaseInfo::parse("Implementation-Version: 5.1.9\nBuild-OS: Mac OS X\n");
+        let notes = info.compatibility_notes(&CucumberBitwigTheme::default());
+        assert_eq!(
+            notes,
+            [
+                "Timeline color editing is unsupported on Bitwig 5.1.9 (Mac OS X)",
+                "No palette colors were found in Bitwig 5.1.9 (Mac OS X)",
+            ]
+        );
+
+        let notes = ReleaseInfo::parse("").compatibility_notes(&CucumberBitwigTheme::default());
+        assert_eq!(notes[0], "Bitwig version is unknown, output file names will lack it");
+        assert_eq!(notes[1], "Timeline color editing is unsupported on this build");
+    }
+}
@@ -0,0 +1,108 @@
+// Swatch grid of theme colors as PNG. Pixels are stored without compression,
+// which keeps the encoder tiny and is fine for a preview of a few hundred colors.
+
+use crate::types::{CucumberBitwigTheme, NamedColor};
+
+const SWATCH_SIZE: usize = 16;
+const COLUMNS: usize = 16;
+
+pub fn theme_preview_png(theme: &CucumberBitwigTheme) -> Vec<u8> {
+    let colors = theme
+        .named_colors
+        .values()
+        .filter_map(|color| match color {
+            NamedColor::Absolute(color) => Some((color.r, color.g, color.b, color.a)),
+            NamedColor::Relative(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    let rows = colors.len().div_ceil(COLUMNS).max(1);
+    let width = COLUMNS * SWATCH_SIZE;
+    let height = rows * SWATCH_SIZE;
+
+    let mut pixels = vec![0u8; width * height * 4];
+    for (idx, (r, g, b, a)) in colors.into_iter().enumerate() {
+        let (column, row) = (idx % COLUMNS, idx / COLUMNS);
+        for y in row * SWATCH_SIZE..(row + 1) * SWATCH_SIZE {
+            for x in column * SWATCH_SIZE..(column + 1) * SWATCH_SIZE {
+                let offset = (y * width + x) * 4;
+                pixels[offset..offset + 4].copy_from_slice(&[r, g, b, a]);
+            }
+        }
+    }
+
+    encode_png(width as u32, height as u32, &pixels)
+}
+
+// RGBA, 8 bits per channel
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth, RGBA color type, compression, filter, interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Every scanline starts with filter type (none)
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for line in rgba.chunks(width as usize * 4) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(is_last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use colorsys::{ColorTransform as _, Rgb, SaturationInSpace};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    contrast_ratio, log,
+    types::{AbsoluteColor, CucumberBitwigTheme, NamedColor},
+    ColorComponents,
+};
+
+// Scriptable color edit, returns None when the color should stay as is
+pub trait ColorTransform {
+    fn apply(&self, name: &str, c: &ColorComponents) -> Option<ColorComponents>;
+}
+
+// Components of colors which don't depend on other colors
+fn absolute_rgba(c: &ColorComponents) -> Option<(u8, u8, u8, u8)> {
+    match c {
+        ColorComponents::RefAndAdjust(..) | ColorComponents::StringAndAdjust(..) => None,
+        other => {
+            let (r, g, b) = other.to_rgb(&Default::default());
+            Some((r, g, b, other.alpha().unwrap_or(255)))
+        }
+    }
+}
+
+fn map_rgb(c: &ColorComponents, f: impl FnOnce(&mut Rgb)) -> Option<ColorComponents> {
+    let (r, g, b, a) = absolute_rgba(c)?;
+    let mut rgb = Rgb::from((r, g, b));
+    f(&mut rgb);
+    let (r, g, b) = rgb.into();
+    Some(ColorComponents::Rgbai(r, g, b, a))
+}
+
+pub struct Invert;
+
+impl ColorTransform for Invert {
+    fn apply(&self, _name: &str, c: &ColorComponents) -> Option<ColorComponents> {
+        map_rgb(c, |rgb| rgb.invert())
+    }
+}
+
+pub struct Desaturate {
+    pub amount: f64, // 0..100
+}
+
+impl ColorTransform for Desaturate {
+    fn apply(&self, _name: &str, c: &ColorComponents) -> Option<ColorComponents> {
+        map_rgb(c, |rgb| rgb.saturate(SaturationInSpace::Hsl(-self.amount)))
+    }
+}
+
+pub struct HueRotate {
+    pub degrees: f64,
+}
+
+impl ColorTransform for HueRotate {
+    fn apply(&self, _name: &str, c: &ColorComponents) -> Option<ColorComponents> {
+        map_rgb(c, |rgb| rgb.adjust_hue(self.degrees))
+    }
+}
+
+// Snaps alpha values which are within threshold of the target to the target,
+// e.g. stray 254s to fully opaque
+pub struct NormalizeAlpha {
+    pub threshold: u8,
+    pub target: u8,
+}
+
+impl Default for NormalizeAlpha {
+    fn default() -> Self {
+        NormalizeAlpha {
+            threshold: 2,
+            target: 255,
+        }
+    }
+}
+
+impl ColorTransform for NormalizeAlpha {
+    fn apply(&self, _name: &str, c: &ColorComponents) -> Option<ColorComponents> {
+        let ColorComponents::Rgbai(r, g, b, a) = c else {
+            return None;
+        };
+        if *a == self.target || a.abs_diff(self.target) > self.threshold {
+            return None;
+        }
+        Some(ColorComponents::Rgbai(*r, *g, *b, self.target))
+    }
+}
+
+// Text and the backgrounds it's drawn on, according to notes in mapping.rs
+pub const KNOWN_CONTRAST_PAIRS: &[(&str, &str)] = &[
+    ("Light Text", "Hole (dark)"),
+    ("Light Text", "Button background"),
+    ("Medium Light Text", "Hole (dark)"),
+    ("Dark Text", "Field background"),
+];
+
+// WCAG AA for normal text
+pub const DEFAULT_MIN_CONTRAST: f64 = 4.5;
+
+// Nudges lightness of foreground colors until they have the target contrast
+// against all of their backgrounds. Foregrounds which can't get there stay as is.
+pub struct EnsureContrast {
+    // Foreground name -> backgrounds, resolved when created
+    backgrounds: BTreeMap<String, Vec<(u8, u8, u8)>>,
+    pub target: f64,
+}
+
+impl EnsureContrast {
+    // Pairs are (foreground, background), ones with relative or missing colors are skipped
+    pub fn new(theme: &CucumberBitwigTheme, pairs: &[(&str, &str)], target: f64) -> Self {
+        let mut backgrounds: BTreeMap<String, Vec<(u8, u8, u8)>> = BTreeMap::new();
+        for (foreground, background) in pairs {
+            let (Some(_), Some(background)) = (theme.absolute_color(foreground), theme.absolute_color(background)) else {
+                continue;
+            };
+            backgrounds
+                .entry(foreground.to_string())
+                .or_default()
+                .push((background.r, background.g, background.b));
+        }
+        EnsureContrast { backgrounds, target }
+    }
+
+    // Lowest contrast against backgrounds of the color, None if it isn't a foreground
+    pub fn min_contrast(&self, name: &str, rgb: (u8, u8, u8)) -> Option<f64> {
+        self.backgrounds
+            .get(name)?
+            .iter()
+            .map(|background| contrast_ratio(rgb, *background))
+            .min_by(f64::total_cmp)
+    }
+}
+
+impl ColorTransform for EnsureContrast {
+    fn apply(&self, name: &str, c: &ColorComponents) -> Option<ColorComponents> {
+        let (r, g, b, a) = absolute_rgba(c)?;
+        if self.min_contrast(name, (r, g, b))? >= self.target {
+            return None;
+        }
+        // Smallest lightness change first, in either direction
+        for step in 1..=100 {
+            for amount in [step as f64, -(step as f64)] {
+                let mut rgb = Rgb::from((r, g, b));
+                rgb.lighten(amount);
+                let nudged: (u8, u8, u8) = rgb.into();
+                if self.min_contrast(name, nudged)? >= self.target {
+                    return Some(ColorComponents::Rgbai(nudged.0, nudged.1, nudged.2, a));
+                }
+            }
+        }
+        log::log_message(&format!("{}: contrast {} can't be reached", name, self.target));
+        None
+    }
+}
+
+// Random opaque RGB for the listed colors, alpha is kept. Each color gets its
+// own generator seeded from the seed and its name, so the result doesn't
+// depend on which other colors are listed.
+pub struct Randomize {
+    pub seed: u64,
+    pub names: BTreeSet<String>,
+}
+
+impl ColorTransform for Randomize {
+    fn apply(&self, name: &str, c: &ColorComponents) -> Option<ColorComponents> {
+        if !self.names.contains(name) {
+            return None;
+        }
+        let (_, _, _, a) = absolute_rgba(c)?;
+        // FNV-1a, std hashers aren't guaranteed to be stable between releases
+        let name_hash = name
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3));
+        let mut rng = StdRng::seed_from_u64(self.seed ^ name_hash);
+        Some(ColorComponents::Rgbai(rng.gen(), rng.gen(), rng.gen(), a))
+    }
+}
+
+// Runs transforms one after another over every absolute theme color,
+// result is suitable to be used as changed colors
+pub fn transform_theme(
+    theme: &CucumberBitwigTheme,
+    transforms: &[&dyn ColorTransform],
+) -> BTreeMap<String, AbsoluteColor> {
+    let mut changed_colors = BTreeMap::new();
+
+    for (name, color) in &theme.named_colors {
+        let NamedColor::Absolute(AbsoluteColor { r, g, b, a }) = color else {
+            continue;
+        };
+        let original = ColorComponents::Rgbai(*r, *g, *b, *a);
+
+        let mut current = None;
+        for transform in transforms {
+            if let Some(transformed) = transform.apply(name, current.as_ref().unwrap_or(&original)) {
+                if !transformed.is_in_range() {
+                    log::log_message(&format!(
+                        "{}: transform produced out of range components {:?}, clamping",
+                        name, transformed
+                    ));
+                }
+                current = Some(transformed);
+            }
+        }
+
+        let Some((r, g, b, a)) = current.as_ref().and_then(absolute_rgba) else {
+            continue;
+        };
+        changed_colors.insert(name.clone(), AbsoluteColor { r, g, b, a });
+    }
+
+    changed_colors
+}
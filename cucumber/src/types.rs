@@ -1,9 +1,13 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+use colorsys::{Hsl, Rgb};
 use krakatau2::zip::ZipArchive;
 use serde::{Deserialize, Serialize};
 
-use crate::extract_general_goodies;
+use crate::{
+    adjust_hsv, blend_on_background, duplicate_definitions, extract_general_goodies_with_options,
+    unit_to_byte, ColorComponents, ExtractOptions, GeneralGoodies, Progress,
+};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum NamedColor {
@@ -11,7 +15,7 @@ pub enum NamedColor {
     Relative(Relative),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct AbsoluteColor {
     pub r: u8,
     pub g: u8,
@@ -19,6 +23,26 @@ pub struct AbsoluteColor {
     pub a: u8,
 }
 
+impl AbsoluteColor {
+    pub fn to_hex(&self) -> String {
+        format!("#{:02X}{:02X}{:02X}{:02X}", self.r, self.g, self.b, self.a)
+    }
+
+    pub fn blended_on(&self, background: (u8, u8, u8)) -> (u8, u8, u8) {
+        blend_on_background((self.r, self.g, self.b), self.a, background)
+    }
+
+    // 0..360, grays have hue 0
+    pub fn hue(&self) -> f64 {
+        Hsl::from(&Rgb::from((self.r, self.g, self.b))).get_hue()
+    }
+
+    // Perceived brightness, 0..255
+    pub fn brightness(&self) -> f64 {
+        0.299 * self.r as f64 + 0.587 * self.g as f64 + 0.114 * self.b as f64
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Relative {
     base: RelativeColorBase,
@@ -28,18 +52,51 @@ pub struct Relative {
     delta_alpha: f32, // -1..1
 }
 
+impl Relative {
+    pub fn base_name(&self) -> &str {
+        match &self.base {
+            RelativeColorBase::Internal(name) => name,
+            RelativeColorBase::External(name) => name,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum RelativeColorBase {
     Internal(String), // Use color defined in main Bitwig palette
     External(String), // Use color defined in external resource
 }
 
+// Edited colors are always written as RGBA. This decides what happens to
+// theme alpha of colors which were defined without one (gray, RGB, adjusted).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PromotionCompositing {
+    // Stay opaque like the original definition, theme alpha is ignored
+    #[default]
+    Absolute,
+    // Theme alpha is written, color gets blended over what's below it
+    Blended,
+}
+
+impl PromotionCompositing {
+    pub fn is_absolute(&self) -> bool {
+        *self == PromotionCompositing::Absolute
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PromotionCompositing::Absolute => "Absolute (opaque)",
+            PromotionCompositing::Blended => "Blended (keeps alpha)",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum UiTarget {
     Playhead,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum ColorConst {
     Black,
     White,
@@ -53,9 +110,49 @@ pub enum ColorConst {
     Yellow,
     Transparent,
     Violet,
+    // Not one of the stock raw colors, should be added as a new (FFFF) constant
+    Custom(AbsoluteColor),
 }
 
 impl ColorConst {
+    pub const ALL: [ColorConst; 12] = {
+        use ColorConst::*;
+        [
+            Black, White, Gray, DarkGray, LightGray, Red, Orange, Green, Blue, Yellow,
+            Transparent, Violet,
+        ]
+    };
+
+    pub fn to_comps(&self) -> (u8, u8, u8, u8) {
+        use ColorConst::*;
+        match self {
+            Black => (0, 0, 0, 255),
+            White => (255, 255, 255, 255),
+            Gray => (127, 127, 127, 255),
+            DarkGray => (63, 63, 63, 255),
+            LightGray => (191, 191, 191, 255),
+            Red => (255, 0, 0, 255),
+            Orange => (255, 140, 0, 255),
+            Green => (0, 255, 0, 255),
+            Blue => (0, 0, 255, 255),
+            Yellow => (255, 255, 0, 255),
+            Transparent => (0, 0, 0, 0),
+            Violet => (169, 169, 254, 255),
+            Custom(AbsoluteColor { r, g, b, a }) => (*r, *g, *b, *a),
+        }
+    }
+
+    // Components to use in the (FFFF) constructor of raw color class
+    pub fn to_float_comps(&self) -> (f32, f32, f32, f32) {
+        let (r, g, b, a) = self.to_comps();
+        (
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        )
+    }
+
     pub fn from_comps(r: u8, g: u8, b: u8, a: u8) -> Self {
         use ColorConst::*;
         match (r, g, b, a) {
@@ -71,32 +168,167 @@ impl ColorConst {
             (255, 255, 0, 255) => Yellow,
             (0, 0, 0, 0) => Transparent,
             (169, 169, 254, 255) => Violet,
-            x => panic!("can't build color const from {:?}", x),
+            (r, g, b, a) => Custom(AbsoluteColor { r, g, b, a }),
         }
     }
 }
 
+// Color computed by Bitwig from a raw color constant (ref_hsv_f palette method)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ColorAdjustment {
+    // Raw color constant name
+    pub base: String,
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+impl ColorAdjustment {
+    pub fn resolve(&self, base: &AbsoluteColor) -> AbsoluteColor {
+        let (r, g, b) = adjust_hsv((base.r, base.g, base.b), self.hue, self.saturation, self.value);
+        AbsoluteColor { r, g, b, a: 255 }
+    }
+}
+
+// Where the color is defined inside of the JAR
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ColorOrigin {
+    pub class_name: String,
+    pub method_idx: usize,
+    // ColorComponents variant of the definition, empty in older theme files
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub variant: String,
+}
+
+// Entry which couldn't be read or class file which couldn't be parsed,
+// colors defined in it are missing
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ParseFailure {
+    pub file_name: String,
+    pub error: String,
+}
+
+// Color definition which was found but can't be edited
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UneditableColor {
+    // None when the name is only known at runtime
+    pub name: Option<String>,
+    pub class_name: String,
+    pub method_name: String,
+    pub reason: String,
+}
+
+// Arranger (tracks area) background, most of translucent colors end up on top of it
+pub const ARRANGER_BACKGROUND: &str = "Light Timeline Background";
+
+// Fewer numbered colors than that are likely a coincidence, not a list
+const MIN_COLOR_LIST_LEN: usize = 3;
+
+// Colors to select first when a theme is opened, in order of preference
+pub const DEFAULT_SELECTED_COLORS: &[&str] = &["On", "Accent (default)"];
+
+// Limits for theme attribution, in characters
+pub const MAX_THEME_NAME_LEN: usize = 100;
+pub const MAX_AUTHOR_LEN: usize = 100;
+pub const MAX_DESCRIPTION_LEN: usize = 2000;
+const MAX_CREATED_LEN: usize = 40;
+
 #[derive(Default, Clone, Debug, Deserialize, Serialize)]
 pub struct CucumberBitwigTheme {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // Creation date, free-form, the editor writes YYYY-MM-DD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
     pub named_colors: BTreeMap<String, NamedColor>,
     pub constant_refs: BTreeMap<UiTarget, ColorConst>,
+    // Current color name -> names it had in older Bitwig versions
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, Vec<String>>,
+    // Only known for themes extracted from a JAR
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub origins: BTreeMap<String, ColorOrigin>,
+    // All definition sites of colors defined more than once, value is taken from the last one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub duplicate_origins: BTreeMap<String, Vec<ColorOrigin>>,
+    // Raw color constants (not part of the palette), by constant name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub raw_colors: BTreeMap<String, AbsoluteColor>,
+    // Exact 0..1 RGBA of raw constants defined with floats or doubles, bytes
+    // in raw_colors are rounded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub raw_color_floats: BTreeMap<String, [f64; 4]>,
+    // Colors which are adjusted raw color constants, by color name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub adjusted_colors: BTreeMap<String, ColorAdjustment>,
+    // Colors derived from another named color (StringAndAdjust): name -> base name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub derived_colors: BTreeMap<String, String>,
+    // Colors to write as plain values even if they look unchanged, used to
+    // turn derived and adjusted colors into directly editable ones
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub flattened_colors: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "PromotionCompositing::is_absolute")]
+    pub promotion_compositing: PromotionCompositing,
+    // Detection info: anchor -> class files which matched it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub anchors: BTreeMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parse_failures: Vec<ParseFailure>,
+    // Color name -> references to it found in the JAR, only for extracted themes
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub use_counts: BTreeMap<String, usize>,
+    // Read-only, only for extracted themes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uneditable_colors: Vec<UneditableColor>,
+    // Palette class the colors were read from, when the JAR has several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette_class: Option<String>,
+    // Editor state of the author, restored when the theme is opened
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub locked_colors: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_colors: Vec<String>,
 }
 
 impl CucumberBitwigTheme {
-    pub fn from_jar< R: std::io::Read + std::io::Seek >(zip: &mut ZipArchive<R>) -> Self {
-        let general_goodies = extract_general_goodies(zip).unwrap();
+    pub fn from_jar< R: std::io::Read + std::io::Seek >(zip: &mut ZipArchive<R>) -> anyhow::Result<Self> {
+        Self::from_jar_with_progress(zip, |_| {})
+    }
+
+    pub fn from_jar_with_progress< R: std::io::Read + std::io::Seek >(
+        zip: &mut ZipArchive<R>,
+        on_progress: impl FnMut(Progress),
+    ) -> anyhow::Result<Self> {
+        Self::from_jar_with_options(zip, &ExtractOptions::default(), on_progress)
+    }
+
+    pub fn from_jar_with_options< R: std::io::Read + std::io::Seek >(
+        zip: &mut ZipArchive<R>,
+        options: &ExtractOptions,
+        on_progress: impl FnMut(Progress),
+    ) -> anyhow::Result<Self> {
+        let general_goodies = extract_general_goodies_with_options(zip, options, on_progress)?;
+        Ok(Self::from_goodies(&general_goodies))
+    }
 
+    pub fn from_goodies(general_goodies: &GeneralGoodies) -> Self {
         let mut theme = CucumberBitwigTheme {
             name: "Extracted Theme".into(),
+            anchors: general_goodies.anchors.clone(),
+            parse_failures: general_goodies.parse_failures.clone(),
+            use_counts: general_goodies.color_uses.clone(),
+            uneditable_colors: general_goodies.uneditable_colors.clone(),
+            palette_class: general_goodies.palette_class.clone(),
             ..Default::default()
         };
 
-        let known_colors = general_goodies.named_colors.iter().map(|color| {
-            (color.color_name.clone(), color.components.clone())
-        }).collect();
+        let known_colors = general_goodies.known_colors();
 
-        for color in general_goodies.named_colors {
+        for color in &general_goodies.named_colors {
             let (r, g, b) = color.components.to_rgb(&known_colors);
             let a = color.components.alpha().unwrap_or(255);
             let named_color = NamedColor::Absolute(
@@ -105,19 +337,232 @@ impl CucumberBitwigTheme {
                 }
             );
             theme.named_colors.insert(color.color_name.clone(), named_color);
+            if let ColorComponents::StringAndAdjust(base, _, _, _) = &color.components {
+                theme.derived_colors.insert(color.color_name.clone(), base.clone());
+            }
+            if let ColorComponents::RefAndAdjust(base, hue, saturation, value) = &color.components {
+                theme.adjusted_colors.insert(
+                    color.color_name.clone(),
+                    ColorAdjustment {
+                        base: base.clone(),
+                        hue: *hue,
+                        saturation: *saturation,
+                        value: *value,
+                    },
+                );
+            }
+            theme.origins.insert(
+                color.color_name.clone(),
+                ColorOrigin {
+                    class_name: color.class_name.clone(),
+                    method_idx: color.method_idx,
+                    variant: color.components.variant_name().to_string(),
+                },
+            );
         }
 
-        let timeline_const_name = general_goodies.timeline_color_ref.const_name;
-        let timeline_const = general_goodies.raw_colors.constants.consts.iter().find(|cnst| {
-            cnst.const_name == timeline_const_name
-        }).unwrap();
-        let (r, g, b) = timeline_const.color_comps.to_rgb(&known_colors);
-        let a = timeline_const.color_comps.alpha().unwrap_or(255);
+        for (name, definitions) in duplicate_definitions(&general_goodies.named_colors) {
+            let origins = definitions
+                .into_iter()
+                .map(|color| ColorOrigin {
+                    class_name: color.class_name.clone(),
+                    method_idx: color.method_idx,
+                    variant: color.components.variant_name().to_string(),
+                })
+                .collect();
+            theme.duplicate_origins.insert(name.to_string(), origins);
+        }
+
+        for cnst in &general_goodies.raw_colors.constants.consts {
+            let (r, g, b) = cnst.color_comps.to_rgb(&known_colors);
+            let a = cnst.color_comps.alpha().unwrap_or(255);
+            theme.raw_colors.insert(cnst.const_name.clone(), AbsoluteColor { r, g, b, a });
+            if let Some(floats) = cnst.color_comps.unit_rgba() {
+                theme.raw_color_floats.insert(cnst.const_name.clone(), floats);
+            }
+        }
 
-        let timeline_color_const = ColorConst::from_comps(r, g, b, a);
+        // Without the playhead reference the theme just has no Playhead target
+        let timeline_const = general_goodies.timeline_color_ref.as_ref().and_then(|timeline_color_ref| {
+            general_goodies.raw_colors.constants.consts.iter().find(|cnst| {
+                cnst.const_name == timeline_color_ref.const_name
+            })
+        });
+        if let Some(timeline_const) = timeline_const {
+            let (r, g, b) = timeline_const.color_comps.to_rgb(&known_colors);
+            let a = timeline_const.color_comps.alpha().unwrap_or(255);
 
-        theme.constant_refs.insert(UiTarget::Playhead, timeline_color_const);
+            let timeline_color_const = ColorConst::from_comps(r, g, b, a);
+
+            theme.constant_refs.insert(UiTarget::Playhead, timeline_color_const);
+        }
 
         theme
     }
+
+    pub fn absolute_color(&self, name: &str) -> Option<&AbsoluteColor> {
+        match self.named_colors.get(name)? {
+            NamedColor::Absolute(color) => Some(color),
+            NamedColor::Relative(_) => None,
+        }
+    }
+
+    // First attribution field which is too long, None if all fit
+    pub fn metadata_problem(&self) -> Option<String> {
+        let fields = [
+            ("Name", Some(&self.name), MAX_THEME_NAME_LEN),
+            ("Author", self.author.as_ref(), MAX_AUTHOR_LEN),
+            ("Description", self.description.as_ref(), MAX_DESCRIPTION_LEN),
+            ("Created", self.created.as_ref(), MAX_CREATED_LEN),
+        ];
+        fields.into_iter().find_map(|(label, value, max_len)| {
+            let len = value?.chars().count();
+            (len > max_len).then(|| format!("{} is {} characters long, at most {} are allowed", label, len, max_len))
+        })
+    }
+
+    // Why the color can't be shown or saved as it is, None if it's fine
+    pub fn color_problem(&self, name: &str) -> Option<String> {
+        if let NamedColor::Relative(relative) = self.named_colors.get(name)? {
+            return Some(format!("Relative to {}, has no RGB value", relative.base_name()));
+        }
+        // Themes which weren't extracted from a JAR have no origins at all
+        if !self.origins.is_empty() && !self.origins.contains_key(name) {
+            return Some("Definition isn't found in the JAR, edits won't be saved".into());
+        }
+        if let Some(adjustment) = self.adjusted_colors.get(name) {
+            if !self.raw_colors.contains_key(&adjustment.base) {
+                return Some(format!("Base raw color {} is missing", adjustment.base));
+            }
+        }
+        if let Some(base) = self.derived_colors.get(name) {
+            if self.absolute_color(base).is_none() {
+                return Some(format!("Base color {} can't be resolved", base));
+            }
+        }
+        None
+    }
+
+    // First preferred color present in the theme, otherwise the first absolute one
+    pub fn initial_selection(&self, preferred: &[&str]) -> Option<(&str, &AbsoluteColor)> {
+        preferred
+            .iter()
+            .filter_map(|name| self.named_colors.get_key_value(*name))
+            .chain(self.named_colors.iter())
+            .find_map(|(name, color)| match color {
+                NamedColor::Absolute(color) => Some((name.as_str(), color)),
+                NamedColor::Relative(_) => None,
+            })
+    }
+
+    // Names of absolute colors which have exactly this value
+    pub fn colors_with_value(&self, value: &AbsoluteColor) -> Vec<&str> {
+        self.named_colors
+            .iter()
+            .filter_map(|(name, color)| match color {
+                NamedColor::Absolute(color) if color == value => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Colors which only differ by a trailing number, e.g. a list of default
+    // track colors. Common name -> color names in number order.
+    pub fn numbered_color_lists(&self) -> BTreeMap<String, Vec<String>> {
+        let mut lists: BTreeMap<String, Vec<(u32, String)>> = BTreeMap::new();
+        for name in self.named_colors.keys() {
+            let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+            let Ok(number) = name[prefix.len()..].parse::<u32>() else {
+                continue;
+            };
+            lists
+                .entry(prefix.trim_end().to_string())
+                .or_default()
+                .push((number, name.clone()));
+        }
+        lists
+            .into_iter()
+            .filter(|(_, names)| names.len() >= MIN_COLOR_LIST_LEN)
+            .map(|(prefix, mut names)| {
+                names.sort();
+                (prefix, names.into_iter().map(|(_, name)| name).collect())
+            })
+            .collect()
+    }
+
+    // Names of colors which are defined relative to the given one
+    pub fn references_to(&self, name: &str) -> Vec<&str> {
+        self.named_colors
+            .iter()
+            .filter_map(|(ref_name, color)| match color {
+                NamedColor::Relative(relative) if relative.base_name() == name => Some(ref_name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Base color name -> colors which change together with it, either relative
+    // ones or derived in the JAR. Adjusted raw constants aren't named colors.
+    pub fn dependency_graph(&self) -> BTreeMap<String, Vec<String>> {
+        let mut graph: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let relative = self.named_colors.iter().filter_map(|(name, color)| match color {
+            NamedColor::Relative(relative) => Some((name, relative.base_name())),
+            NamedColor::Absolute(_) => None,
+        });
+        let derived = self.derived_colors.iter().map(|(name, base)| (name, base.as_str()));
+        for (name, base) in relative.chain(derived) {
+            let dependents = graph.entry(base.to_string()).or_default();
+            if !dependents.contains(name) {
+                dependents.push(name.clone());
+            }
+        }
+        graph
+    }
+
+    pub fn apply_changes(&mut self, changed_colors: &BTreeMap<String, AbsoluteColor>) {
+        for (name, color) in changed_colors {
+            self.named_colors.insert(name.clone(), NamedColor::Absolute(color.clone()));
+        }
+    }
+
+    pub fn apply_raw_colors(&mut self, changed_raw_colors: &BTreeMap<String, AbsoluteColor>) {
+        for (name, color) in changed_raw_colors {
+            self.raw_colors.insert(name.clone(), color.clone());
+        }
+    }
+
+    // Byte values follow, so anything resolved against raw colors sees the change
+    pub fn apply_raw_color_floats(&mut self, changed_floats: &BTreeMap<String, [f64; 4]>) {
+        for (name, floats) in changed_floats {
+            let [r, g, b, a] = floats.map(unit_to_byte);
+            self.raw_colors.insert(name.clone(), AbsoluteColor { r, g, b, a });
+            self.raw_color_floats.insert(name.clone(), *floats);
+        }
+    }
+
+    // Resolved values of adjusted colors are updated too, so absolute colors stay in sync
+    pub fn apply_adjustments(&mut self, changed_adjustments: &BTreeMap<String, ColorAdjustment>) {
+        for (name, adjustment) in changed_adjustments {
+            if let Some(base) = self.raw_colors.get(&adjustment.base) {
+                let color = adjustment.resolve(base);
+                self.named_colors.insert(name.clone(), NamedColor::Absolute(color));
+            }
+            self.adjusted_colors.insert(name.clone(), adjustment.clone());
+        }
+    }
+
+    // Flattened colors keep their current value but lose what it was derived from
+    pub fn apply_flattening(&mut self, flattened: &BTreeSet<String>) {
+        for name in flattened {
+            self.adjusted_colors.remove(name);
+            self.derived_colors.remove(name);
+            self.flattened_colors.insert(name.clone());
+        }
+    }
+
+    pub fn apply_constant_refs(&mut self, changed_refs: &BTreeMap<UiTarget, ColorConst>) {
+        for (target, cnst) in changed_refs {
+            self.constant_refs.insert(target.clone(), cnst.clone());
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+use cucumber::types::ColorAdjustment;
+use leptos::{component, view, IntoView};
+use leptos::SignalGet;
+use leptos::SignalWith;
+
+use leptos::event_target_value;
+
+use super::editor::EditorState;
+
+#[derive(Clone, Copy)]
+enum HsvField {
+    Hue,
+    Saturation,
+    Value,
+}
+
+// Colors which Bitwig computes from a raw color constant (ref_hsv_f), they
+// stay references after patching instead of becoming plain values
+#[component]
+pub fn AdjustmentEditor(
+    state: EditorState,
+) -> impl IntoView {
+    let base_names = move || {
+        state.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.raw_colors.keys().cloned().collect::<Vec<_>>(),
+            _ => vec![],
+        })
+    };
+
+    view! {
+        { move || {
+            let name = state.current_color.get()?.name;
+            let adjustment = state.adjustment(&name)?;
+            let locked = state.is_locked(&name);
+            let preview = state
+                .raw_color(&adjustment.base)
+                .map(|base| adjustment.resolve(&base).to_hex());
+
+            let base_name = name.clone();
+            let base_adjustment = adjustment.clone();
+            let selected_base = adjustment.base.clone();
+            let number_input = move |field: HsvField, min: &'static str, max: &'static str, step: &'static str| {
+                let name = name.clone();
+                let adjustment = adjustment.clone();
+                let value = match field {
+                    HsvField::Hue => adjustment.hue,
+                    HsvField::Saturation => adjustment.saturation,
+                    HsvField::Value => adjustment.value,
+                };
+                view! {
+                    <input
+                        type="number"
+                        min=min
+                        max=max
+                        step=step
+                        disabled=locked
+                        prop:value=value
+                        on:change=move |e| {
+                            let Ok(new_value) = event_target_value(&e).parse::<f32>() else {
+                                return;
+                            };
+                            let mut adjustment = adjustment.clone();
+                            match field {
+                                HsvField::Hue => adjustment.hue = new_value,
+                                HsvField::Saturation => adjustment.saturation = new_value,
+                                HsvField::Value => adjustment.value = new_value,
+                            }
+                            state.set_adjustment(&name, adjustment);
+                        }
+                    />
+                }
+            };
+
+            Some(view! {
+                <div class="adjustment-editor">
+                    <h3>"Adjusted raw color"</h3>
+                    <label>
+                        "Base: "
+                        <select
+                            disabled=locked
+                            on:change=move |e| {
+                                let adjustment = ColorAdjustment {
+                                    base: event_target_value(&e),
+                                    ..base_adjustment.clone()
+                                };
+                                state.set_adjustment(&base_name, adjustment);
+                            }
+                        >
+                            { base_names().into_iter().map(|base| {
+                                let selected = base == selected_base;
+                                view! { <option value=base.clone() selected=selected>{ base }</option> }
+                            }).collect::<Vec<_>>() }
+                        </select>
+                    </label>
+                    <label>"H: " { number_input(HsvField::Hue, "-360", "360", "1") }</label>
+                    <label>"S: " { number_input(HsvField::Saturation, "-1", "1", "0.01") }</label>
+                    <label>"V: " { number_input(HsvField::Value, "-1", "1", "0.01") }</label>
+                    { preview.map(|hex| view! {
+                        <span class="adjustment-preview" style:background-color=hex.clone()>{ hex }</span>
+                    }) }
+                </div>
+            })
+        } }
+    }
+}
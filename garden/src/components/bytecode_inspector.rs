@@ -0,0 +1,63 @@
+use std::io::Cursor;
+
+use cucumber::disassemble_color_method;
+use leptos::{component, create_signal, event_target_checked, view, IntoView};
+use leptos::SignalGet;
+use leptos::SignalWith;
+
+use super::editor::EditorState;
+
+// Disassembly of the method defining selected color, for diagnosing edits
+// which don't take effect. Nothing is disassembled until it's opened.
+#[component]
+pub fn BytecodeInspector(
+    state: EditorState,
+) -> impl IntoView {
+    let (open, set_open) = create_signal(false);
+
+    let listing = move || {
+        if !open.get() {
+            return None;
+        }
+        let current = state.current_color.get()?;
+        let origin = state.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.origins.get(&current.name).cloned(),
+            _ => None,
+        });
+        let Some(origin) = origin else {
+            return Some(Err("Color origin is unknown, drop a JAR first".to_string()));
+        };
+        let result = state.jar.with_value(|jar| {
+            let Some(jar) = jar else {
+                return Err("Original JAR is not loaded".to_string());
+            };
+            let mut zip = zip::ZipArchive::new(Cursor::new(jar.as_slice())).map_err(|err| err.to_string())?;
+            disassemble_color_method(&mut zip, &origin.class_name, origin.method_idx, &current.name)
+                .map_err(|err| err.to_string())
+        });
+        Some(result)
+    };
+
+    view! {
+        <div class="bytecode-inspector">
+            <label>
+                <input
+                    type="checkbox"
+                    prop:checked=open
+                    on:change=move |e| set_open(event_target_checked(&e))
+                />
+                "Show bytecode"
+            </label>
+            { move || listing().map(|result| match result {
+                Ok(lines) => view! {
+                    <pre>
+                        { lines.into_iter().map(|line| view! {
+                            <div class:highlighted=line.highlighted>{ line.text }</div>
+                        }).collect::<Vec<_>>() }
+                    </pre>
+                }.into_view(),
+                Err(err) => view! { <span>{ err }</span> }.into_view(),
+            }) }
+        </div>
+    }
+}
@@ -1,39 +1,138 @@
-use leptos::{component, view, IntoView, ReadSignal, WriteSignal};
+use cucumber::types::AbsoluteColor;
+use leptos::{component, view, IntoView};
 use leptos::SignalGet;
+use leptos::SignalSet;
+use leptos::SignalWith;
 
 use leptos::event_target_value;
 
-use super::editor::CurrentColor;
+use super::editor::{CurrentColor, EditorState};
 
 #[component]
 pub fn ColorEditor(
-    maybe_color: ReadSignal<Option<CurrentColor>>,
-    set_current_color: WriteSignal<Option<CurrentColor>>,
+    state: EditorState,
 ) -> impl IntoView {
 
-    let set_color = move |r| {
-        let color = maybe_color.get().unwrap();
-        set_current_color(Some(CurrentColor { name: color.name, r: r, g: color.g, b: color.b, a: color.a }))
+    // Component index: 0 - red, 1 - green, 2 - blue
+    let set_component = move |idx: usize, value: u8| {
+        let color = state.current_color.get().unwrap();
+        let mut new_value = AbsoluteColor { r: color.r, g: color.g, b: color.b, a: color.a };
+        match idx {
+            0 => new_value.r = value,
+            1 => new_value.g = value,
+            _ => new_value.b = value,
+        }
+        state.set_color(&color.name, new_value);
+    };
+
+    // Slider and number input share the value, typed values are clamped to 0..255
+    let component_input = move |idx: usize, label: &'static str, value: u8, locked: bool| {
+        view! {
+            <label class="component">
+                { label }
+                <input
+                    type="range"
+                    min="0"
+                    max="255"
+                    disabled=locked
+                    prop:value=value
+                    on:input=move |e| {
+                        let new_value: u8 = event_target_value(&e).parse().unwrap();
+                        set_component(idx, new_value);
+                    }
+                />
+                <input
+                    type="number"
+                    min="0"
+                    max="255"
+                    disabled=locked
+                    prop:value=value
+                    on:change=move |e| {
+                        let Ok(new_value) = event_target_value(&e).trim().parse::<f64>() else {
+                            return;
+                        };
+                        set_component(idx, new_value.round().clamp(0.0, 255.0) as u8);
+                    }
+                />
+            </label>
+        }
     };
 
     view! {
         <div>
             <h3>"COLOR EDITOR:" { move || {
-                let color = maybe_color.get();
+                let color = state.current_color.get();
                 if let Some(color) = color {
+                    let locked = state.is_locked(&color.name);
+                    let matching = state
+                        .same_original_value(&color.name)
+                        .into_iter()
+                        .filter(|other| !state.is_locked(other))
+                        .count();
+                    let dependents = state.dependents_of(&color.name);
+                    let variant = state.theme.with(|theme| match theme {
+                        Some(Ok(theme)) => theme.origins.get(&color.name).map(|origin| origin.variant.clone()),
+                        _ => None,
+                    });
+                    // Variants with alpha are written as they are, others get promoted
+                    let promotion_note = variant
+                        .filter(|variant| !variant.is_empty() && !["Rgbai", "Rgbaf", "Rgbad"].contains(&variant.as_str()))
+                        .map(|variant| format!(
+                            "Defined as {} without alpha, edits are written as RGBA: {}",
+                            variant,
+                            state.promotion.get().label()
+                        ));
+                    let problem = state.color_problem(&color.name);
+                    let duplicates = state.theme.with(|theme| match theme {
+                        Some(Ok(theme)) => theme.duplicate_origins.get(&color.name).map(|origins| {
+                            origins
+                                .iter()
+                                .map(|origin| format!("{}#{}", origin.class_name, origin.method_idx))
+                                .collect::<Vec<_>>()
+                        }),
+                        _ => None,
+                    });
+                    let apply_name = color.name.clone();
+                    let apply_value = AbsoluteColor { r: color.r, g: color.g, b: color.b, a: color.a };
+                    // Original value is only shown, sliders stay disabled until toggled back
+                    let showing_original = state.is_showing_original(&color.name);
+                    let shown = match state.original_value(&color.name) {
+                        Some(original) if showing_original => CurrentColor::new(&color.name, &original),
+                        _ => color,
+                    };
+                    let disabled = locked || showing_original;
                     view! {
                         <div>
-                            <input
-                                type="range"
-                                min="0"
-                                max="255"
-                                prop:value=color.r
-                                on:input=move |e| {
-                                    let new_value: u8 = event_target_value(&e).parse().unwrap();
-                                    set_color(new_value);
+                            { component_input(0, "R", shown.r, disabled) }
+                            { component_input(1, "G", shown.g, disabled) }
+                            { component_input(2, "B", shown.b, disabled) }
+                            { format!("{:?}", shown) }
+                            { locked.then_some(" (LOCKED)") }
+                            { showing_original.then_some(" (ORIGINAL)") }
+                            { promotion_note.map(|note| view! { <p class="promotion-note">{ note }</p> }) }
+                            { problem.map(|problem| view! { <p class="problem-note">{ problem }</p> }) }
+                            { duplicates.map(|sites| view! {
+                                <p class="dependents-warning">
+                                    { format!("Defined in {} places, all of them get this value: {}", sites.len(), sites.join(", ")) }
+                                </p>
+                            }) }
+                            { (!dependents.is_empty()).then(|| view! {
+                                <p class="dependents-warning">
+                                    { format!(
+                                        "Derived from this color, will change with it: {}",
+                                        dependents.join(", ")
+                                    ) }
+                                </p>
+                            }) }
+                            <button
+                                disabled=disabled || matching == 0
+                                on:click=move |_| {
+                                    let count = state.apply_to_same_original_value(&apply_name, apply_value.clone());
+                                    state.set_status.set(format!("Applied to {count} colors"));
                                 }
-                            />
-                            { format!("{:?}", color) }
+                            >
+                                { format!("Apply to {matching} colors with the same original value") }
+                            </button>
                         </div>
                     }.into_view()
                 } else {
@@ -44,4 +143,4 @@ pub fn ColorEditor(
             } }</h3>
         </div>
     }
-}
\ No newline at end of file
+}
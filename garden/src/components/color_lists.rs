@@ -0,0 +1,55 @@
+use cucumber::types::AbsoluteColor;
+use leptos::{component, view, IntoView};
+use leptos::SignalWith;
+
+use leptos::event_target_value;
+
+use super::editor::{parse_hex_rgb, EditorState};
+
+// Numbered color lists (track colors and alike) gathered in one place,
+// each color can be edited right from its swatch
+#[component]
+pub fn ColorLists(
+    state: EditorState,
+) -> impl IntoView {
+    let lists = move || {
+        state.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.numbered_color_lists(),
+            _ => Default::default(),
+        })
+    };
+
+    view! {
+        <div class="color-lists">
+            <h3>"Color lists"</h3>
+            { move || lists().into_iter().map(|(prefix, names)| view! {
+                <div class="color-list">
+                    <strong>{ prefix }</strong>
+                    { names.into_iter().map(|name| {
+                        let color = state.current_value(&name);
+                        let hex = color
+                            .as_ref()
+                            .map(|color| format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b))
+                            .unwrap_or_default();
+                        let alpha = color.as_ref().map_or(255, |color| color.a);
+                        let locked = state.is_locked(&name);
+                        let title = name.clone();
+                        view! {
+                            <input
+                                type="color"
+                                title=title
+                                disabled=locked || color.is_none()
+                                prop:value=hex
+                                on:change=move |e| {
+                                    if let Some((r, g, b)) = parse_hex_rgb(&event_target_value(&e)) {
+                                        state.set_color(&name, AbsoluteColor { r, g, b, a: alpha });
+                                    }
+                                }
+                            />
+                        }
+                    }).collect::<Vec<_>>() }
+                </div>
+            }).collect::<Vec<_>>() }
+        </div>
+    }
+}
@@ -0,0 +1,112 @@
+use web_sys::KeyboardEvent;
+
+// Editor actions which have a keyboard shortcut
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorCommand {
+    ExportJson,
+    ToggleLeftPanel,
+    ToggleRightPanel,
+    ToggleBottomPanel,
+    // Drops changes made after the last export
+    ResetToSaved,
+    // Page colors mapped onto a few Bitwig colors
+    ThemeFromApp,
+    // Cycles through colors which can't be resolved or saved
+    NextProblemColor,
+    // Random values for colors shown in the list, with the seed from the toolbar
+    RandomizeShown,
+    // Selected color shows its original value until toggled again, edits stay
+    CompareOriginal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shortcut {
+    // Cmd on macOS
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    // KeyboardEvent.code, so shortcuts don't depend on keyboard layout
+    pub code: &'static str,
+}
+
+impl Shortcut {
+    const fn ctrl(code: &'static str) -> Self {
+        Shortcut { ctrl: true, alt: false, shift: false, code }
+    }
+
+    // Alt+Shift combinations aren't taken by browsers
+    const fn alt_shift(code: &'static str) -> Self {
+        Shortcut { ctrl: false, alt: true, shift: true, code }
+    }
+
+    pub fn matches(&self, event: &KeyboardEvent) -> bool {
+        (event.ctrl_key() || event.meta_key()) == self.ctrl
+            && event.alt_key() == self.alt
+            && event.shift_key() == self.shift
+            && event.code() == self.code
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        if self.ctrl {
+            parts.push("Ctrl");
+        }
+        if self.alt {
+            parts.push("Alt");
+        }
+        if self.shift {
+            parts.push("Shift");
+        }
+        parts.push(self.code.strip_prefix("Key").unwrap_or(self.code));
+        parts.join("+")
+    }
+}
+
+impl EditorCommand {
+    pub const ALL: [EditorCommand; 9] = [
+        EditorCommand::ExportJson,
+        EditorCommand::ToggleLeftPanel,
+        EditorCommand::ToggleRightPanel,
+        EditorCommand::ToggleBottomPanel,
+        EditorCommand::ResetToSaved,
+        EditorCommand::ThemeFromApp,
+        EditorCommand::NextProblemColor,
+        EditorCommand::RandomizeShown,
+        EditorCommand::CompareOriginal,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EditorCommand::ExportJson => "Export JSON",
+            EditorCommand::ToggleLeftPanel => "Toggle colors panel",
+            EditorCommand::ToggleRightPanel => "Toggle tools panel",
+            EditorCommand::ToggleBottomPanel => "Toggle status panel",
+            EditorCommand::ResetToSaved => "Reset to last export",
+            EditorCommand::ThemeFromApp => "Theme from app colors",
+            EditorCommand::NextProblemColor => "Next problem color",
+            EditorCommand::RandomizeShown => "Randomize shown colors",
+            EditorCommand::CompareOriginal => "Compare with original value",
+        }
+    }
+
+    // Keep these distinct, from_event takes the first match
+    pub fn shortcut(&self) -> Shortcut {
+        match self {
+            EditorCommand::ExportJson => Shortcut::ctrl("KeyE"),
+            EditorCommand::ToggleLeftPanel => Shortcut::alt_shift("KeyL"),
+            EditorCommand::ToggleRightPanel => Shortcut::alt_shift("KeyR"),
+            EditorCommand::ToggleBottomPanel => Shortcut::alt_shift("KeyB"),
+            EditorCommand::ResetToSaved => Shortcut::alt_shift("KeyS"),
+            EditorCommand::ThemeFromApp => Shortcut::alt_shift("KeyT"),
+            EditorCommand::NextProblemColor => Shortcut::alt_shift("KeyP"),
+            EditorCommand::RandomizeShown => Shortcut::alt_shift("KeyX"),
+            EditorCommand::CompareOriginal => Shortcut::alt_shift("KeyO"),
+        }
+    }
+
+    pub fn from_event(event: &KeyboardEvent) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|command| command.shortcut().matches(event))
+    }
+}
@@ -0,0 +1,170 @@
+use leptos::{component, view, IntoView, ReadSignal, WriteSignal};
+use leptos::SignalGet;
+use leptos::SignalSet;
+use leptos::SignalWith;
+
+use super::editor::EditorState;
+
+#[derive(Debug, Clone)]
+pub struct ContextMenuTarget {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+pub fn copy_to_clipboard(text: &str) {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    // Clipboard API is still unstable in web-sys, so call it dynamically
+    let navigator = leptos::window().navigator();
+    let Ok(clipboard) = js_sys::Reflect::get(&navigator, &JsValue::from_str("clipboard")) else {
+        return;
+    };
+    let Ok(write_text) = js_sys::Reflect::get(&clipboard, &JsValue::from_str("writeText")) else {
+        return;
+    };
+    let write_text: js_sys::Function = write_text.unchecked_into();
+    let _ = write_text.call1(&clipboard, &JsValue::from_str(text));
+}
+
+#[component]
+pub fn ColorContextMenu(
+    state: EditorState,
+    target: ReadSignal<Option<ContextMenuTarget>>,
+    set_target: WriteSignal<Option<ContextMenuTarget>>,
+    set_multi_assign: WriteSignal<Option<String>>,
+) -> impl IntoView {
+    move || target.get().map(|target| {
+        let name = target.name.clone();
+        let locked = state.is_locked(&name);
+        let pinned = state.is_pinned(&name);
+
+        let copy_hex = {
+            let name = name.clone();
+            move |_| {
+                if let Some(color) = state.current_value(&name) {
+                    copy_to_clipboard(&color.to_hex());
+                    state.set_status.set(format!("Copied {}", color.to_hex()));
+                }
+                set_target.set(None);
+            }
+        };
+
+        let copy_name = {
+            let name = name.clone();
+            move |_| {
+                copy_to_clipboard(&name);
+                state.set_status.set(format!("Copied \"{name}\""));
+                set_target.set(None);
+            }
+        };
+
+        let set_equal_to = {
+            let name = name.clone();
+            move |_| {
+                set_target.set(None);
+                let Ok(Some(source)) = leptos::window().prompt_with_message("Set equal to color:") else {
+                    return;
+                };
+                match state.current_value(&source) {
+                    Some(color) => {
+                        if state.set_color(&name, color) {
+                            state.set_status.set(format!("{name} set equal to {source}"));
+                        }
+                    }
+                    None => state.set_status.set(format!("Unknown color: {source}")),
+                }
+            }
+        };
+
+        let resolvable = state.current_value(&name).is_some();
+        let swap_with = {
+            let name = name.clone();
+            move |_| {
+                set_target.set(None);
+                let Ok(Some(other)) = leptos::window().prompt_with_message("Swap with color:") else {
+                    return;
+                };
+                if state.swap_colors(&name, &other) {
+                    state.set_status.set(format!("Swapped {name} and {other}"));
+                }
+            }
+        };
+
+        let copy_to_many = {
+            let name = name.clone();
+            move |_| {
+                set_target.set(None);
+                set_multi_assign.set(Some(name.clone()));
+            }
+        };
+
+        let revert = {
+            let name = name.clone();
+            move |_| {
+                if state.revert_color(&name) {
+                    state.set_status.set(format!("Reverted {name}"));
+                }
+                set_target.set(None);
+            }
+        };
+
+        let toggle_lock = {
+            let name = name.clone();
+            move |_| {
+                state.toggle_lock(&name);
+                set_target.set(None);
+            }
+        };
+
+        let toggle_pin = {
+            let name = name.clone();
+            move |_| {
+                state.toggle_pin(&name);
+                set_target.set(None);
+            }
+        };
+
+        let show_references = {
+            let name = name.clone();
+            move |_| {
+                let references = state.theme.with(|theme| match theme {
+                    Some(Ok(theme)) => theme.references_to(&name).join(", "),
+                    _ => String::new(),
+                });
+                if references.is_empty() {
+                    state.set_status.set(format!("No colors reference {name}"));
+                } else {
+                    state.set_status.set(format!("Referencing {name}: {references}"));
+                }
+                set_target.set(None);
+            }
+        };
+
+        view! {
+            <div
+                class="context-menu"
+                style:left=format!("{}px", target.x)
+                style:top=format!("{}px", target.y)
+                on:click=|e| e.stop_propagation()
+            >
+                <strong>{ name }</strong>
+                <button on:click=copy_hex>"Copy hex"</button>
+                <button on:click=copy_name>"Copy name"</button>
+                <button on:click=set_equal_to>"Set equal to..."</button>
+                <button
+                    on:click=swap_with
+                    disabled=!resolvable
+                    title=(!resolvable).then_some("This color has no value to swap")
+                >
+                    "Swap with..."
+                </button>
+                <button on:click=copy_to_many disabled=!resolvable>"Copy to colors..."</button>
+                <button on:click=revert>"Revert"</button>
+                <button on:click=toggle_lock>{ if locked { "Unlock" } else { "Lock" } }</button>
+                <button on:click=toggle_pin>{ if pinned { "Unpin" } else { "Pin to quick bar" } }</button>
+                <button on:click=show_references>"Show references"</button>
+            </div>
+        }
+    })
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use leptos::{component, create_signal, view, IntoView};
+use leptos::SignalGet;
+use leptos::SignalUpdate;
+use leptos::SignalWith;
+
+use super::editor::EditorState;
+
+// Which colors are computed from which: derived colors (StringAndAdjust) and
+// relative ones by base color, adjusted raw constants (RefAndAdjust) by constant.
+// Only built while the panel is shown.
+#[component]
+pub fn DependencyGraph(
+    state: EditorState,
+) -> impl IntoView {
+    let (shown, set_shown) = create_signal(false);
+
+    let edges = move || {
+        state.theme.with(|theme| match theme {
+            Some(Ok(theme)) => {
+                let mut edges = theme
+                    .dependency_graph()
+                    .into_iter()
+                    .map(|(base, dependents)| ((base, false), dependents))
+                    .collect::<BTreeMap<_, _>>();
+                for (name, adjustment) in &theme.adjusted_colors {
+                    edges
+                        .entry((adjustment.base.clone(), true))
+                        .or_insert_with(Vec::new)
+                        .push(name.clone());
+                }
+                edges
+            }
+            _ => BTreeMap::new(),
+        })
+    };
+
+    view! {
+        <div class="dependency-graph">
+            <h3>
+                "Color references "
+                <button on:click=move |_| set_shown.update(|shown| *shown = !*shown)>
+                    { move || if shown.get() { "Hide" } else { "Show" } }
+                </button>
+            </h3>
+            { move || shown.get().then(|| {
+                let edges = edges();
+                if edges.is_empty() {
+                    return view! { <p>"No colors reference other colors"</p> }.into_view();
+                }
+                edges.into_iter().map(|((base, is_raw), dependents)| {
+                    let base_label = if is_raw { format!("{base} (raw)") } else { base.clone() };
+                    view! {
+                        <div class="dependency">
+                            { if is_raw {
+                                view! { <span class="base">{ base_label }</span> }.into_view()
+                            } else {
+                                view! {
+                                    <button class="base" on:click=move |_| state.select_color(&base)>
+                                        { base_label }
+                                    </button>
+                                }.into_view()
+                            } }
+                            " → "
+                            { dependents.into_iter().map(|name| {
+                                let label = name.clone();
+                                view! {
+                                    <button on:click=move |_| state.select_color(&name)>{ label }</button>
+                                }
+                            }).collect::<Vec<_>>() }
+                        </div>
+                    }
+                }).collect::<Vec<_>>().into_view()
+            }) }
+        </div>
+    }
+}
@@ -1,32 +1,312 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Cursor;
+use std::time::Duration;
 
-use cucumber::types::{AbsoluteColor, CucumberBitwigTheme};
-use leptos::{create_resource, create_signal, ServerFnError};
-use leptos::{component, create_node_ref, html::Div, logging, view, IntoView, server};
-use leptos_use::{use_drop_zone_with_options, UseDropZoneEvent, UseDropZoneOptions, UseDropZoneReturn};
+use cucumber::exchange;
+use cucumber::output::{read_patch_marker, read_release_info, ReleaseInfo};
+use cucumber::{simulate_cvd, AnchorSet, ColorVisionDeficiency, ExtractOptions, JarScan, Progress, Stage, PALETTE_ANCHOR_NAME};
+use cucumber::transform::{transform_theme, ColorTransform, Desaturate, EnsureContrast, HueRotate, Invert, NormalizeAlpha, DEFAULT_MIN_CONTRAST, KNOWN_CONTRAST_PAIRS, Randomize};
+use cucumber::types::{AbsoluteColor, ColorAdjustment, ColorConst, ColorOrigin, PromotionCompositing, CucumberBitwigTheme, UiTarget, ARRANGER_BACKGROUND, DEFAULT_SELECTED_COLORS};
+use leptos::{create_effect, create_memo, create_resource, create_signal, ev, store_value, Memo, ReadSignal, Resource, ServerFnError, StoredValue, WriteSignal};
+use leptos::{component, create_node_ref, set_timeout, spawn_local, html::Div, logging, view, IntoView, server};
+use leptos_router::use_query_map;
+use leptos_use::{use_document, use_drop_zone_with_options, use_event_listener, UseDropZoneEvent, UseDropZoneOptions, UseDropZoneReturn};
 
 use leptos::Suspense;
+use leptos::SignalGet;
+use leptos::SignalGetUntracked;
+use leptos::SignalSet;
 use leptos::SignalUpdate;
+use leptos::SignalWith;
+use leptos::SignalWithUntracked;
+use leptos::event_target_checked;
+use leptos::event_target_value;
 use cucumber::types::NamedColor;
+use crate::components::adjustment_editor::AdjustmentEditor;
+use crate::components::bytecode_inspector::BytecodeInspector;
 use crate::components::color_editor::ColorEditor;
+use crate::components::color_lists::ColorLists;
+use crate::components::commands::EditorCommand;
+use crate::components::context_menu::{copy_to_clipboard, ColorContextMenu, ContextMenuTarget};
+use crate::components::dependency_graph::DependencyGraph;
+use crate::components::eyedropper::Eyedropper;
+use crate::components::jar_compare::JarCompare;
+use crate::components::multi_assign::MultiAssignDialog;
+use crate::components::playhead_picker::PlayheadPicker;
+use crate::components::raw_colors_panel::RawColorsPanel;
+use crate::components::theme_metadata::ThemeMetadataDialog;
 
-fn handle_jar_blob(data: Vec<u8>) -> CucumberBitwigTheme {
+pub fn read_file_bytes(file: &web_sys::File, on_loaded: impl FnOnce(Vec<u8>) + 'static) {
+    use web_sys::FileReader;
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen::JsCast;
+    use web_sys::Event;
+
+    let mut on_loaded = Some(on_loaded);
+    let reader = FileReader::new().unwrap();
+    let onloadend = Closure::wrap(Box::new(move |event: Event| {
+        let reader: FileReader = event.target().unwrap().unchecked_into();
+        if reader.ready_state() == FileReader::DONE {
+            let result = reader.result().unwrap();
+            let array = js_sys::Uint8Array::new(&result);
+            let bytes = array.to_vec();
+            logging::log!("Read {} bytes", bytes.len());
+            if let Some(on_loaded) = on_loaded.take() {
+                on_loaded(bytes);
+            }
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    reader.set_onloadend(Some(onloadend.as_ref().unchecked_ref()));
+    reader.read_as_array_buffer(file).unwrap();
+    onloadend.forget();
+}
+
+// Scans the JAR a batch of entries at a time, each batch in a task of its
+// own, so the browser gets to repaint progress in between. on_done gets the
+// theme once the whole JAR is scanned.
+pub fn handle_jar_blob(
+    data: Vec<u8>,
+    anchors: AnchorSet,
+    palette_class: Option<String>,
+    on_progress: impl FnMut(Progress) + 'static,
+    on_done: impl FnOnce(CucumberBitwigTheme) + 'static,
+    on_error: impl FnOnce(String) + 'static,
+) {
     logging::log!("STG 1");
     let reader = Cursor::new(data);
     logging::log!("STG 2");
-    let mut zip = zip::ZipArchive::new(reader).unwrap();
+    let zip = match zip::ZipArchive::new(reader) {
+        Ok(zip) => zip,
+        Err(err) => return on_error(format!("Not a JAR: {err}")),
+    };
     logging::log!("STG 3");
-    CucumberBitwigTheme::from_jar(&mut zip)
+    // Strict, so missing colors can be explained by classes which failed to parse
+    let options = ExtractOptions {
+        strict: true,
+        anchors,
+        palette_class,
+        ..Default::default()
+    };
+    let scan = JarScan::new(&zip, options);
+    scan_jar_step(zip, scan, on_progress, on_done, on_error);
+}
+
+fn scan_jar_step(
+    mut zip: zip::ZipArchive<Cursor<Vec<u8>>>,
+    mut scan: JarScan,
+    mut on_progress: impl FnMut(Progress) + 'static,
+    on_done: impl FnOnce(CucumberBitwigTheme) + 'static,
+    on_error: impl FnOnce(String) + 'static,
+) {
+    match scan.step(&mut zip) {
+        Ok(Some(progress)) => {
+            on_progress(progress);
+            // Even a zero timeout waits for the next task, rendering goes first
+            set_timeout(
+                move || scan_jar_step(zip, scan, on_progress, on_done, on_error),
+                Duration::ZERO,
+            );
+        }
+        Ok(None) => match scan.finish() {
+            Ok(goodies) => on_done(CucumberBitwigTheme::from_goodies(&goodies)),
+            Err(err) => on_error(format!("Failed to scan JAR: {err}")),
+        },
+        Err(err) => on_error(format!("Failed to scan JAR: {err}")),
+    }
+}
+
+pub fn parse_hex_rgb(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let comp = |idx: usize| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok();
+    Some((comp(0)?, comp(2)?, comp(4)?))
+}
+
+// Computed style colors, "rgb(r, g, b)" or "rgba(r, g, b, a)" with alpha in 0..1
+fn parse_css_rgb(value: &str) -> Option<AbsoluteColor> {
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let parts = args.split(',').map(str::trim).collect::<Vec<_>>();
+    let comp = |idx: usize| parts.get(idx)?.parse::<u8>().ok();
+    let a = match parts.get(3) {
+        Some(alpha) => (alpha.parse::<f32>().ok()?.clamp(0.0, 1.0) * 255.0).round() as u8,
+        None => 255,
+    };
+    Some(AbsoluteColor { r: comp(0)?, g: comp(1)?, b: comp(2)?, a })
+}
+
+// CSS properties of the page body and Bitwig colors which get their value
+const APP_COLOR_TARGETS: &[(&str, &[&str])] = &[
+    ("background-color", &[ARRANGER_BACKGROUND]),
+    ("accent-color", DEFAULT_SELECTED_COLORS),
+];
+
+// Colors the editor page itself is drawn with, by CSS property
+fn sample_app_colors() -> Vec<(&'static str, AbsoluteColor)> {
+    let Some(body) = leptos::document().body() else {
+        return vec![];
+    };
+    let Ok(Some(style)) = leptos::window().get_computed_style(&body) else {
+        return vec![];
+    };
+    APP_COLOR_TARGETS
+        .iter()
+        .filter_map(|(property, _)| {
+            let value = style.get_property_value(property).ok()?;
+            let color = match parse_css_rgb(&value)? {
+                // Transparent body shows the browser canvas, which is white
+                color if *property == "background-color" && color.a == 0 => {
+                    AbsoluteColor { r: 255, g: 255, b: 255, a: 255 }
+                }
+                color => color,
+            };
+            Some((*property, color))
+        })
+        .collect()
+}
+
+// Whitespace separated terms, all of them should match. Terms prefixed
+// with `class:` are matched against the class defining the color.
+fn color_matches_filter(filter: &str, name: &str, class_name: Option<&str>) -> bool {
+    let name = name.to_lowercase();
+    let class_name = class_name.map(str::to_lowercase);
+    filter.to_lowercase().split_whitespace().all(|term| match term.strip_prefix("class:") {
+        Some(class_term) => class_name.as_ref().is_some_and(|class_name| class_name.contains(class_term)),
+        None => name.contains(term),
+    })
+}
+
+// Hover text of a color swatch, facts about its definition in the JAR
+fn origin_tooltip(name: &str, origin: Option<&ColorOrigin>, alpha: Option<u8>) -> String {
+    let mut lines = vec![name.to_string()];
+    match origin {
+        Some(origin) => {
+            lines.push(format!("Class: {}", origin.class_name));
+            lines.push(format!("Method: #{}", origin.method_idx));
+            if !origin.variant.is_empty() {
+                lines.push(format!("Variant: {}", origin.variant));
+            }
+        }
+        None => lines.push("Origin unknown".into()),
+    }
+    let compositing = match alpha {
+        Some(255) => "opaque",
+        Some(_) => "alpha blended over background",
+        None => "unknown",
+    };
+    lines.push(format!("Compositing: {}", compositing));
+    lines.join("\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorSort {
+    Name,
+    Hue,
+    Brightness,
+    // Most referenced first
+    Uses,
+}
+
+impl ColorSort {
+    fn from_value(value: &str) -> Self {
+        match value {
+            "hue" => ColorSort::Hue,
+            "brightness" => ColorSort::Brightness,
+            "uses" => ColorSort::Uses,
+            _ => ColorSort::Name,
+        }
+    }
+
+    // None for colors which can't be resolved, they go last
+    fn key(&self, color: Option<&AbsoluteColor>, uses: Option<usize>) -> Option<f64> {
+        match self {
+            ColorSort::Name => Some(0.0),
+            ColorSort::Hue => color.map(AbsoluteColor::hue),
+            ColorSort::Brightness => color.map(AbsoluteColor::brightness),
+            ColorSort::Uses => uses.map(|uses| -(uses as f64)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LoadingState {
+    ReadingFile,
+    // With seconds left in the stage, once it can be estimated
+    Scanning(Progress, Option<f64>),
 }
 
 #[server(GetTheme, "/api")]
 pub async fn get_theme(theme_name: String) -> Result<CucumberBitwigTheme, ServerFnError> {
     // TODO: Make this secure (disallow fs path injection)
     let text = tokio::fs::read_to_string(format!("storage/{}.json", theme_name)).await?;
-    let theme: CucumberBitwigTheme = serde_json::from_str(&text).unwrap();
+    let theme = exchange::import_theme_json(&text).unwrap();
     Ok(theme)
 }
 
+fn download_text(file_name: &str, mime_type: &str, text: &str) {
+    use wasm_bindgen::{JsCast, JsValue};
+    use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(text));
+    let blob = Blob::new_with_str_sequence_and_options(&parts, BlobPropertyBag::new().type_(mime_type)).unwrap();
+    let url = Url::create_object_url_with_blob(&blob).unwrap();
+
+    let anchor: HtmlAnchorElement = leptos::document().create_element("a").unwrap().unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    Url::revoke_object_url(&url).unwrap();
+}
+
+// Local storage key of pinned color names (newline separated)
+const PINNED_COLORS_KEY: &str = "cucumber.pinned_colors";
+
+fn load_pinned_colors() -> Vec<String> {
+    let storage = leptos::window().local_storage().ok().flatten();
+    let text = storage.and_then(|storage| storage.get_item(PINNED_COLORS_KEY).ok().flatten());
+    text.map(|text| text.lines().map(String::from).collect()).unwrap_or_default()
+}
+
+fn save_pinned_colors(pinned: &[String]) {
+    if let Ok(Some(storage)) = leptos::window().local_storage() {
+        let _ = storage.set_item(PINNED_COLORS_KEY, &pinned.join("\n"));
+    }
+}
+
+// Local storage key of custom anchors: palette, init and raw color lines
+const CUSTOM_ANCHORS_KEY: &str = "cucumber.custom_anchors";
+
+fn load_custom_anchors() -> Option<AnchorSet> {
+    let storage = leptos::window().local_storage().ok().flatten()?;
+    let text = storage.get_item(CUSTOM_ANCHORS_KEY).ok().flatten()?;
+    let mut lines = text.lines();
+    Some(AnchorSet {
+        palette: lines.next()?.to_string(),
+        init: lines.next()?.to_string(),
+        raw_color: lines.next()?.parse().ok()?,
+    })
+}
+
+// Defaults aren't stored, so they keep following detection updates
+fn save_custom_anchors(anchors: &AnchorSet) {
+    let Ok(Some(storage)) = leptos::window().local_storage() else {
+        return;
+    };
+    if *anchors == AnchorSet::default() {
+        let _ = storage.remove_item(CUSTOM_ANCHORS_KEY);
+    } else {
+        let text = format!("{}\n{}\n{}", anchors.palette, anchors.init, anchors.raw_color);
+        let _ = storage.set_item(CUSTOM_ANCHORS_KEY, &text);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CurrentColor {
     pub name: String,
@@ -36,6 +316,482 @@ pub struct CurrentColor {
     pub a: u8,
 }
 
+impl CurrentColor {
+    pub fn new(name: &str, color: &AbsoluteColor) -> Self {
+        let AbsoluteColor { r, g, b, a } = *color;
+        CurrentColor { name: name.to_string(), r, g, b, a }
+    }
+}
+
+/// Signals shared by the editor components. All color edits should go
+/// through its methods, so locks and the selected color stay consistent.
+#[derive(Clone, Copy)]
+pub struct EditorState {
+    pub theme: Resource<(), Result<CucumberBitwigTheme, ServerFnError>>,
+    pub current_color: ReadSignal<Option<CurrentColor>>,
+    pub set_current_color: WriteSignal<Option<CurrentColor>>,
+    pub changed_colors: ReadSignal<BTreeMap<String, AbsoluteColor>>,
+    pub set_changed_colors: WriteSignal<BTreeMap<String, AbsoluteColor>>,
+    // Changed colors as of the last export, to go back to after a failed experiment
+    pub saved_colors: ReadSignal<BTreeMap<String, AbsoluteColor>>,
+    pub set_saved_colors: WriteSignal<BTreeMap<String, AbsoluteColor>>,
+    // Names of changed colors, least recently edited first
+    pub edit_order: ReadSignal<Vec<String>>,
+    pub set_edit_order: WriteSignal<Vec<String>>,
+    pub changed_constant_refs: ReadSignal<BTreeMap<UiTarget, ColorConst>>,
+    pub set_changed_constant_refs: WriteSignal<BTreeMap<UiTarget, ColorConst>>,
+    pub changed_raw_colors: ReadSignal<BTreeMap<String, AbsoluteColor>>,
+    pub set_changed_raw_colors: WriteSignal<BTreeMap<String, AbsoluteColor>>,
+    // Exact 0..1 values of raw colors defined with floats
+    pub changed_raw_floats: ReadSignal<BTreeMap<String, [f64; 4]>>,
+    pub set_changed_raw_floats: WriteSignal<BTreeMap<String, [f64; 4]>>,
+    pub changed_adjustments: ReadSignal<BTreeMap<String, ColorAdjustment>>,
+    pub set_changed_adjustments: WriteSignal<BTreeMap<String, ColorAdjustment>>,
+    // Derived or adjusted colors which should be written as plain values
+    pub flattened_colors: ReadSignal<BTreeSet<String>>,
+    pub set_flattened_colors: WriteSignal<BTreeSet<String>>,
+    pub locked_colors: ReadSignal<BTreeSet<String>>,
+    pub set_locked_colors: WriteSignal<BTreeSet<String>>,
+    pub pinned_colors: ReadSignal<Vec<String>>,
+    pub set_pinned_colors: WriteSignal<Vec<String>>,
+    // Used for every JAR loaded in the editor
+    // Alpha handling for edited colors which were defined without alpha
+    pub promotion: ReadSignal<PromotionCompositing>,
+    pub set_promotion: WriteSignal<PromotionCompositing>,
+    // Base color -> colors changing with it, computed once per loaded theme
+    pub dependents: Memo<BTreeMap<String, Vec<String>>>,
+    pub anchors: ReadSignal<AnchorSet>,
+    pub set_anchors: WriteSignal<AnchorSet>,
+    pub set_status: WriteSignal<String>,
+    // View only, the selected color is shown with its original value
+    pub comparing_original: ReadSignal<bool>,
+    pub set_comparing_original: WriteSignal<bool>,
+    // Bytes of the last dropped JAR
+    pub jar: StoredValue<Option<Vec<u8>>>,
+}
+
+impl EditorState {
+    pub fn original_value(&self, name: &str) -> Option<AbsoluteColor> {
+        self.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.absolute_color(name).cloned(),
+            _ => None,
+        })
+    }
+
+    pub fn current_value(&self, name: &str) -> Option<AbsoluteColor> {
+        self.changed_colors
+            .with(|changed| changed.get(name).cloned())
+            .or_else(|| self.original_value(name))
+    }
+
+    pub fn is_showing_original(&self, name: &str) -> bool {
+        self.comparing_original.get()
+            && self.current_color.with(|current| current.as_ref().is_some_and(|current| current.name == name))
+    }
+
+    pub fn constant_ref(&self, target: &UiTarget) -> Option<ColorConst> {
+        self.changed_constant_refs
+            .with(|refs| refs.get(target).cloned())
+            .or_else(|| {
+                self.theme.with(|theme| match theme {
+                    Some(Ok(theme)) => theme.constant_refs.get(target).cloned(),
+                    _ => None,
+                })
+            })
+    }
+
+    pub fn set_constant_ref(&self, target: UiTarget, cnst: ColorConst) {
+        self.set_changed_constant_refs.update(|refs| {
+            refs.insert(target, cnst);
+        });
+    }
+
+    pub fn raw_color(&self, name: &str) -> Option<AbsoluteColor> {
+        self.changed_raw_colors
+            .with(|changed| changed.get(name).cloned())
+            .or_else(|| {
+                self.theme.with(|theme| match theme {
+                    Some(Ok(theme)) => theme.raw_colors.get(name).cloned(),
+                    _ => None,
+                })
+            })
+    }
+
+    pub fn set_raw_color(&self, name: &str, color: AbsoluteColor) {
+        // Byte edit replaces a float one
+        self.set_changed_raw_floats.update(|changed| {
+            changed.remove(name);
+        });
+        self.set_changed_raw_colors.update(|changed| {
+            changed.insert(name.to_string(), color);
+        });
+    }
+
+    // None for raw colors defined with integers
+    pub fn raw_floats(&self, name: &str) -> Option<[f64; 4]> {
+        self.changed_raw_floats
+            .with(|changed| changed.get(name).copied())
+            .or_else(|| {
+                self.theme.with(|theme| match theme {
+                    Some(Ok(theme)) => theme.raw_color_floats.get(name).copied(),
+                    _ => None,
+                })
+            })
+    }
+
+    // Byte value follows, so swatches and adjusted colors stay in sync
+    pub fn set_raw_floats(&self, name: &str, floats: [f64; 4]) {
+        let [r, g, b, a] = floats.map(|comp| (comp * 255.0).clamp(0.0, 255.0) as u8);
+        self.set_changed_raw_colors.update(|changed| {
+            changed.insert(name.to_string(), AbsoluteColor { r, g, b, a });
+        });
+        self.set_changed_raw_floats.update(|changed| {
+            changed.insert(name.to_string(), floats);
+        });
+    }
+
+    pub fn adjustment(&self, name: &str) -> Option<ColorAdjustment> {
+        self.changed_adjustments
+            .with(|changed| changed.get(name).cloned())
+            .or_else(|| {
+                self.theme.with(|theme| match theme {
+                    Some(Ok(theme)) => theme.adjusted_colors.get(name).cloned(),
+                    _ => None,
+                })
+            })
+    }
+
+    // Resolved value goes to changed colors too, so swatches show the result
+    pub fn set_adjustment(&self, name: &str, adjustment: ColorAdjustment) -> bool {
+        if self.is_locked(name) {
+            self.set_status.set(format!("{name} is locked"));
+            return false;
+        }
+        let Some(base) = self.raw_color(&adjustment.base) else {
+            self.set_status.set(format!("Unknown base color {}", adjustment.base));
+            return false;
+        };
+        let color = adjustment.resolve(&base);
+        self.set_changed_adjustments.update(|changed| {
+            changed.insert(name.to_string(), adjustment);
+        });
+        self.set_flattened_colors.update(|flattened| {
+            flattened.remove(name);
+        });
+        self.set_changed_colors.update(|changed| {
+            changed.insert(name.to_string(), color.clone());
+        });
+        self.mark_edited([name.to_string()]);
+        self.sync_current_color(name, &color);
+        true
+    }
+
+    pub fn is_locked(&self, name: &str) -> bool {
+        self.locked_colors.with(|locked| locked.contains(name))
+    }
+
+    pub fn toggle_lock(&self, name: &str) {
+        self.set_locked_colors.update(|locked| {
+            if !locked.remove(name) {
+                locked.insert(name.to_string());
+            }
+        });
+    }
+
+    pub fn is_pinned(&self, name: &str) -> bool {
+        self.pinned_colors.with(|pinned| pinned.iter().any(|pin| pin == name))
+    }
+
+    // Pins are kept in order of pinning and survive reloads
+    pub fn toggle_pin(&self, name: &str) {
+        self.set_pinned_colors.update(|pinned| {
+            match pinned.iter().position(|pin| pin == name) {
+                Some(idx) => {
+                    pinned.remove(idx);
+                }
+                None => pinned.push(name.to_string()),
+            }
+            save_pinned_colors(pinned);
+        });
+    }
+
+    pub fn dependents_of(&self, name: &str) -> Vec<String> {
+        self.dependents.with(|graph| graph.get(name).cloned().unwrap_or_default())
+    }
+
+    pub fn update_anchors(&self, update: impl FnOnce(&mut AnchorSet)) {
+        self.set_anchors.update(|anchors| {
+            update(anchors);
+            save_custom_anchors(anchors);
+        });
+    }
+
+    // Selects the color as it is now, with edits applied
+    pub fn select_color(&self, name: &str) {
+        match self.current_value(name) {
+            Some(color) => self.set_current_color.set(Some(CurrentColor::new(name, &color))),
+            None => self.set_status.set(format!("{name} is not in this theme")),
+        }
+    }
+
+    pub fn color_problem(&self, name: &str) -> Option<String> {
+        self.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.color_problem(name),
+            _ => None,
+        })
+    }
+
+    // Next problem color after the current one in name order, wraps around.
+    // Relative colors can't be selected, those only get reported.
+    pub fn select_next_problem(&self) {
+        let problems = self.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme
+                .named_colors
+                .keys()
+                .filter_map(|name| Some((name.clone(), theme.color_problem(name)?)))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        });
+        let current = self.current_color.with_untracked(|color| color.as_ref().map(|color| color.name.clone()));
+        let next = problems
+            .iter()
+            .find(|(name, _)| current.as_ref().map_or(true, |current| name > current))
+            .or_else(|| problems.first());
+        let Some((name, reason)) = next else {
+            self.set_status.set("No problem colors".into());
+            return;
+        };
+        if self.current_value(name).is_some() {
+            self.select_color(name);
+        }
+        let position = problems.iter().position(|(other, _)| other == name).unwrap_or(0) + 1;
+        self.set_status.set(format!("Problem {position}/{}: {name}: {reason}", problems.len()));
+    }
+
+    pub fn set_color(&self, name: &str, color: AbsoluteColor) -> bool {
+        if self.is_locked(name) {
+            self.set_status.set(format!("{name} is locked"));
+            return false;
+        }
+        // Explicit value replaces the adjustment
+        self.set_changed_adjustments.update(|changed| {
+            changed.remove(name);
+        });
+        self.set_changed_colors.update(|changed| {
+            changed.insert(name.to_string(), color.clone());
+        });
+        self.mark_edited([name.to_string()]);
+        self.sync_current_color(name, &color);
+        true
+    }
+
+    // Derived (StringAndAdjust) and adjusted (RefAndAdjust) colors which can be flattened
+    pub fn flattenable_colors(&self) -> Vec<String> {
+        self.theme.with_untracked(|theme| match theme {
+            Some(Ok(theme)) => theme
+                .derived_colors
+                .keys()
+                .chain(theme.adjusted_colors.keys())
+                .filter(|name| !self.is_locked(name))
+                .filter(|name| self.flattened_colors.with_untracked(|flattened| !flattened.contains(*name)))
+                .cloned()
+                .collect(),
+            _ => vec![],
+        })
+    }
+
+    // Snapshots current values of derived colors as plain ones, so they can be
+    // edited directly. Colors without a known value are skipped.
+    pub fn flatten_derived_colors(&self) -> usize {
+        let mut flattened = BTreeSet::new();
+        for name in self.flattenable_colors() {
+            let Some(color) = self.current_value(&name) else {
+                continue;
+            };
+            if self.set_color(&name, color) {
+                flattened.insert(name);
+            }
+        }
+        let count = flattened.len();
+        self.set_flattened_colors.update(|all| all.extend(flattened));
+        count
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.changed_colors.with(|changed| self.saved_colors.with(|saved| changed != saved))
+    }
+
+    pub fn mark_saved(&self) {
+        self.set_saved_colors.set(self.changed_colors.get_untracked());
+    }
+
+    // Returns how many colors went back to their saved value, locked ones are kept
+    pub fn reset_to_saved(&self) -> usize {
+        let saved = self.saved_colors.get_untracked();
+        let changed = self.changed_colors.get_untracked();
+        let names = changed
+            .keys()
+            .chain(saved.keys())
+            .filter(|name| changed.get(*name) != saved.get(*name))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        names
+            .into_iter()
+            .filter(|name| match saved.get(name) {
+                Some(color) => self.set_color(name, color.clone()),
+                None => self.revert_color(name),
+            })
+            .count()
+    }
+
+    // Exchanges current values, nothing changes unless both can be set
+    pub fn swap_colors(&self, first: &str, second: &str) -> bool {
+        let (Some(first_color), Some(second_color)) = (self.current_value(first), self.current_value(second)) else {
+            self.set_status.set(format!("Can't swap {first} and {second}, one of them has no value"));
+            return false;
+        };
+        if let Some(locked) = [first, second].into_iter().find(|name| self.is_locked(name)) {
+            self.set_status.set(format!("{locked} is locked"));
+            return false;
+        }
+        self.set_color(first, second_color) && self.set_color(second, first_color)
+    }
+
+    // Other colors which had the same value as this one before any edits
+    pub fn same_original_value(&self, name: &str) -> Vec<String> {
+        let Some(original) = self.original_value(name) else {
+            return vec![];
+        };
+        self.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme
+                .colors_with_value(&original)
+                .into_iter()
+                .filter(|other| *other != name)
+                .map(String::from)
+                .collect(),
+            _ => vec![],
+        })
+    }
+
+    // Returns how many of the matching colors were changed, locked ones are skipped
+    pub fn apply_to_same_original_value(&self, name: &str, color: AbsoluteColor) -> usize {
+        let targets = self
+            .same_original_value(name)
+            .into_iter()
+            .filter(|other| !self.is_locked(other))
+            .collect::<Vec<_>>();
+        self.set_changed_adjustments.update(|changed| {
+            for other in &targets {
+                changed.remove(other);
+            }
+        });
+        self.set_changed_colors.update(|changed| {
+            for other in &targets {
+                changed.insert(other.clone(), color.clone());
+            }
+        });
+        for other in &targets {
+            self.sync_current_color(other, &color);
+        }
+        let count = targets.len();
+        self.mark_edited(targets);
+        count
+    }
+
+    // Value of source goes to all unlocked targets in one change
+    pub fn assign_to_many(&self, source: &str, targets: &BTreeSet<String>) -> usize {
+        let Some(color) = self.current_value(source) else {
+            return 0;
+        };
+        let targets = targets
+            .iter()
+            .filter(|target| !self.is_locked(target))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Explicit value replaces the adjustment, as in set_color
+        self.set_changed_adjustments.update(|changed| {
+            for target in &targets {
+                changed.remove(target);
+            }
+        });
+        self.set_changed_colors.update(|changed| {
+            for target in &targets {
+                changed.insert(target.clone(), color.clone());
+            }
+        });
+        for target in &targets {
+            self.sync_current_color(target, &color);
+        }
+        let count = targets.len();
+        self.mark_edited(targets);
+        count
+    }
+
+    pub fn revert_color(&self, name: &str) -> bool {
+        if self.is_locked(name) {
+            self.set_status.set(format!("{name} is locked"));
+            return false;
+        }
+        self.set_changed_colors.update(|changed| {
+            changed.remove(name);
+        });
+        self.set_changed_adjustments.update(|changed| {
+            changed.remove(name);
+        });
+        self.set_edit_order.update(|order| order.retain(|edited| edited != name));
+        self.set_flattened_colors.update(|flattened| {
+            flattened.remove(name);
+        });
+        if let Some(original) = self.original_value(name) {
+            self.sync_current_color(name, &original);
+        }
+        true
+    }
+
+    // Runs transform over current colors, locked ones are left alone
+    pub fn apply_transform(&self, transform: &dyn ColorTransform) -> usize {
+        let Some(Ok(mut theme)) = self.theme.get_untracked() else {
+            return 0;
+        };
+        self.changed_colors.with_untracked(|changed| theme.apply_changes(changed));
+        let transformed = transform_theme(&theme, &[transform]);
+        let transformed = transformed
+            .into_iter()
+            .filter(|(name, _)| !self.is_locked(name))
+            .collect::<BTreeMap<_, _>>();
+        let count = transformed.len();
+        for (name, color) in &transformed {
+            self.sync_current_color(name, color);
+        }
+        self.mark_edited(transformed.keys().cloned().collect::<Vec<_>>());
+        self.set_changed_colors.update(|changed| changed.extend(transformed));
+        count
+    }
+
+    // Edit position of a changed color, higher is more recent
+    pub fn edit_rank(&self, name: &str) -> Option<usize> {
+        self.edit_order.with(|order| order.iter().position(|edited| edited == name))
+    }
+
+    fn mark_edited(&self, names: impl IntoIterator<Item = String>) {
+        self.set_edit_order.update(|order| {
+            for name in names {
+                order.retain(|edited| *edited != name);
+                order.push(name);
+            }
+        });
+    }
+
+    fn sync_current_color(&self, name: &str, color: &AbsoluteColor) {
+        let is_current = self
+            .current_color
+            .with_untracked(|current| current.as_ref().is_some_and(|current| current.name == name));
+        if is_current {
+            self.set_current_color.set(Some(CurrentColor::new(name, color)));
+        }
+    }
+}
+
 #[component]
 pub fn Editor() -> impl IntoView {
     let drop_zone_el = create_node_ref::<Div>();
@@ -50,37 +806,217 @@ pub fn Editor() -> impl IntoView {
     );
 
     let (current_color, set_current_color) = create_signal(None::<CurrentColor>);
+    let (changed_colors, set_changed_colors) = create_signal(BTreeMap::<String, AbsoluteColor>::new());
+    let (saved_colors, set_saved_colors) = create_signal(BTreeMap::<String, AbsoluteColor>::new());
+    let (edit_order, set_edit_order) = create_signal(Vec::<String>::new());
+    let (focus_mode, set_focus_mode) = create_signal(false);
+    // Only used in focus mode, otherwise there is no edit order to follow
+    let (recent_first, set_recent_first) = create_signal(false);
+    let (filter, set_filter) = create_signal(String::new());
+    let (sort, set_sort) = create_signal(ColorSort::Name);
+    // Whether the color list shows the color with the current filter and focus mode
+    let shown_in_list = move |theme: &CucumberBitwigTheme, name: &str| {
+        let class_name = theme.origins.get(name).map(|origin| origin.class_name.as_str());
+        (!focus_mode.get() || changed_colors.with(|changed| changed.contains_key(name)))
+            && filter.with(|filter| color_matches_filter(filter, name, class_name))
+    };
+    // Increased after each randomization, so running it again gives new colors
+    let (random_seed, set_random_seed) = create_signal(1u64);
+    // Opt-in theme to compare loaded colors with, helps to notice already patched JARs
+    let (reference, set_reference) = create_signal(None::<CucumberBitwigTheme>);
+    let (status, set_status) = create_signal(String::new());
+    let (loading, set_loading) = create_signal(None::<LoadingState>);
+    // Current stage and when it started, in ms since epoch
+    let stage_started = store_value(None::<(Stage, f64)>);
+    // From manifest of the loaded JAR
+    let (release_info, set_release_info) = create_signal(None::<ReleaseInfo>);
+    let (preview_background, set_preview_background) = create_signal(None::<(u8, u8, u8)>);
+    // View only, swatches are drawn as seen with the deficiency
+    let (color_vision, set_color_vision) = create_signal(ColorVisionDeficiency::None);
+    let (changed_constant_refs, set_changed_constant_refs) = create_signal(BTreeMap::<UiTarget, ColorConst>::new());
+    let (changed_raw_colors, set_changed_raw_colors) = create_signal(BTreeMap::<String, AbsoluteColor>::new());
+    let (changed_raw_floats, set_changed_raw_floats) = create_signal(BTreeMap::<String, [f64; 4]>::new());
+    let (changed_adjustments, set_changed_adjustments) = create_signal(BTreeMap::<String, ColorAdjustment>::new());
+    let (flattened_colors, set_flattened_colors) = create_signal(BTreeSet::<String>::new());
+    let (locked_colors, set_locked_colors) = create_signal(BTreeSet::<String>::new());
+    let (pinned_colors, set_pinned_colors) = create_signal(Vec::<String>::new());
+    let (anchors, set_anchors) = create_signal(AnchorSet::default());
+    let (promotion, set_promotion) = create_signal(PromotionCompositing::default());
+    let dependents = create_memo(move |_| {
+        async_data.with(|theme| match theme {
+            Some(Ok(theme)) => theme.dependency_graph(),
+            _ => BTreeMap::new(),
+        })
+    });
+    let (context_menu, set_context_menu) = create_signal(None::<ContextMenuTarget>);
+    // Source color of the open multi-assign dialog
+    let (multi_assign, set_multi_assign) = create_signal(None::<String>);
+    let (metadata_open, set_metadata_open) = create_signal(false);
+    let (normalize_alpha, set_normalize_alpha) = create_signal(NormalizeAlpha::default());
+    let (contrast_target, set_contrast_target) = create_signal(DEFAULT_MIN_CONTRAST);
+    // "Foreground => Background" per line, built-in pairs when empty
+    let (contrast_pairs, set_contrast_pairs) = create_signal(String::new());
+    let (comparing_original, set_comparing_original) = create_signal(false);
+    let (show_left_panel, set_show_left_panel) = create_signal(true);
+    let (show_right_panel, set_show_right_panel) = create_signal(true);
+    let (show_bottom_panel, set_show_bottom_panel) = create_signal(true);
+
+    let state = EditorState {
+        theme: async_data,
+        current_color,
+        set_current_color,
+        changed_colors,
+        set_changed_colors,
+        saved_colors,
+        set_saved_colors,
+        edit_order,
+        set_edit_order,
+        changed_constant_refs,
+        set_changed_constant_refs,
+        changed_raw_colors,
+        set_changed_raw_colors,
+        changed_raw_floats,
+        set_changed_raw_floats,
+        changed_adjustments,
+        set_changed_adjustments,
+        flattened_colors,
+        set_flattened_colors,
+        locked_colors,
+        set_locked_colors,
+        pinned_colors,
+        set_pinned_colors,
+        promotion,
+        set_promotion,
+        dependents,
+        anchors,
+        set_anchors,
+        set_status,
+        comparing_original,
+        set_comparing_original,
+        jar: store_value(None),
+    };
+
+    // Local storage is only there in the browser, effects don't run on the server
+    create_effect(move |_| set_pinned_colors(load_pinned_colors()));
+    create_effect(move |_| {
+        if let Some(custom) = load_custom_anchors() {
+            set_anchors(custom);
+        }
+    });
+
+    create_effect(move |_| {
+        async_data.with(|theme| {
+            if let Some(Ok(theme)) = theme {
+                set_promotion(theme.promotion_compositing);
+            }
+        });
+    });
+
+    // Locks and pins saved with the theme replace the current ones. Memo, so
+    // other edits of the loaded theme don't bring them back.
+    let loaded_locks_and_pins = create_memo(move |_| {
+        async_data.with(|theme| match theme {
+            Some(Ok(theme)) => (theme.locked_colors.clone(), theme.pinned_colors.clone()),
+            _ => Default::default(),
+        })
+    });
+    create_effect(move |_| {
+        let (locked, pinned) = loaded_locks_and_pins.get();
+        if !locked.is_empty() {
+            set_locked_colors(locked);
+        }
+        if !pinned.is_empty() {
+            save_pinned_colors(&pinned);
+            set_pinned_colors(pinned);
+        }
+    });
+
+    // Select something on theme load, ?color=<name> overrides the default choice
+    let query = use_query_map();
+    create_effect(move |_| {
+        async_data.with(|theme| {
+            let Some(Ok(theme)) = theme else {
+                return;
+            };
+            let still_present = current_color.with_untracked(|current| {
+                current.as_ref().is_some_and(|current| theme.absolute_color(&current.name).is_some())
+            });
+            if still_present {
+                return;
+            }
+            let requested = query.with_untracked(|query| query.get("color").cloned());
+            let preferred = requested
+                .iter()
+                .map(String::as_str)
+                .chain(DEFAULT_SELECTED_COLORS.iter().copied())
+                .collect::<Vec<_>>();
+            let selection = theme
+                .initial_selection(&preferred)
+                .map(|(name, color)| CurrentColor::new(name, color));
+            set_current_color(selection);
+        });
+    });
+
+    // Without palette class the last one matching the anchor is used
+    let scan_jar = move |bytes: Vec<u8>, palette_class: Option<String>| {
+        state.jar.set_value(Some(bytes.clone()));
+        let (marker, release) = match zip::ZipArchive::new(Cursor::new(bytes.as_slice())) {
+            Ok(mut zip) => (read_patch_marker(&mut zip), read_release_info(&mut zip)),
+            Err(_) => (None, None),
+        };
+        let on_progress = move |progress: Progress| {
+            let now = js_sys::Date::now();
+            let started = match stage_started.get_value() {
+                Some((stage, started)) if stage == progress.stage => started,
+                _ => {
+                    stage_started.set_value(Some((progress.stage, now)));
+                    now
+                }
+            };
+            let remaining = progress.remaining_secs((now - started) / 1000.0);
+            set_loading(Some(LoadingState::Scanning(progress, remaining)));
+        };
+        let on_done = move |theme: CucumberBitwigTheme| {
+            let has_playhead = theme.constant_refs.contains_key(&UiTarget::Playhead);
+            let notes = release.as_ref().map(|release| release.compatibility_notes(&theme)).unwrap_or_default();
+            async_data.update(|old_theme| {
+                *old_theme = Some(Ok(theme));
+            });
+            set_loading(None);
+            if !notes.is_empty() {
+                set_status(notes.join(". "));
+            } else if !has_playhead {
+                set_status("Playhead color isn't available for this Bitwig version".into());
+            }
+            set_release_info(release);
+            if let Some(marker) = marker {
+                set_status(format!(
+                    "This JAR was already patched by cucumber {} (theme format v{})",
+                    marker.cucumber_version, marker.theme_format_version
+                ));
+            }
+        };
+        let on_error = move |message: String| {
+            set_loading(None);
+            set_status(message);
+        };
+        handle_jar_blob(bytes, anchors.get_untracked(), palette_class, on_progress, on_done, on_error);
+    };
 
     let on_drop = move |mut event: UseDropZoneEvent| {
         logging::log!("DROP: {:?}", event);
         let file = event.files.pop().unwrap();
+        set_loading(Some(LoadingState::ReadingFile));
+        stage_started.set_value(None);
 
-        {
-            use web_sys::FileReader;
-            use wasm_bindgen::closure::Closure;
-            use wasm_bindgen::JsCast;
-            use web_sys::Event;
-
-            let reader = FileReader::new().unwrap();
-            let onloadend = Closure::wrap(Box::new(move |event: Event| {
-                let reader: FileReader = event.target().unwrap().unchecked_into();
-                if reader.ready_state() == FileReader::DONE {
-                    let result = reader.result().unwrap();
-                    let array = js_sys::Uint8Array::new(&result);
-                    let bytes = array.to_vec();
-                    // Process the bytes as needed
-                    logging::log!("Read {} bytes", bytes.len());
-                    let theme = handle_jar_blob(bytes);
-                    async_data.update(|old_theme| {
-                        *old_theme = Some(Ok(theme));
-                    });
-                }
-            }) as Box<dyn FnMut(_)>);
+        read_file_bytes(&file, move |bytes| scan_jar(bytes, None));
+    };
 
-            reader.set_onloadend(Some(onloadend.as_ref().unchecked_ref()));
-            reader.read_as_array_buffer(&file).unwrap();
-            onloadend.forget();
-        }
+    let palette_candidates = move || {
+        async_data.with(|theme| match theme {
+            Some(Ok(theme)) => theme.anchors.get(PALETTE_ANCHOR_NAME).cloned().unwrap_or_default(),
+            _ => vec![],
+        })
     };
 
     let UseDropZoneReturn {
@@ -91,6 +1027,165 @@ pub fn Editor() -> impl IntoView {
         UseDropZoneOptions::default().on_drop(on_drop)
     );
 
+    let edited_theme = move || {
+        let Some(Ok(theme)) = async_data.get() else {
+            set_status("Nothing to export yet".into());
+            return None;
+        };
+        let mut theme = changed_colors.with(|changed| {
+            changed_constant_refs.with(|refs| {
+                changed_raw_colors.with(|raw| {
+                    changed_raw_floats.with(|raw_floats| {
+                        changed_adjustments.with(|adjustments| {
+                            flattened_colors.with(|flattened| {
+                                exchange::theme_with_changes(&theme, changed, refs, raw, raw_floats, adjustments, flattened)
+                            })
+                        })
+                    })
+                })
+            })
+        });
+        theme.promotion_compositing = promotion.get_untracked();
+        theme.locked_colors = locked_colors.get_untracked();
+        theme.pinned_colors = pinned_colors.get_untracked();
+        if theme.created.is_none() {
+            let today = String::from(js_sys::Date::new_0().to_iso_string());
+            theme.created = Some(today.chars().take(10).collect());
+        }
+        Some(theme)
+    };
+
+    let export_json = move || {
+        let Some(theme) = edited_theme() else {
+            return;
+        };
+        match exchange::export_theme_json(&theme) {
+            Ok(json) => {
+                download_text(&format!("{}.json", theme.name), "application/json", &json);
+                state.mark_saved();
+                set_status(format!("Exported theme JSON ({} changed colors)", changed_colors.with(|changed| changed.len())));
+            }
+            Err(err) => set_status(format!("Failed to export theme JSON: {err}")),
+        }
+    };
+
+    let run_command = move |command: EditorCommand| match command {
+        // Exports theme JSON without touching the JAR
+        EditorCommand::ExportJson => export_json(),
+        EditorCommand::ToggleLeftPanel => set_show_left_panel.update(|show| *show = !*show),
+        EditorCommand::ToggleRightPanel => set_show_right_panel.update(|show| *show = !*show),
+        EditorCommand::ToggleBottomPanel => set_show_bottom_panel.update(|show| *show = !*show),
+        EditorCommand::ResetToSaved => {
+            if !state.is_dirty() {
+                set_status("No changes since the last export".into());
+                return;
+            }
+            let confirmed = leptos::window()
+                .confirm_with_message("Discard all changes made since the last export?");
+            if confirmed != Ok(true) {
+                return;
+            }
+            let count = state.reset_to_saved();
+            set_status(format!("Reset {count} colors to the last export"));
+        }
+        EditorCommand::ThemeFromApp => {
+            let mut count = 0;
+            for (property, color) in sample_app_colors() {
+                let Some((_, targets)) = APP_COLOR_TARGETS.iter().find(|(target, _)| *target == property) else {
+                    continue;
+                };
+                for name in targets.iter() {
+                    if state.original_value(name).is_some() && state.set_color(name, color.clone()) {
+                        count += 1;
+                    }
+                }
+            }
+            set_status(format!("Theme from app colors: {count} colors changed"));
+        }
+        EditorCommand::NextProblemColor => state.select_next_problem(),
+        EditorCommand::RandomizeShown => {
+            let Some(Ok(theme)) = async_data.get_untracked() else {
+                set_status("Nothing to randomize yet".into());
+                return;
+            };
+            let names = theme
+                .named_colors
+                .keys()
+                .filter(|name| shown_in_list(&theme, name))
+                .cloned()
+                .collect();
+            let seed = random_seed.get_untracked();
+            let count = state.apply_transform(&Randomize { seed, names });
+            set_random_seed(seed.wrapping_add(1));
+            set_status(format!("Randomized {count} colors (seed {seed})"));
+        }
+        EditorCommand::CompareOriginal => {
+            if current_color.get_untracked().is_none() {
+                set_status("Select a color to compare".into());
+                return;
+            }
+            let comparing = !comparing_original.get_untracked();
+            set_comparing_original(comparing);
+            set_status(if comparing { "Showing original value" } else { "Showing edited value" }.into());
+        }
+    };
+
+    let _ = use_event_listener(use_document(), ev::keydown, move |event| {
+        if let Some(command) = EditorCommand::from_event(&event) {
+            event.prevent_default();
+            run_command(command);
+        }
+    });
+
+    let _ = use_event_listener(use_document(), ev::click, move |_| set_context_menu(None));
+
+    let run_transform = move |label: &str, transform: &dyn ColorTransform| {
+        let count = state.apply_transform(transform);
+        set_status(format!("{label}: {count} colors changed"));
+    };
+
+    let ensure_contrast = move || {
+        let Some(Ok(mut theme)) = async_data.get_untracked() else {
+            return;
+        };
+        changed_colors.with_untracked(|changed| theme.apply_changes(changed));
+        let custom = contrast_pairs.get_untracked();
+        let custom = custom
+            .lines()
+            .filter_map(|line| line.split_once("=>"))
+            .map(|(foreground, background)| (foreground.trim(), background.trim()))
+            .collect::<Vec<_>>();
+        let pairs = if custom.is_empty() { KNOWN_CONTRAST_PAIRS } else { &custom[..] };
+        let transform = EnsureContrast::new(&theme, pairs, contrast_target.get_untracked());
+
+        let foregrounds = pairs.iter().map(|(foreground, _)| *foreground).collect::<BTreeSet<_>>();
+        let before = foregrounds
+            .iter()
+            .filter_map(|name| Some((*name, theme.absolute_color(name)?.clone())))
+            .collect::<Vec<_>>();
+        state.apply_transform(&transform);
+
+        let adjusted = before
+            .into_iter()
+            .filter_map(|(name, old)| {
+                let new = state.current_value(name).filter(|new| *new != old)?;
+                let contrast = |color: &AbsoluteColor| transform.min_contrast(name, (color.r, color.g, color.b));
+                Some(format!(
+                    "{name} {} → {} ({:.1}:1 → {:.1}:1)",
+                    old.to_hex(),
+                    new.to_hex(),
+                    contrast(&old)?,
+                    contrast(&new)?,
+                ))
+            })
+            .collect::<Vec<_>>();
+        if adjusted.is_empty() {
+            set_status("Contrast: nothing to adjust".into());
+        } else {
+            set_status(format!("Contrast: {}", adjusted.join(", ")));
+        }
+    };
+
     let on_click = move |event| {
         async_data.update(|theme| {
             if let Some(Ok(theme)) = theme {
@@ -102,18 +1197,345 @@ pub fn Editor() -> impl IntoView {
     view! {
         <h1>"Editor"</h1>
 
-        <ColorEditor maybe_color=current_color set_current_color=set_current_color/>
+        <details class="commands">
+            <summary>"Commands"</summary>
+            { EditorCommand::ALL.into_iter().map(|command| view! {
+                <button on:click=move |_| run_command(command)>
+                    { format!("{} ({})", command.label(), command.shortcut().describe()) }
+                </button>
+            }).collect::<Vec<_>>() }
+        </details>
+
+        <ColorContextMenu state=state target=context_menu set_target=set_context_menu set_multi_assign=set_multi_assign/>
+        <MultiAssignDialog state=state source=multi_assign set_source=set_multi_assign/>
+        <ThemeMetadataDialog state=state open=metadata_open set_open=set_metadata_open/>
+        <div class="tools-panel" class:hidden=move || !show_right_panel.get()>
+            <ColorEditor state=state/>
+            <AdjustmentEditor state=state/>
+            <PlayheadPicker state=state/>
+            <RawColorsPanel state=state/>
+            <ColorLists state=state/>
+            <Eyedropper state=state/>
+            <BytecodeInspector state=state/>
+            <DependencyGraph state=state/>
+        </div>
+        <JarCompare state=state/>
 
         <button on:click=on_click>"MUTATE"</button>
+        <button on:click=move |_| set_metadata_open.set(true)>"Theme info..."</button>
+        <button on:click=move |_| export_json() title=EditorCommand::ExportJson.shortcut().describe()>"Export JSON"</button>
+        { move || state.is_dirty().then(|| view! { <span class="unsaved">"Unsaved changes"</span> }) }
+        <button on:click=move |_| {
+            if let Some(theme) = edited_theme() {
+                copy_to_clipboard(&exchange::export_css_vars(&theme));
+                set_status("Copied colors as CSS variables".into());
+            }
+        }>"Copy as CSS variables"</button>
+        <button on:click=move |_| {
+            let Some(Ok(theme)) = async_data.get() else {
+                set_status("Nothing to report yet".into());
+                return;
+            };
+            let report = changed_colors.with(|changed| {
+                changed_raw_colors.with(|raw| exchange::export_change_report(&theme, changed, raw))
+            });
+            download_text(&format!("{} changes.md", theme.name), "text/markdown", &report);
+        }>"Export change report"</button>
+        <details class="developer">
+            <summary>"Developer"</summary>
+            <button on:click=move |_| {
+                let code = changed_colors.with(exchange::changed_colors_as_rust);
+                copy_to_clipboard(&code);
+                set_status("Copied changed colors as Rust code".into());
+            }>"Copy changed colors as Rust"</button>
+        </details>
+        <p class="status" class:hidden=move || !show_bottom_panel.get()>{ status }</p>
+        <label>
+            "Promoted colors: "
+            <select on:change=move |e| {
+                let mode = match event_target_value(&e).as_str() {
+                    "blended" => PromotionCompositing::Blended,
+                    _ => PromotionCompositing::Absolute,
+                };
+                set_promotion(mode);
+            }>
+                <option value="absolute" selected=move || promotion.get().is_absolute()>
+                    { PromotionCompositing::Absolute.label() }
+                </option>
+                <option value="blended" selected=move || !promotion.get().is_absolute()>
+                    { PromotionCompositing::Blended.label() }
+                </option>
+            </select>
+        </label>
+        <div class="transforms">
+            "Transform all: "
+            <button on:click=move |_| run_transform("Invert", &Invert)>"Invert"</button>
+            <button on:click=move |_| run_transform("Desaturate", &Desaturate { amount: 50.0 })>"Desaturate"</button>
+            <button on:click=move |_| run_transform("Hue rotate", &HueRotate { degrees: 30.0 })>"Hue +30°"</button>
+            <button
+                title="Turn derived and adjusted colors into plain editable values"
+                on:click=move |_| {
+                    let count = state.flattenable_colors().len();
+                    if count == 0 {
+                        set_status("No derived colors to flatten".into());
+                        return;
+                    }
+                    let message = format!(
+                        "{count} colors will become plain values. They won't follow their base colors anymore, \
+                        and adjustments which depend on the background will be lost. Continue?"
+                    );
+                    if leptos::window().confirm_with_message(&message) != Ok(true) {
+                        return;
+                    }
+                    let count = state.flatten_derived_colors();
+                    set_status(format!("Flattened {count} colors"));
+                }
+            >"Flatten derived colors"</button>
+        </div>
+        <div class="transforms">
+            "Snap alpha within "
+            <input
+                type="number"
+                min="0"
+                max="255"
+                prop:value=move || normalize_alpha.with(|normalize| normalize.threshold)
+                on:change=move |e| {
+                    if let Ok(threshold) = event_target_value(&e).parse() {
+                        set_normalize_alpha.update(|normalize| normalize.threshold = threshold);
+                    }
+                }
+            />
+            " of "
+            <input
+                type="number"
+                min="0"
+                max="255"
+                prop:value=move || normalize_alpha.with(|normalize| normalize.target)
+                on:change=move |e| {
+                    if let Ok(target) = event_target_value(&e).parse() {
+                        set_normalize_alpha.update(|normalize| normalize.target = target);
+                    }
+                }
+            />
+            <button on:click=move |_| {
+                normalize_alpha.with(|normalize| run_transform("Normalize alpha", normalize))
+            }>"Normalize alpha"</button>
+        </div>
+        <div class="transforms">
+            "Minimum contrast "
+            <input
+                type="number"
+                min="1"
+                max="21"
+                step="0.5"
+                prop:value=contrast_target
+                on:change=move |e| {
+                    if let Ok(target) = event_target_value(&e).parse() {
+                        set_contrast_target(target);
+                    }
+                }
+            />
+            <textarea
+                placeholder=KNOWN_CONTRAST_PAIRS
+                    .iter()
+                    .map(|(foreground, background)| format!("{foreground} => {background}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                prop:value=contrast_pairs
+                on:input=move |e| set_contrast_pairs(event_target_value(&e))
+            ></textarea>
+            <button on:click=move |_| ensure_contrast()>"Ensure contrast"</button>
+            <button on:click=move |_| {
+                let Some(theme) = edited_theme() else {
+                    return;
+                };
+                let custom = contrast_pairs.get_untracked();
+                let custom = custom
+                    .lines()
+                    .filter_map(|line| line.split_once("=>"))
+                    .map(|(foreground, background)| (foreground.trim(), background.trim()))
+                    .collect::<Vec<_>>();
+                let pairs = if custom.is_empty() { KNOWN_CONTRAST_PAIRS } else { &custom[..] };
+                let html = exchange::accessibility_report_html(&theme, pairs, contrast_target.get_untracked());
+                download_text(&format!("{} accessibility.html", theme.name), "text/html", &html);
+            }>"Export accessibility report"</button>
+        </div>
+        <div class="transforms">
+            "Random seed "
+            <input
+                type="number"
+                min="0"
+                prop:value=random_seed
+                on:change=move |e| {
+                    if let Ok(seed) = event_target_value(&e).parse() {
+                        set_random_seed(seed);
+                    }
+                }
+            />
+            <button
+                on:click=move |_| run_command(EditorCommand::RandomizeShown)
+                title=EditorCommand::RandomizeShown.shortcut().describe()
+            >"Randomize shown colors"</button>
+            <button
+                class="compare"
+                class:active=comparing_original
+                on:click=move |_| run_command(EditorCommand::CompareOriginal)
+                title=EditorCommand::CompareOriginal.shortcut().describe()
+            >"Compare with original"</button>
+        </div>
 
         <Suspense
             fallback=move || view! { <span>"Not ready"</span> }
         >
             <h2>"Loaded data:"</h2>
             {move || {
-                async_data.and_then(|theme| view! { <pre> { format!("{:#?}", theme.name) } </pre> })
+                async_data.and_then(|theme| view! {
+                    <pre> { format!("{:#?}", theme.name) } </pre>
+                    { theme.author.clone().map(|author| view! { <p class="author">{ format!("by {author}") }</p> }) }
+                    { theme.created.clone().map(|created| view! { <p class="created">{ format!("Created {created}") }</p> }) }
+                    { theme.description.clone().map(|description| view! { <p class="description">{ description }</p> }) }
+                })
             }}
         </Suspense>
+        { move || release_info.get().map(|release| {
+            let theme_notes = async_data.with(|theme| match theme {
+                Some(Ok(theme)) => release.compatibility_notes(theme),
+                _ => vec![],
+            });
+            let summary = [
+                release.version.clone().map(|version| format!("Bitwig {version}")),
+                release.build.clone().map(|build| format!("build {build}")),
+                release.os.clone(),
+                release.jvm.clone().map(|jvm| format!("JVM {jvm}")),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ");
+            view! {
+                <details class="release-info">
+                    <summary>{ if summary.is_empty() { "Unknown release".to_string() } else { summary } }</summary>
+                    { theme_notes.into_iter().map(|note| view! { <p class="problem-note">{ note }</p> }).collect::<Vec<_>>() }
+                    { release.raw.into_iter().map(|(key, value)| view! {
+                        <div>{ format!("{key}: {value}") }</div>
+                    }).collect::<Vec<_>>() }
+                </details>
+            }
+        }) }
+        <details class="anchors">
+            <summary>"Detected classes"</summary>
+            {move || {
+                async_data.and_then(|theme| {
+                    theme.anchors.iter().map(|(anchor, class_files)| view! {
+                        <div>{ format!("{}: {}", anchor, class_files.join(", ")) }</div>
+                    }).collect::<Vec<_>>()
+                })
+            }}
+        </details>
+        <details class="parse-failures">
+            <summary>
+                { move || {
+                    let count = async_data.with(|theme| match theme {
+                        Some(Ok(theme)) => theme.parse_failures.len(),
+                        _ => 0,
+                    });
+                    format!("Classes failed to parse: {count}")
+                } }
+            </summary>
+            {move || {
+                async_data.and_then(|theme| {
+                    theme.parse_failures.iter().map(|failure| view! {
+                        <div>{ format!("{}: {}", failure.file_name, failure.error) }</div>
+                    }).collect::<Vec<_>>()
+                })
+            }}
+        </details>
+        <details class="uneditable-colors">
+            <summary>
+                { move || {
+                    let count = async_data.with(|theme| match theme {
+                        Some(Ok(theme)) => theme.uneditable_colors.len(),
+                        _ => 0,
+                    });
+                    format!("Colors which can't be edited: {count}")
+                } }
+            </summary>
+            {move || {
+                async_data.and_then(|theme| {
+                    theme.uneditable_colors.iter().map(|color| {
+                        let name = color.name.clone().unwrap_or_else(|| "(name known at runtime)".into());
+                        view! {
+                            <div>
+                                <span class="name">{ name }</span>
+                                { format!(" in {}.{}: {}", color.class_name, color.method_name, color.reason) }
+                            </div>
+                        }
+                    }).collect::<Vec<_>>()
+                })
+            }}
+        </details>
+
+        <details class="advanced-load">
+            <summary>"Advanced load"</summary>
+            <p>"Anchors used to find the palette classes, change them if a new Bitwig version isn't recognized."</p>
+            <label>
+                "Palette anchor: "
+                <input
+                    type="text"
+                    prop:value=move || anchors.with(|anchors| anchors.palette.clone())
+                    on:change=move |e| state.update_anchors(|anchors| anchors.palette = event_target_value(&e))
+                />
+            </label>
+            <label>
+                "Init anchor: "
+                <input
+                    type="text"
+                    prop:value=move || anchors.with(|anchors| anchors.init.clone())
+                    on:change=move |e| state.update_anchors(|anchors| anchors.init = event_target_value(&e))
+                />
+            </label>
+            <label>
+                "Raw color anchor: "
+                <input
+                    type="number"
+                    step="any"
+                    prop:value=move || anchors.with(|anchors| anchors.raw_color)
+                    on:change=move |e| {
+                        match event_target_value(&e).parse::<f64>() {
+                            Ok(value) => state.update_anchors(|anchors| anchors.raw_color = value),
+                            Err(_) => set_status("Raw color anchor should be a number".into()),
+                        }
+                    }
+                />
+            </label>
+            <button on:click=move |_| state.update_anchors(|anchors| *anchors = AnchorSet::default())>
+                "Reset to defaults"
+            </button>
+            { move || (palette_candidates().len() > 1).then(|| {
+                let active = async_data.with(|theme| match theme {
+                    Some(Ok(theme)) => theme.palette_class.clone(),
+                    _ => None,
+                });
+                view! {
+                    <label>
+                        "Palette class: "
+                        <select on:change=move |e| {
+                            let Some(bytes) = state.jar.get_value() else {
+                                set_status("Drop the JAR again to switch palettes".into());
+                                return;
+                            };
+                            stage_started.set_value(None);
+                            scan_jar(bytes, Some(event_target_value(&e)));
+                        }>
+                            { palette_candidates().into_iter().map(|file_name| {
+                                let selected = active.as_ref() == Some(&file_name);
+                                view! { <option value=file_name.clone() selected=selected>{ file_name }</option> }
+                            }).collect::<Vec<_>>() }
+                        </select>
+                    </label>
+                }
+            }) }
+        </details>
 
         <div
             class:dropover=is_over_drop_zone
@@ -121,53 +1543,225 @@ pub fn Editor() -> impl IntoView {
         >
             "Drop JAR here"
         </div>
-        <h2>"Colors"</h2>
-        <Suspense
-            fallback=move || view! { <span>"Not ready"</span> }
-        >
-            <div class="colors">
-                { move || {
-                    async_data.and_then(|theme| {
-                        theme.named_colors.iter().map(|(name, color)| {
-                            match color {
-                                NamedColor::Absolute(AbsoluteColor { r, g, b, a }) => {
-                                    let r = *r;
-                                    let g = *g;
-                                    let b = *b;
-                                    let a = *a;
-                                    let a_u8 = a;
-                                    let a = a as f32 / 255.0;
-                                    let bg = format!("rgba({r}, {g}, {b}, {a})");
-                                    let fg = if (r as u16 + g as u16 + b as u16 + ((255.0 - a * 255.0) * 2.0) as u16) > 128 * 3 {
-                                        "black"
-                                    } else {
-                                        "white"
-                                    };
-
-                                    view! { <div
-                                        class="color"
-                                        style:background-color=bg
-                                        style:color=fg
-                                        on:click=move |_| {
-                                            logging::log!("CLIIIIICK");
-                                            set_current_color(
-                                                Some(CurrentColor { name: "Abstract Button Pressed Background".into(), r, g, b, a: a_u8 })
-                                            );
-                                        }
-                                    >
-                                        { name }
-                                    </div> }
-                                },
-                                NamedColor::Relative(_) => view! {
-                                    <div class="color">
-                                        { name }" (RELATIVE - IGNORED)"
-                                    </div>
-                                }
-                            }
-                        }).collect::<Vec<_>>()
-                    })
-                } }
+        { move || loading.get().map(|state| {
+            let text = match state {
+                LoadingState::ReadingFile => "Reading JAR...".to_string(),
+                LoadingState::Scanning(progress, None) => {
+                    format!("{} — {:.0}%", progress.stage, progress.percentage())
+                }
+                LoadingState::Scanning(progress, Some(remaining)) => {
+                    format!("{} — {:.0}% — ~{:.0}s remaining", progress.stage, progress.percentage(), remaining.ceil())
+                }
+            };
+            // Reading has no progress to show
+            let spinner = matches!(state, LoadingState::ReadingFile)
+                .then(|| view! { <span class="spinner"></span> });
+            view! { <div class="loading-placeholder">{ spinner }{ text }</div> }
+        }) }
+        <div class="colors-panel" class:hidden=move || !show_left_panel.get()>
+            <div class="quick-bar">
+                { move || pinned_colors.get().into_iter().map(|name| {
+                    let label = name.clone();
+                    let selected = current_color.with(|current| {
+                        current.as_ref().is_some_and(|current| current.name == name)
+                    });
+                    view! {
+                        <button
+                            class="pin"
+                            class:selected=selected
+                            on:click=move |_| state.select_color(&name)
+                        >
+                            { label }
+                        </button>
+                    }
+                }).collect::<Vec<_>>() }
             </div>
-        </Suspense>
+            <h2>
+                "Colors "
+                <span
+                    class="changed-badge"
+                    title="Show changed colors only"
+                    on:click=move |_| set_focus_mode(true)
+                >
+                    { move || changed_colors.with(|changed| changed.len()) }
+                </span>
+            </h2>
+            <label>
+                <input
+                    type="checkbox"
+                    prop:checked=focus_mode
+                    on:change=move |e| set_focus_mode(event_target_checked(&e))
+                />
+                "Focus mode (changed colors only)"
+            </label>
+            { move || focus_mode.get().then(|| view! {
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=recent_first
+                        on:change=move |e| set_recent_first(event_target_checked(&e))
+                    />
+                    "Most recently changed first"
+                </label>
+            }) }
+            <label>
+                <input
+                    type="checkbox"
+                    on:change=move |e| {
+                        if !event_target_checked(&e) {
+                            set_reference(None);
+                            return;
+                        }
+                        spawn_local(async move {
+                            match get_theme("factory-theme".into()).await {
+                                Ok(theme) => set_reference(Some(theme)),
+                                Err(err) => set_status(format!("Failed to load reference theme: {err}")),
+                            }
+                        });
+                    }
+                />
+                "Highlight differences from factory theme"
+            </label>
+            <input
+                type="search"
+                placeholder="Filter (class:<name> to search by class)"
+                prop:value=filter
+                on:input=move |e| set_filter(event_target_value(&e))
+            />
+            <label>
+                "Sort by: "
+                <select on:change=move |e| set_sort(ColorSort::from_value(&event_target_value(&e)))>
+                    <option value="name">"Name"</option>
+                    <option value="hue">"Hue"</option>
+                    <option value="brightness">"Brightness"</option>
+                    <option value="uses">"Times used"</option>
+                </select>
+            </label>
+            <label>
+                "Preview on background: "
+                <input
+                    type="color"
+                    on:input=move |e| set_preview_background(parse_hex_rgb(&event_target_value(&e)))
+                />
+                <button on:click=move |_| set_preview_background(None)>"Arranger background"</button>
+            </label>
+            <label>
+                "Simulate color vision: "
+                <select on:change=move |e| {
+                    let value = event_target_value(&e);
+                    let kind = ColorVisionDeficiency::ALL
+                        .into_iter()
+                        .find(|kind| kind.label() == value)
+                        .unwrap_or_default();
+                    set_color_vision(kind);
+                }>
+                    { ColorVisionDeficiency::ALL.into_iter().map(|kind| view! {
+                        <option value=kind.label()>{ kind.label() }</option>
+                    }).collect::<Vec<_>>() }
+                </select>
+            </label>
+            <Suspense
+                fallback=move || view! { <span>"Not ready"</span> }
+            >
+                <div class="colors">
+                    { move || {
+                        async_data.and_then(|theme| {
+                            let background = preview_background.get().or_else(|| {
+                                theme.absolute_color(ARRANGER_BACKGROUND).map(|color| (color.r, color.g, color.b))
+                            });
+                            let mut colors = theme
+                                .named_colors
+                                .iter()
+                                .filter(|(name, _)| shown_in_list(theme, name))
+                                .collect::<Vec<_>>();
+                            // Stable sort, so equal keys stay in name order
+                            let sort = sort.get();
+                            let sort_key = |name: &str| {
+                                let color = changed_colors
+                                    .with(|changed| changed.get(name).cloned())
+                                    .or_else(|| theme.absolute_color(name).cloned());
+                                sort.key(color.as_ref(), theme.use_counts.get(name).copied())
+                            };
+                            colors.sort_by(|(a, _), (b, _)| match (sort_key(a), sort_key(b)) {
+                                (Some(a), Some(b)) => a.total_cmp(&b),
+                                (a, b) => a.is_none().cmp(&b.is_none()),
+                            });
+                            if focus_mode.get() && recent_first.get() {
+                                colors.sort_by_key(|(name, _)| Reverse(state.edit_rank(name)));
+                            }
+                            colors.into_iter().map(|(name, color)| {
+                                match color {
+                                    NamedColor::Absolute(original) => {
+                                        let edited = changed_colors
+                                            .with(|changed| changed.get(name).cloned())
+                                            .unwrap_or_else(|| original.clone());
+                                        // Selecting keeps the edited value even while comparing
+                                        let selected = CurrentColor::new(name, &edited);
+                                        let color = if state.is_showing_original(name) { original.clone() } else { edited };
+                                        let AbsoluteColor { r, g, b, a } = color;
+                                        let menu_color_name = name.clone();
+                                        let locked = state.is_locked(name);
+                                        let modified = changed_colors.with(|changed| changed.contains_key(name));
+                                        let differs = reference.with(|reference| {
+                                            reference.as_ref().is_some_and(|reference| reference.absolute_color(name) != Some(original))
+                                        });
+                                        let tooltip = origin_tooltip(name, theme.origins.get(name), Some(a));
+                                        let a = a as f32 / 255.0;
+                                        let vision = color_vision.get();
+                                        let bg = match background {
+                                            Some(background) => {
+                                                let (r, g, b) = simulate_cvd(color.blended_on(background), vision);
+                                                format!("rgb({r}, {g}, {b})")
+                                            }
+                                            None => {
+                                                let (r, g, b) = simulate_cvd((r, g, b), vision);
+                                                format!("rgba({r}, {g}, {b}, {a})")
+                                            }
+                                        };
+                                        let fg = if (r as u16 + g as u16 + b as u16 + ((255.0 - a * 255.0) * 2.0) as u16) > 128 * 3 {
+                                            "black"
+                                        } else {
+                                            "white"
+                                        };
+
+                                        view! { <div
+                                            class="color"
+                                            class:locked=locked
+                                            class:differs=differs
+                                            style:background-color=bg
+                                            style:color=fg
+                                            title=tooltip
+                                            on:click=move |_| {
+                                                logging::log!("CLIIIIICK");
+                                                set_current_color(Some(selected.clone()));
+                                            }
+                                            on:contextmenu=move |e| {
+                                                e.prevent_default();
+                                                set_context_menu(Some(ContextMenuTarget {
+                                                    name: menu_color_name.clone(),
+                                                    x: e.client_x(),
+                                                    y: e.client_y(),
+                                                }));
+                                            }
+                                        >
+                                            { modified.then(|| view! { <span class="modified-dot" title="Modified"></span> }) }
+                                            { name }
+                                            { theme.use_counts.get(name).map(|uses| view! {
+                                                <span class="use-count" title="Times referenced in the JAR">{ *uses }</span>
+                                            }) }
+                                        </div> }
+                                    },
+                                    NamedColor::Relative(_) => view! {
+                                        <div class="color" title=origin_tooltip(name, theme.origins.get(name), None)>
+                                            { name }" (RELATIVE - IGNORED)"
+                                        </div>
+                                    }
+                                }
+                            }).collect::<Vec<_>>()
+                        })
+                    } }
+                </div>
+            </Suspense>
+        </div>
     }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use cucumber::types::AbsoluteColor;
+use leptos::{component, create_node_ref, create_signal, html::{Div, Img}, view, IntoView};
+use leptos::SignalGet;
+use leptos::SignalGetUntracked;
+use leptos::SignalSet;
+use leptos_use::{use_drop_zone_with_options, UseDropZoneEvent, UseDropZoneOptions, UseDropZoneReturn};
+use wasm_bindgen::JsCast;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlImageElement, MouseEvent, Url};
+
+use super::editor::EditorState;
+
+// Pixel of the image at full resolution, no matter how it's scaled on the page
+fn sample_pixel(img: &HtmlImageElement, event: &MouseEvent) -> Option<(u8, u8, u8)> {
+    let (natural_width, natural_height) = (img.natural_width(), img.natural_height());
+    let (shown_width, shown_height) = (img.client_width(), img.client_height());
+    if natural_width == 0 || shown_width <= 0 || shown_height <= 0 {
+        return None;
+    }
+    let x = (event.offset_x() as f64 * natural_width as f64 / shown_width as f64) as u32;
+    let y = (event.offset_y() as f64 * natural_height as f64 / shown_height as f64) as u32;
+
+    let canvas: HtmlCanvasElement = leptos::document().create_element("canvas").ok()?.unchecked_into();
+    canvas.set_width(natural_width);
+    canvas.set_height(natural_height);
+    let context: CanvasRenderingContext2d = canvas.get_context("2d").ok()??.unchecked_into();
+    context.draw_image_with_html_image_element(img, 0.0, 0.0).ok()?;
+    let data = context
+        .get_image_data(x.min(natural_width - 1) as f64, y.min(natural_height - 1) as f64, 1.0, 1.0)
+        .ok()?
+        .data();
+    Some((data[0], data[1], data[2]))
+}
+
+// Drop a screenshot or reference image, click on it to apply the color under cursor
+#[component]
+pub fn Eyedropper(
+    state: EditorState,
+) -> impl IntoView {
+    let drop_zone_el = create_node_ref::<Div>();
+    let img_el = create_node_ref::<Img>();
+    let (image_url, set_image_url) = create_signal(None::<String>);
+
+    let on_drop = move |mut event: UseDropZoneEvent| {
+        let Some(file) = event.files.pop() else {
+            return;
+        };
+        if let Some(old_url) = image_url.get_untracked() {
+            let _ = Url::revoke_object_url(&old_url);
+        }
+        set_image_url.set(Url::create_object_url_with_blob(&file).ok());
+    };
+
+    let UseDropZoneReturn {
+        is_over_drop_zone,
+        ..
+    } = use_drop_zone_with_options(
+        drop_zone_el,
+        UseDropZoneOptions::default().on_drop(on_drop)
+    );
+
+    let on_click = move |event: MouseEvent| {
+        let (Some(img), Some(current)) = (img_el.get_untracked(), state.current_color.get_untracked()) else {
+            state.set_status.set("Select a color to apply the sample to".into());
+            return;
+        };
+        if let Some((r, g, b)) = sample_pixel(&img, &event) {
+            state.set_color(&current.name, AbsoluteColor { r, g, b, a: current.a });
+        }
+    };
+
+    view! {
+        <div
+            class="eyedropper"
+            class:dropover=is_over_drop_zone
+            node_ref=drop_zone_el
+        >
+            { move || match image_url.get() {
+                Some(url) => view! { <img node_ref=img_el src=url on:click=on_click/> }.into_view(),
+                None => view! { <span>"Drop image here to pick colors from it"</span> }.into_view(),
+            } }
+        </div>
+    }
+}
@@ -0,0 +1,118 @@
+use cucumber::exchange::{diff_against_reference, DiffKind};
+use cucumber::types::{AbsoluteColor, CucumberBitwigTheme};
+use cucumber::Progress;
+use leptos::{component, create_node_ref, create_signal, html::Div, view, IntoView};
+use leptos::SignalGet;
+use leptos::SignalGetUntracked;
+use leptos::SignalSet;
+use leptos::SignalWith;
+use leptos_use::{use_drop_zone_with_options, UseDropZoneEvent, UseDropZoneOptions, UseDropZoneReturn};
+
+use super::editor::{handle_jar_blob, read_file_bytes, EditorState};
+
+fn swatch(color: Option<AbsoluteColor>) -> impl IntoView {
+    match color {
+        Some(color) => {
+            let hex = color.to_hex();
+            view! { <td style:background-color=hex.clone()>{ hex }</td> }
+        }
+        None => view! { <td>"—"</td> },
+    }
+}
+
+// Second JAR (e.g. another Bitwig version or a patched one), named colors
+// are compared side by side with the loaded theme
+#[component]
+pub fn JarCompare(
+    state: EditorState,
+) -> impl IntoView {
+    let drop_zone_el = create_node_ref::<Div>();
+    let (other, set_other) = create_signal(None::<CucumberBitwigTheme>);
+    let (progress, set_progress) = create_signal(None::<Progress>);
+
+    let on_drop = move |mut event: UseDropZoneEvent| {
+        let Some(file) = event.files.pop() else {
+            return;
+        };
+        read_file_bytes(&file, move |bytes| {
+            handle_jar_blob(
+                bytes,
+                state.anchors.get_untracked(),
+                None,
+                move |progress| set_progress.set(Some(progress)),
+                move |theme| {
+                    set_other.set(Some(theme));
+                    set_progress.set(None);
+                },
+                move |message| {
+                    set_progress.set(None);
+                    state.set_status.set(message);
+                },
+            );
+        });
+    };
+
+    let UseDropZoneReturn {
+        is_over_drop_zone,
+        ..
+    } = use_drop_zone_with_options(
+        drop_zone_el,
+        UseDropZoneOptions::default().on_drop(on_drop)
+    );
+
+    let rows = move || {
+        let diffs = state.theme.with(|theme| {
+            other.with(|other| match (theme, other) {
+                // Differences of the second JAR relative to the loaded one
+                (Some(Ok(theme)), Some(other)) => Some(diff_against_reference(other, theme)),
+                _ => None,
+            })
+        })?;
+        let rows = diffs
+            .into_iter()
+            .filter(|diff| !diff.raw)
+            .map(|diff| {
+                let kind = diff.kind();
+                view! {
+                    <tr
+                        class:added=kind == DiffKind::Added
+                        class:removed=kind == DiffKind::Removed
+                        class:modified=kind == DiffKind::Modified
+                    >
+                        <td>{ diff.name }</td>
+                        { swatch(diff.reference) }
+                        { swatch(diff.ours) }
+                    </tr>
+                }
+            })
+            .collect::<Vec<_>>();
+        Some(view! {
+            <table class="jar-compare">
+                <tr><th>"Color"</th><th>"Loaded JAR"</th><th>"Second JAR"</th></tr>
+                { rows }
+            </table>
+        })
+    };
+
+    view! {
+        <details class="compare">
+            <summary>"Compare with another JAR"</summary>
+            <div
+                class="drop-zone"
+                class:dropover=is_over_drop_zone
+                node_ref=drop_zone_el
+            >
+                "Drop second JAR here"
+            </div>
+            { move || progress.get().map(|progress| view! {
+                <div class="loading-placeholder">
+                    { format!("{}: {:.0}%", progress.stage, progress.percentage()) }
+                </div>
+            }) }
+            { move || other.with(Option::is_some).then(|| view! {
+                <button on:click=move |_| set_other.set(None)>"Close comparison"</button>
+            }) }
+            { rows }
+        </details>
+    }
+}
@@ -1,5 +1,17 @@
+pub mod adjustment_editor;
+pub mod bytecode_inspector;
 pub mod color_editor;
+pub mod color_lists;
+pub mod commands;
+pub mod context_menu;
+pub mod dependency_graph;
 pub mod editor;
+pub mod eyedropper;
+pub mod jar_compare;
 pub mod login;
+pub mod multi_assign;
+pub mod playhead_picker;
 pub mod profile;
+pub mod raw_colors_panel;
+pub mod theme_metadata;
 pub mod top_bar;
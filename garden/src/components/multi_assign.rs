@@ -0,0 +1,96 @@
+use std::collections::BTreeSet;
+
+use cucumber::types::NamedColor;
+use leptos::{component, create_signal, event_target_checked, event_target_value, view, IntoView, ReadSignal, WriteSignal};
+use leptos::SignalGet;
+use leptos::SignalSet;
+use leptos::SignalUpdate;
+use leptos::SignalWith;
+
+use super::editor::EditorState;
+
+// Copies value of the source color to every checked target at once
+#[component]
+pub fn MultiAssignDialog(
+    state: EditorState,
+    source: ReadSignal<Option<String>>,
+    set_source: WriteSignal<Option<String>>,
+) -> impl IntoView {
+    let (filter, set_filter) = create_signal(String::new());
+    let (targets, set_targets) = create_signal(BTreeSet::<String>::new());
+
+    let close = move || {
+        set_source.set(None);
+        set_targets.set(BTreeSet::new());
+        set_filter.set(String::new());
+    };
+
+    move || source.get().map(|source| {
+        let title = format!("Copy {source} to:");
+        let assign_source = source.clone();
+        let candidates = move || {
+            let source = source.clone();
+            state.theme.with(|theme| match theme {
+                Some(Ok(theme)) => theme
+                    .named_colors
+                    .iter()
+                    .filter(|(name, color)| **name != source && matches!(color, NamedColor::Absolute(_)))
+                    .map(|(name, _)| name.clone())
+                    .filter(|name| filter.with(|filter| name.to_lowercase().contains(&filter.to_lowercase())))
+                    .collect::<Vec<_>>(),
+                _ => vec![],
+            })
+        };
+
+        view! {
+            <div class="dialog" on:click=|e| e.stop_propagation()>
+                <h3>{ title }</h3>
+                <input
+                    type="search"
+                    placeholder="Filter targets"
+                    prop:value=filter
+                    on:input=move |e| set_filter.set(event_target_value(&e))
+                />
+                <div class="targets">
+                    { move || candidates().into_iter().map(|name| {
+                        let checked = targets.with(|targets| targets.contains(&name));
+                        let locked = state.is_locked(&name);
+                        let label = name.clone();
+                        view! {
+                            <label class:locked=locked>
+                                <input
+                                    type="checkbox"
+                                    prop:checked=checked
+                                    disabled=locked
+                                    on:change=move |e| {
+                                        let name = name.clone();
+                                        set_targets.update(|targets| {
+                                            if event_target_checked(&e) {
+                                                targets.insert(name);
+                                            } else {
+                                                targets.remove(&name);
+                                            }
+                                        });
+                                    }
+                                />
+                                { label }
+                            </label>
+                        }
+                    }).collect::<Vec<_>>() }
+                </div>
+                <button
+                    disabled=move || targets.with(BTreeSet::is_empty)
+                    on:click=move |_| {
+                        let targets = targets.get();
+                        let count = state.assign_to_many(&assign_source, &targets);
+                        state.set_status.set(format!("Copied {assign_source} to {count} colors"));
+                        close();
+                    }
+                >
+                    { move || format!("Assign to {} colors", targets.with(BTreeSet::len)) }
+                </button>
+                <button on:click=move |_| close()>"Cancel"</button>
+            </div>
+        }
+    })
+}
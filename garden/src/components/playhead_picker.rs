@@ -0,0 +1,89 @@
+use cucumber::contrast_ratio;
+use cucumber::types::{AbsoluteColor, ColorConst, UiTarget, ARRANGER_BACKGROUND};
+use leptos::{component, view, IntoView};
+
+use leptos::event_target_value;
+
+use super::editor::{parse_hex_rgb, EditorState};
+
+fn const_swatch_style((r, g, b, a): (u8, u8, u8, u8)) -> String {
+    format!("rgba({r}, {g}, {b}, {})", a as f32 / 255.0)
+}
+
+#[component]
+pub fn PlayheadPicker(
+    state: EditorState,
+) -> impl IntoView {
+    let available = move || {
+        state.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.constant_refs.contains_key(&UiTarget::Playhead),
+            _ => false,
+        })
+    };
+
+    view! {
+        <div>
+            <h3>"Playhead color"</h3>
+            { move || (!available()).then(|| view! {
+                <p class="problem-note">"Playhead color can't be changed for this Bitwig version"</p>
+            }) }
+            <div class="colors">
+                { move || {
+                    let selected = state.constant_ref(&UiTarget::Playhead);
+                    let custom = match &selected {
+                        Some(cnst @ ColorConst::Custom(_)) => Some(cnst.clone()),
+                        _ => None,
+                    };
+                    ColorConst::ALL.into_iter().chain(custom).map(|cnst| {
+                        let is_selected = selected.as_ref() == Some(&cnst);
+                        let style = const_swatch_style(cnst.to_comps());
+                        let label = match &cnst {
+                            ColorConst::Custom(color) => color.to_hex(),
+                            other => format!("{:?}", other),
+                        };
+                        view! {
+                            <div
+                                class="color"
+                                class:selected=is_selected
+                                style:background-color=style
+                                on:click=move |_| state.set_constant_ref(UiTarget::Playhead, cnst.clone())
+                            >
+                                { label }
+                            </div>
+                        }
+                    }).collect::<Vec<_>>()
+                } }
+            </div>
+            { move || {
+                let playhead = state.constant_ref(&UiTarget::Playhead)?.to_comps();
+                let background = state.current_value(ARRANGER_BACKGROUND)?;
+                let background_rgb = (background.r, background.g, background.b);
+                let (r, g, b, a) = playhead;
+                let playhead_rgb = AbsoluteColor { r, g, b, a }.blended_on(background_rgb);
+                let contrast = contrast_ratio(playhead_rgb, background_rgb);
+                Some(view! {
+                    <div
+                        class="playhead-preview"
+                        title="Playhead over the arranger background"
+                        style:background-color=format!("rgb({}, {}, {})", background.r, background.g, background.b)
+                    >
+                        <div class="playhead" style:background-color=const_swatch_style(playhead)></div>
+                    </div>
+                    <span>{ format!("Contrast {:.1}:1", contrast) }</span>
+                })
+            } }
+            <label>
+                "New raw color: "
+                <input
+                    type="color"
+                    on:change=move |e| {
+                        if let Some((r, g, b)) = parse_hex_rgb(&event_target_value(&e)) {
+                            let custom = ColorConst::Custom(AbsoluteColor { r, g, b, a: 255 });
+                            state.set_constant_ref(UiTarget::Playhead, custom);
+                        }
+                    }
+                />
+            </label>
+        </div>
+    }
+}
@@ -0,0 +1,104 @@
+use cucumber::types::AbsoluteColor;
+use leptos::{component, create_signal, view, IntoView};
+use leptos::SignalWith;
+
+use leptos::event_target_value;
+
+use super::editor::{parse_hex_rgb, EditorState};
+
+// 0..1 sliders for float defined constants, values are kept as is instead of
+// going through bytes
+#[component]
+fn RawFloatSliders(
+    name: String,
+    floats: [f64; 4],
+    state: EditorState,
+) -> impl IntoView {
+    view! {
+        <div class="raw-floats">
+            { ["R", "G", "B", "A"].into_iter().enumerate().map(|(idx, label)| {
+                let name = name.clone();
+                view! {
+                    <label title=format!("{}", floats[idx])>
+                        { label }
+                        <input
+                            type="range"
+                            min="0"
+                            max="1"
+                            step="any"
+                            prop:value=floats[idx]
+                            on:change=move |e| {
+                                if let Ok(value) = event_target_value(&e).parse::<f64>() {
+                                    let mut floats = floats;
+                                    floats[idx] = value;
+                                    state.set_raw_floats(&name, floats);
+                                }
+                            }
+                        />
+                        { format!("{:.6}", floats[idx]) }
+                    </label>
+                }
+            }).collect::<Vec<_>>() }
+        </div>
+    }
+}
+
+// Raw color constants live outside of the palette (playhead black is one of them)
+#[component]
+pub fn RawColorsPanel(
+    state: EditorState,
+) -> impl IntoView {
+    // Constant names are obfuscated, the search is for names seen in logs or
+    // stack traces. Exact match goes first.
+    let (search, set_search) = create_signal(String::new());
+    let names = move || {
+        let mut names = state.theme.with(|theme| match theme {
+            Some(Ok(theme)) => theme.raw_colors.keys().cloned().collect::<Vec<_>>(),
+            _ => vec![],
+        });
+        search.with(|search| {
+            let search = search.trim();
+            names.retain(|name| name.contains(search));
+            names.sort_by_key(|name| name != search);
+        });
+        names
+    };
+
+    view! {
+        <div>
+            <h3>"Raw colors"</h3>
+            <input
+                type="search"
+                placeholder="Find constant by name"
+                prop:value=search
+                on:input=move |e| set_search(event_target_value(&e))
+            />
+            <div class="raw-colors">
+                { move || names().into_iter().map(|name| {
+                    let color = state.raw_color(&name).unwrap();
+                    let changed = state.changed_raw_colors.with(|changed| changed.contains_key(&name));
+                    let hex = format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+                    let label = format!("{} {} (RGB {}, {}, {})", name, color.to_hex(), color.r, color.g, color.b);
+                    let sliders = state.raw_floats(&name).map(|floats| {
+                        view! { <RawFloatSliders name=name.clone() floats=floats state=state/> }
+                    });
+                    view! {
+                        <label class:changed=changed>
+                            <input
+                                type="color"
+                                prop:value=hex
+                                on:change=move |e| {
+                                    if let Some((r, g, b)) = parse_hex_rgb(&event_target_value(&e)) {
+                                        state.set_raw_color(&name, AbsoluteColor { r, g, b, a: color.a });
+                                    }
+                                }
+                            />
+                            { label }
+                        </label>
+                        { sliders }
+                    }
+                }).collect::<Vec<_>>() }
+            </div>
+        </div>
+    }
+}
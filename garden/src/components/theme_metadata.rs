@@ -0,0 +1,96 @@
+use cucumber::types::{MAX_AUTHOR_LEN, MAX_DESCRIPTION_LEN, MAX_THEME_NAME_LEN};
+use leptos::{component, create_signal, event_target_value, view, IntoView, ReadSignal, WriteSignal};
+use leptos::SignalGet;
+use leptos::SignalGetUntracked;
+use leptos::SignalSet;
+use leptos::SignalUpdate;
+use leptos::SignalWith;
+
+use super::editor::EditorState;
+
+// Name, author and description written into exported theme JSON
+#[component]
+pub fn ThemeMetadataDialog(
+    state: EditorState,
+    open: ReadSignal<bool>,
+    set_open: WriteSignal<bool>,
+) -> impl IntoView {
+    let (name, set_name) = create_signal(String::new());
+    let (author, set_author) = create_signal(String::new());
+    let (description, set_description) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<String>);
+
+    // Drafts start from the loaded theme each time the dialog opens
+    let reset = move || {
+        state.theme.with_untracked(|theme| {
+            if let Some(Ok(theme)) = theme {
+                set_name.set(theme.name.clone());
+                set_author.set(theme.author.clone().unwrap_or_default());
+                set_description.set(theme.description.clone().unwrap_or_default());
+            }
+        });
+        set_error.set(None);
+    };
+
+    let save = move || {
+        let fields = [
+            ("Name", name.get_untracked(), MAX_THEME_NAME_LEN),
+            ("Author", author.get_untracked(), MAX_AUTHOR_LEN),
+            ("Description", description.get_untracked(), MAX_DESCRIPTION_LEN),
+        ];
+        if let Some((label, _, max_len)) = fields.iter().find(|(_, value, max_len)| value.chars().count() > *max_len) {
+            set_error.set(Some(format!("{label} can be at most {max_len} characters long")));
+            return;
+        }
+        if fields[0].1.trim().is_empty() {
+            set_error.set(Some("Name can't be empty".into()));
+            return;
+        }
+        let [(_, name, _), (_, author, _), (_, description, _)] = fields;
+        let non_empty = |value: String| (!value.trim().is_empty()).then_some(value);
+        state.theme.update(|theme| {
+            if let Some(Ok(theme)) = theme {
+                theme.name = name;
+                theme.author = non_empty(author);
+                theme.description = non_empty(description);
+            }
+        });
+        state.set_status.set("Theme info updated".into());
+        set_open.set(false);
+    };
+
+    let counter = move |value: ReadSignal<String>, max_len: usize| {
+        move || format!("{}/{}", value.with(|value| value.chars().count()), max_len)
+    };
+
+    move || open.get().then(|| {
+        reset();
+        view! {
+            <div class="dialog theme-metadata">
+                <h3>"Theme info"</h3>
+                <label>
+                    "Name "
+                    <input prop:value=name on:input=move |e| set_name.set(event_target_value(&e))/>
+                    <span class="counter">{ counter(name, MAX_THEME_NAME_LEN) }</span>
+                </label>
+                <label>
+                    "Author "
+                    <input prop:value=author on:input=move |e| set_author.set(event_target_value(&e))/>
+                    <span class="counter">{ counter(author, MAX_AUTHOR_LEN) }</span>
+                </label>
+                <label>
+                    "Description "
+                    <textarea
+                        rows="4"
+                        prop:value=description
+                        on:input=move |e| set_description.set(event_target_value(&e))
+                    ></textarea>
+                    <span class="counter">{ counter(description, MAX_DESCRIPTION_LEN) }</span>
+                </label>
+                { move || error.get().map(|error| view! { <p class="problem-note">{ error }</p> }) }
+                <button on:click=move |_| save()>"Save"</button>
+                <button on:click=move |_| set_open.set(false)>"Cancel"</button>
+            </div>
+        }
+    })
+}